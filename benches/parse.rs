@@ -0,0 +1,67 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use std::fmt::Write;
+use vcd_rust::string_helpers::identifier_for_handle;
+
+const SCALARS: u64 = 200;
+const VECTORS: u64 = 200;
+const STEPS: u64 = 5000;
+
+/// A dump with a mix of scalar and 32 bit vector variables, a tenth of which change each step.
+/// Values never repeat, so no warnings are raised.
+fn dump() -> String {
+    let mut vcd = String::from("$timescale 1 ps $end\n$scope module top $end\n");
+    for handle in 1..=SCALARS + VECTORS {
+        let width = if handle <= SCALARS { 1 } else { 32 };
+        let identifier = identifier_for_handle(handle);
+        writeln!(vcd, "$var wire {} {} s{} $end", width, identifier, handle).unwrap();
+    }
+    vcd.push_str("$upscope $end\n$enddefinitions $end\n");
+    for step in 0..STEPS {
+        writeln!(vcd, "#{}", step * 10).unwrap();
+        for handle in (step % 10 + 1..=SCALARS + VECTORS).step_by(10) {
+            let identifier = identifier_for_handle(handle);
+            let value = step.wrapping_mul(2654435761) ^ handle;
+            match handle <= SCALARS {
+                true => writeln!(vcd, "{}{}", step / 10 % 2, identifier).unwrap(),
+                false => writeln!(vcd, "b{:b} {}", value as u32, identifier).unwrap(),
+            }
+        }
+    }
+    vcd
+}
+
+fn load(c: &mut Criterion) {
+    let vcd = dump();
+    let path = std::env::temp_dir().join("vcd_rust_bench.vcd");
+    std::fs::write(&path, &vcd).unwrap();
+    let filename = path.display().to_string();
+
+    let mut group = c.benchmark_group("load");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(vcd.len() as u64));
+    group.bench_function("file_lines", |b| {
+        b.iter(|| vcd_rust::load_from_file(filename.clone()).unwrap())
+    });
+    group.bench_function("file_mmap", |b| {
+        b.iter(|| vcd_rust::load_from_file_mmap(filename.clone()).unwrap())
+    });
+    group.bench_function("str", |b| b.iter(|| vcd_rust::load_from_str(&vcd).unwrap()));
+    group.bench_function("bytes", |b| {
+        b.iter(|| vcd_rust::load_from_bytes(vcd.as_bytes()).unwrap())
+    });
+    let threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+    group.bench_function("bytes_parallel", |b| {
+        b.iter(|| {
+            let options = vcd_rust::options::ParseOptions::default();
+            vcd_rust::load_from_bytes_parallel(vcd.as_bytes(), &options, threads).unwrap()
+        })
+    });
+    group.bench_function("tokenize", |b| {
+        b.iter(|| vcd_rust::tokenizer::ByteTokenizer::new(vcd.as_bytes()).count())
+    });
+    group.finish();
+    std::fs::remove_file(&path).unwrap();
+}
+
+criterion_group!(benches, load);
+criterion_main!(benches);
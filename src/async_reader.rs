@@ -0,0 +1,260 @@
+//! Loading VCDs on a tokio executor without blocking it, from any [`AsyncBufRead`].
+//!
+//! [`load`] is the asynchronous equivalent of [`crate::load_from_file_with_options`], and an
+//! [`EventReader`] the one of a [`Follower`](crate::follow::Follower): it hands out the header and
+//! then the value changes as they are read, without keeping them. Both parse a line at a time
+//! and give the executor back every [`YIELD_INTERVAL`] bytes, so other tasks keep running while
+//! a large dump is parsed.
+//!
+//! Loading stops when its future or the reader is dropped. [`load`] also tells the
+//! [`ParseOptions::progress`] hook how far it got, which can stop it with a
+//! [`LoadError::Cancelled`].
+
+use crate::compression::Compression;
+use crate::error::LoadError;
+use crate::follow::{IncrementalParser, ValueChange};
+use crate::options::ParseOptions;
+use crate::parser::LineBuffer;
+use crate::report::LoadReport;
+use crate::state_machine::StateMachine;
+use crate::tokenizer::Position;
+use crate::vcd::VCD;
+use crate::warning::Warning;
+use std::collections::VecDeque;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, BufReader};
+
+/// How many bytes are parsed between two yields to the executor.
+pub const YIELD_INTERVAL: u64 = 64 * 1024;
+
+/// Loads a VCD from `reader` the way `options` say.
+pub async fn load<R: AsyncBufRead + Unpin>(
+    mut reader: R,
+    options: &ParseOptions,
+) -> Result<LoadReport, LoadError> {
+    let mut state_machine = StateMachine::with_options(options.clone());
+    let mut errors = vec![];
+    let mut line = LineBuffer::new(Position::new());
+    let mut yielded_at = 0;
+    while let Some(buf) = line.start_line() {
+        let read = reader.read_until(b'\n', buf).await;
+        match line.end_line(read) {
+            Some(Ok((text, position))) => {
+                crate::parse_text(&mut state_machine, text, position, options, &mut errors)?
+            }
+            Some(Err(err)) => crate::record(err, options, &mut errors)?,
+            None => break,
+        }
+        if options.header_only && state_machine.at_value_change_boundary() {
+            break;
+        }
+        let offset = line.position().offset as u64;
+        if offset >= yielded_at + YIELD_INTERVAL {
+            tokio::task::yield_now().await;
+            yielded_at = offset;
+        }
+    }
+    crate::finish(state_machine, options, errors)
+}
+
+/// The file equivalent of [`load`]. Errors are wrapped in a [`LoadError::InFile`] naming the
+/// file. Compressed files can't be loaded this way, as their decompressors block; load them with
+/// [`crate::load_from_file_with_options`] on a blocking thread instead.
+pub async fn load_from_file(
+    filename: String,
+    options: &ParseOptions,
+) -> Result<LoadReport, LoadError> {
+    match open_file(filename.clone()).await {
+        Ok(reader) => crate::in_file(load(reader, options).await, &filename),
+        Err(err) => {
+            let mut errors = vec![];
+            crate::record(err, options, &mut errors)?;
+            Ok(LoadReport {
+                errors,
+                ..LoadReport::default()
+            })
+        }
+    }
+}
+
+async fn open_file(filename: String) -> Result<BufReader<tokio::fs::File>, LoadError> {
+    let open_error = |error: String| LoadError::FileOpenError {
+        filename: filename.clone(),
+        error,
+    };
+    let file = tokio::fs::File::open(filename.as_str())
+        .await
+        .map_err(|e| open_error(e.to_string()))?;
+    let mut reader = BufReader::new(file);
+    let start = reader
+        .fill_buf()
+        .await
+        .map_err(|e| open_error(e.to_string()))?;
+    match Compression::detect(start) {
+        Compression::None => Ok(reader),
+        _ => Err(open_error(
+            "compressed files can't be loaded asynchronously".to_string(),
+        )),
+    }
+}
+
+/// Something found in a VCD by an [`EventReader`].
+#[derive(Clone)]
+pub enum Event {
+    /// The header, once its `$enddefinitions` has been parsed. It comes before any change.
+    Header(Box<VCD>),
+    Change(ValueChange),
+}
+
+/// Reads the header and the value changes of a VCD one at a time, keeping only the last change
+/// of each variable in memory.
+pub struct EventReader<R> {
+    reader: R,
+    buf: Vec<u8>,
+    parser: IncrementalParser,
+    events: VecDeque<Event>,
+    header_read: bool,
+    finished: bool,
+    yielded_at: u64,
+}
+
+impl<R: AsyncBufRead + Unpin> EventReader<R> {
+    pub fn new(reader: R, options: &ParseOptions) -> Self {
+        EventReader {
+            reader,
+            buf: vec![],
+            parser: IncrementalParser::new(options),
+            events: VecDeque::new(),
+            header_read: false,
+            finished: false,
+            yielded_at: 0,
+        }
+    }
+
+    /// How many bytes were read so far.
+    pub fn bytes_read(&self) -> u64 {
+        self.parser.bytes_fed()
+    }
+
+    /// The time of the last timestamp parsed.
+    pub fn time(&self) -> u64 {
+        self.parser.time()
+    }
+
+    /// The warnings found since the last call.
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        self.parser.take_warnings()
+    }
+
+    /// The next event, or `None` once the whole input has been read. Changes come in time order.
+    /// After an error, the reader shouldn't be used any further.
+    pub async fn next_event(&mut self) -> Result<Option<Event>, LoadError> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(Some(event));
+            }
+            if self.finished {
+                return Ok(None);
+            }
+            self.buf.clear();
+            let read = self.reader.read_until(b'\n', &mut self.buf).await;
+            let changes = match read {
+                Ok(0) => {
+                    self.finished = true;
+                    self.parser.finish()?
+                }
+                Ok(_) => self.parser.feed(&self.buf)?,
+                Err(_) => return Err(self.parser.read_error()),
+            };
+            if let Some(header) = self.parser.header().filter(|_| !self.header_read) {
+                self.events
+                    .push_back(Event::Header(Box::new(header.clone())));
+                self.header_read = true;
+            }
+            self.events.extend(changes.into_iter().map(Event::Change));
+            if self.bytes_read() >= self.yielded_at + YIELD_INTERVAL {
+                tokio::task::yield_now().await;
+                self.yielded_at = self.bytes_read();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::{Progress, ProgressHook};
+    use crate::types::value::{Bit, Value};
+    use std::ops::ControlFlow;
+    use std::sync::{Arc, Mutex};
+
+    const DUMP: &str = "$timescale 1 ns $end\n$scope module top $end\n\
+                        $var wire 1 ! clk $end\n$upscope $end\n$enddefinitions $end\n\
+                        #0\n0!\n#5\n1!\n#10\n0!\n";
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn load_matches_the_synchronous_loader() {
+        let reported = Arc::new(Mutex::new(vec![]));
+        let seen = Arc::clone(&reported);
+        let options = ParseOptions {
+            progress: Some(ProgressHook::new(1, move |progress: &Progress| {
+                seen.lock().unwrap().push(progress.bytes);
+                ControlFlow::Continue(())
+            })),
+            ..ParseOptions::default()
+        };
+        let report = block_on(load(DUMP.as_bytes(), &options)).unwrap();
+        let expected = crate::load_from_str(DUMP).unwrap();
+        assert_eq!(report.vcd.variables, expected.variables);
+        assert_eq!(report.vcd.waveforms, expected.waveforms);
+        let reported = reported.lock().unwrap();
+        assert!(reported.windows(2).all(|pair| pair[1] > pair[0]));
+        assert_eq!(reported.last(), Some(&(DUMP.trim_end().len() as u64)));
+    }
+
+    #[test]
+    fn progress_hook_cancels_loading() {
+        let options = ParseOptions {
+            progress: Some(ProgressHook::new(1, |progress: &Progress| {
+                match progress.bytes > 100 {
+                    true => ControlFlow::Break(()),
+                    false => ControlFlow::Continue(()),
+                }
+            })),
+            ..ParseOptions::default()
+        };
+        let result = block_on(load(DUMP.as_bytes(), &options));
+        let err = result.err().unwrap();
+        assert_eq!(err.code(), "E017");
+        assert!(matches!(err, LoadError::Cancelled { offset } if offset > 100));
+    }
+
+    #[test]
+    fn event_reader_reads_header_then_changes() {
+        let mut reader = EventReader::new(DUMP.as_bytes(), &ParseOptions::default());
+        let mut changes = vec![];
+        block_on(async {
+            match reader.next_event().await.unwrap() {
+                Some(Event::Header(header)) => assert!(header.variables.contains_key("!")),
+                _ => panic!("expected the header first"),
+            }
+            while let Some(event) = reader.next_event().await.unwrap() {
+                match event {
+                    Event::Change(change) => changes.push((change.time, change.value)),
+                    Event::Header(_) => panic!("header read twice"),
+                }
+            }
+        });
+        let zero = Value::Scalar(Bit::Zero);
+        let one = Value::Scalar(Bit::One);
+        assert_eq!(changes, vec![(0, zero.clone()), (5, one), (10, zero)]);
+        assert_eq!(reader.bytes_read(), DUMP.len() as u64);
+        assert_eq!(reader.time(), 10);
+    }
+}
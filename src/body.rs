@@ -0,0 +1,251 @@
+//! What can be told about the value change section of a VCD file without parsing it.
+
+use crate::compression::Compression;
+use crate::error::LoadError;
+use crate::parser::LineReader;
+use crate::tokenizer::{ByteTokenizer, Position, Span, Tokenizer};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+
+/// How much is read at each end of the body to find timestamps, at first.
+const WINDOW_LEN: u64 = 64 * 1024;
+
+/// The size and time range of the value change section of a file.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct BodyInfo {
+    /// Where the body starts, just past the `$end` of `$enddefinitions`.
+    pub offset: u64,
+    /// The length of the body in bytes.
+    pub len: u64,
+    /// The first and last timestamps. They are found by looking at a few KiB at each end of the
+    /// body; the window at the end starts at a line, so a `$comment` cut by it can throw them off.
+    pub time_range: Option<(u64, u64)>,
+}
+
+/// Finds the size and time range of the body of a VCD file, reading only its header and the
+/// ends of the body. Returns `None` if the file has no `$enddefinitions`, or is compressed, as
+/// the size of a compressed body isn't known without decompressing it.
+pub fn body_info(filename: String) -> Result<Option<BodyInfo>, LoadError> {
+    let open_error = |e: std::io::Error| LoadError::FileOpenError {
+        filename: filename.clone(),
+        error: e.to_string(),
+    };
+    let mut reader = BufReader::new(File::open(filename.as_str()).map_err(open_error)?);
+    if Compression::detect(reader.fill_buf().map_err(open_error)?) != Compression::None {
+        return Ok(None);
+    }
+    let start = match body_start(&mut reader)? {
+        Some(start) => start,
+        None => return Ok(None),
+    };
+    let offset = start.offset as u64;
+    let mut file = reader.into_inner();
+    let read_error = |_| LoadError::FileReadError {
+        span: Span::new(start.offset, 0, start.line, start.column),
+    };
+    let end = file.metadata().map_err(read_error)?.len();
+    let first = first_time(&mut file, offset, end).map_err(read_error)?;
+    let last = last_time(&mut file, offset, end).map_err(read_error)?;
+    Ok(Some(BodyInfo {
+        offset,
+        len: end - offset,
+        time_range: first.zip(last),
+    }))
+}
+
+/// The position just past the `$end` of `$enddefinitions`.
+fn body_start<R: BufRead>(reader: R) -> Result<Option<Position>, LoadError> {
+    let mut lines = LineReader::new(reader);
+    let mut definitions_ended = false;
+    while let Some(line) = lines.next_line() {
+        let (line, position) = line?;
+        for token in Tokenizer::resume(line, position) {
+            match (definitions_ended, token.text) {
+                (false, "$enddefinitions") => definitions_ended = true,
+                (true, _) => {
+                    return Ok(Some(Position {
+                        offset: token.span.offset + token.span.len,
+                        line: token.span.line,
+                        column: token.span.column + token.span.len,
+                    }))
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn first_time(file: &mut File, offset: u64, end: u64) -> std::io::Result<Option<u64>> {
+    let mut len = WINDOW_LEN;
+    loop {
+        let window_end = end.min(offset + len);
+        let mut window = read_range(file, offset, window_end)?;
+        if window_end < end {
+            // The last word may go on past the window.
+            let last_space = window.iter().rposition(u8::is_ascii_whitespace);
+            window.truncate(last_space.unwrap_or(0));
+        }
+        if let Some(time) = timestamps(&window).next() {
+            return Ok(Some(time));
+        }
+        if window_end == end {
+            return Ok(None);
+        }
+        len *= 2;
+    }
+}
+
+fn last_time(file: &mut File, offset: u64, end: u64) -> std::io::Result<Option<u64>> {
+    let mut len = WINDOW_LEN;
+    loop {
+        let window_start = offset.max(end.saturating_sub(len));
+        let mut window = read_range(file, window_start, end)?;
+        if window_start > offset {
+            // The first line may have started before the window.
+            let first_newline = window.iter().position(|c| *c == b'\n');
+            window.drain(..first_newline.unwrap_or(window.len()));
+        }
+        if let Some(time) = timestamps(&window).last() {
+            return Ok(Some(time));
+        }
+        if window_start == offset {
+            return Ok(None);
+        }
+        len *= 2;
+    }
+}
+
+fn read_range(file: &mut File, start: u64, end: u64) -> std::io::Result<Vec<u8>> {
+    let mut bytes = vec![];
+    file.seek(SeekFrom::Start(start))?;
+    file.take(end - start).read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// The timestamps among the words of `bytes`, leaving out identifiers and comments that look
+/// like one.
+fn timestamps(bytes: &[u8]) -> impl Iterator<Item = u64> + '_ {
+    let mut scanner = Scanner::default();
+    ByteTokenizer::new(bytes).filter_map(move |(token, _)| {
+        let word = String::from_utf8_lossy(token);
+        match (scanner.next(&word), &token[1..]) {
+            (Word::Timestamp, digits)
+                if !digits.is_empty() && digits.iter().all(u8::is_ascii_digit) =>
+            {
+                std::str::from_utf8(digits).ok()?.parse().ok()
+            }
+            _ => None,
+        }
+    })
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum Word<'a> {
+    Timestamp,
+    /// The value of a vector, real or port change, whose identifier is the next word.
+    Value,
+    /// A change of the identifier, or its last word.
+    Change(&'a str),
+    Other,
+}
+
+/// Follows the value change section word by word, without parsing values, to tell timestamps
+/// and the identifiers of value changes apart from the rest.
+#[derive(Default)]
+pub struct Scanner {
+    in_command: bool,
+    pending_value: bool,
+}
+
+impl Scanner {
+    pub fn next<'a>(&mut self, word: &'a str) -> Word<'a> {
+        if self.in_command {
+            self.in_command = word != "$end";
+            return Word::Other;
+        }
+        if self.pending_value {
+            self.pending_value = false;
+            return Word::Change(word);
+        }
+        match word.as_bytes()[0] {
+            b'$' => {
+                let dump = ["$dumpvars", "$dumpall", "$dumpon", "$dumpoff", "$dumpports"];
+                self.in_command = word != "$end" && !dump.contains(&word);
+                Word::Other
+            }
+            b'#' => Word::Timestamp,
+            b'b' | b'B' | b'r' | b'R' | b'p' | b'P' => {
+                self.pending_value = true;
+                Word::Value
+            }
+            _ => Word::Change(word.get(1..).unwrap_or_default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps_among_words() {
+        let words = b"#0 $dumpvars 1! $end\n#10\nb1 #2\n$comment #3 $end #x #1a # r1 #4 #5";
+        assert_eq!(timestamps(words).collect::<Vec<_>>(), vec![0, 10, 5]);
+    }
+
+    #[test]
+    fn identifier_after_the_last_timestamp_is_not_one() {
+        let path = std::env::temp_dir().join(format!("vcd_rust_body_{}.vcd", std::process::id()));
+        let dump = "$var wire 2 #999999 a $end\n$enddefinitions $end\n#0\nb0 #999999\n#100\n\
+                    b1 #999999\n";
+        std::fs::write(&path, dump).unwrap();
+        let info = body_info(path.display().to_string());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(info.unwrap().unwrap().time_range, Some((0, 100)));
+    }
+
+    #[test]
+    fn body_starts_after_enddefinitions() {
+        let header = "$var wire 1 ! a $end\n$enddefinitions\n  $end\n#0\n";
+        assert_eq!(
+            body_start(header.as_bytes()),
+            Ok(Some(Position {
+                offset: 43,
+                line: 3,
+                column: 7
+            }))
+        );
+        assert_eq!(body_start(&b"$date $end"[..]), Ok(None));
+    }
+
+    #[test]
+    fn scanner_finds_identifiers_of_changes() {
+        let mut scanner = Scanner::default();
+        let words = "#0 $dumpvars 1! b10 \" $end $comment 1# b1 $end r1.5 $ #10 pUDD <0"
+            .split(' ')
+            .map(|word| scanner.next(word))
+            .collect::<Vec<_>>();
+        use Word::*;
+        assert_eq!(
+            words,
+            vec![
+                Timestamp,
+                Other,
+                Change("!"),
+                Value,
+                Change("\""),
+                Other,
+                Other,
+                Other,
+                Other,
+                Other,
+                Value,
+                Change("$"),
+                Timestamp,
+                Value,
+                Change("<0"),
+            ]
+        );
+    }
+}
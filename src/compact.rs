@@ -0,0 +1,114 @@
+//! A VCD whose waveforms are kept as [`CompactWaveform`]s, which take a fraction of the memory
+//! of [`Waveform`](crate::types::waveform::Waveform)s for dumps with many changes, and a report
+//! of the memory each waveform takes.
+
+use crate::error::LoadError;
+use crate::types::compact_waveform::CompactWaveform;
+use crate::types::waveform::{WaveformQuery, WaveformStore};
+use crate::vcd::VCD;
+use std::collections::HashMap;
+
+pub struct CompactVcd {
+    /// Everything but the waveforms, which are left empty.
+    pub header: VCD,
+    /// Value changes of each variable, keyed by the variable's identifier code.
+    pub waveforms: HashMap<String, CompactWaveform>,
+}
+
+impl From<VCD> for CompactVcd {
+    /// Converts the waveforms one at a time, freeing each after it's converted.
+    fn from(mut vcd: VCD) -> Self {
+        let mut waveforms = HashMap::with_capacity(vcd.waveforms.len());
+        for (identifier, waveform) in vcd.waveforms.drain() {
+            waveforms.insert(identifier, CompactWaveform::from(&waveform));
+        }
+        CompactVcd {
+            header: vcd,
+            waveforms,
+        }
+    }
+}
+
+impl CompactVcd {
+    pub fn memory_report(&self) -> Vec<SignalMemory> {
+        memory_report(&self.waveforms)
+    }
+}
+
+impl WaveformStore for CompactVcd {
+    fn identifiers(&self) -> Vec<String> {
+        self.waveforms.keys().cloned().collect()
+    }
+
+    fn query<R>(
+        &self,
+        identifier: &str,
+        query: impl FnOnce(&dyn WaveformQuery) -> R,
+    ) -> Result<Option<R>, LoadError> {
+        Ok(self
+            .waveforms
+            .get(identifier)
+            .map(|waveform| query(waveform)))
+    }
+}
+
+/// How much memory the waveform of a variable takes.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SignalMemory {
+    pub identifier: String,
+    pub changes: usize,
+    pub bytes: usize,
+}
+
+/// The memory taken by each of `waveforms`, largest first.
+pub fn memory_report<W: WaveformQuery>(waveforms: &HashMap<String, W>) -> Vec<SignalMemory> {
+    let mut report: Vec<_> = waveforms
+        .iter()
+        .map(|(identifier, waveform)| SignalMemory {
+            identifier: identifier.clone(),
+            changes: waveform.len(),
+            bytes: waveform.memory_usage(),
+        })
+        .collect();
+    report.sort_by(|a, b| {
+        b.bytes
+            .cmp(&a.bytes)
+            .then_with(|| a.identifier.cmp(&b.identifier))
+    });
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compact_vcd_has_the_same_values() {
+        let mut text = "$scope module top $end\n$var wire 1 ! clk $end\n\
+                        $var wire 8 \" count $end\n$upscope $end\n$enddefinitions $end\n"
+            .to_string();
+        for time in 0..1000 {
+            text.push_str(&format!("#{}\n{}!\n", time, time % 2));
+            if time % 4 == 0 {
+                text.push_str(&format!("b{:b} \"\n", time / 4 % 256));
+            }
+        }
+        let vcd = crate::load_from_str(&text).unwrap();
+        let naive_report = vcd.memory_report();
+        let compact = CompactVcd::from(vcd.clone());
+        assert!(compact.header.waveforms.is_empty());
+        assert_eq!(compact.header.variables, vcd.variables);
+        for (identifier, waveform) in &vcd.waveforms {
+            let changes = compact.waveforms[identifier].iter().collect::<Vec<_>>();
+            assert_eq!(changes, waveform.changes().to_vec());
+        }
+
+        let report = compact.memory_report();
+        assert_eq!(report.len(), 2);
+        assert_eq!(report[0].identifier, "!");
+        assert_eq!(report[0].changes, 1000);
+        assert!(report[0].bytes >= report[1].bytes);
+        let naive_clk = naive_report.iter().find(|signal| signal.identifier == "!");
+        assert!(report[0].bytes * 4 < naive_clk.unwrap().bytes);
+    }
+}
@@ -0,0 +1,159 @@
+//! Transparent decompression of compressed dumps. The compression is told from the magic bytes
+//! at the start of the file, not its extension, and the dump is decompressed while it's parsed.
+//! Each format needs the cargo feature of the same name: `gzip`, `zstd`, `bzip2` or `xz`.
+
+use std::io::{self, BufRead, Error, ErrorKind};
+
+/// The ways a dump can be compressed.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    Bzip2,
+    Xz,
+}
+
+impl Compression {
+    /// Detects the compression of data starting with `header`. Six bytes are enough to tell all
+    /// formats apart.
+    pub fn detect(header: &[u8]) -> Compression {
+        if header.starts_with(&[0x1f, 0x8b]) {
+            Compression::Gzip
+        } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Compression::Zstd
+        } else if header.starts_with(b"BZh") {
+            Compression::Bzip2
+        } else if header.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Compression::Xz
+        } else {
+            Compression::None
+        }
+    }
+
+    /// The cargo feature needed to decompress this format.
+    pub fn feature(self) -> Option<&'static str> {
+        match self {
+            Compression::None => None,
+            Compression::Gzip => Some("gzip"),
+            Compression::Zstd => Some("zstd"),
+            Compression::Bzip2 => Some("bzip2"),
+            Compression::Xz => Some("xz"),
+        }
+    }
+}
+
+/// Wraps `reader` in a decoder for the compression its data starts with, or returns it as it is
+/// if it isn't compressed. Files made of several concatenated streams, like those of `pigz`,
+/// are read to the end.
+///
+/// Fails with [`ErrorKind::Unsupported`] if the data is compressed in a format whose feature is
+/// disabled.
+pub fn decompress<'a, R: BufRead + 'a>(mut reader: R) -> io::Result<Box<dyn BufRead + 'a>> {
+    let compression = Compression::detect(reader.fill_buf()?);
+    match compression {
+        Compression::None => Ok(Box::new(reader)),
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => Ok(Box::new(io::BufReader::new(
+            flate2::bufread::MultiGzDecoder::new(reader),
+        ))),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => Ok(Box::new(io::BufReader::new(
+            zstd::stream::read::Decoder::with_buffer(reader)?,
+        ))),
+        #[cfg(feature = "bzip2")]
+        Compression::Bzip2 => Ok(Box::new(io::BufReader::new(
+            bzip2::bufread::MultiBzDecoder::new(reader),
+        ))),
+        #[cfg(feature = "xz")]
+        Compression::Xz => Ok(Box::new(io::BufReader::new(
+            xz2::bufread::XzDecoder::new_multi_decoder(reader),
+        ))),
+        #[allow(unreachable_patterns)]
+        _ => Err(Error::new(
+            ErrorKind::Unsupported,
+            format!(
+                "the file is compressed with {:?}, but the `{}` feature is disabled",
+                compression,
+                compression.feature().unwrap_or_default()
+            ),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    const DUMP: &str =
+        "$timescale 1ns $end\n$var wire 1 ! clk $end\n$enddefinitions $end\n#0\n1!\n";
+
+    fn decompressed(data: &[u8]) -> String {
+        let mut text = String::new();
+        decompress(data).unwrap().read_to_string(&mut text).unwrap();
+        text
+    }
+
+    #[test]
+    fn detect_magic_bytes() {
+        assert_eq!(Compression::detect(b"$date"), Compression::None);
+        assert_eq!(Compression::detect(&[0x1f, 0x8b, 0x08]), Compression::Gzip);
+        assert_eq!(
+            Compression::detect(&[0x28, 0xb5, 0x2f, 0xfd, 0x00]),
+            Compression::Zstd
+        );
+        assert_eq!(Compression::detect(b"BZh91AY"), Compression::Bzip2);
+        assert_eq!(
+            Compression::detect(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]),
+            Compression::Xz
+        );
+        assert_eq!(Compression::detect(&[0xfd, b'7']), Compression::None);
+        assert_eq!(Compression::detect(&[]), Compression::None);
+    }
+
+    #[test]
+    fn plain_text_is_passed_through() {
+        assert_eq!(decompressed(DUMP.as_bytes()), DUMP);
+        assert_eq!(decompressed(b""), "");
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn concatenated_gzip_members() {
+        use std::io::Write;
+        let (first, second) = DUMP.split_at(20);
+        let mut data = vec![];
+        for part in &[first, second] {
+            let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+            encoder.write_all(part.as_bytes()).unwrap();
+            data.extend(encoder.finish().unwrap());
+        }
+        assert_eq!(decompressed(&data), DUMP);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd() {
+        let data = zstd::stream::encode_all(DUMP.as_bytes(), 3).unwrap();
+        assert_eq!(decompressed(&data), DUMP);
+    }
+
+    #[cfg(feature = "bzip2")]
+    #[test]
+    fn bzip2() {
+        use std::io::Write;
+        let mut encoder = bzip2::write::BzEncoder::new(vec![], bzip2::Compression::default());
+        encoder.write_all(DUMP.as_bytes()).unwrap();
+        assert_eq!(decompressed(&encoder.finish().unwrap()), DUMP);
+    }
+
+    #[cfg(feature = "xz")]
+    #[test]
+    fn xz() {
+        use std::io::Write;
+        let mut encoder = xz2::write::XzEncoder::new(vec![], 6);
+        encoder.write_all(DUMP.as_bytes()).unwrap();
+        assert_eq!(decompressed(&encoder.finish().unwrap()), DUMP);
+    }
+}
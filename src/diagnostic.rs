@@ -0,0 +1,264 @@
+use crate::error::LoadError;
+use crate::tokenizer::Span;
+use crate::warning::Warning;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+/// A [`LoadError`] or [`Warning`] together with everything needed to show it to a user: the name
+/// of the file it came from and the text of the line it points at.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    pub file: String,
+    pub span: Option<Span>,
+    pub source_line: Option<String>,
+}
+
+impl Diagnostic {
+    /// Builds a diagnostic for an error found while parsing `source`, which is called `file` in
+    /// the rendered output.
+    pub fn from_source(error: &LoadError, file: &str, source: &str) -> Self {
+        let source_line = error.span().and_then(|span| line_in_source(source, span));
+        Diagnostic::from_error(error, file, source_line)
+    }
+
+    /// Builds a diagnostic for an error found while parsing the file at `filename`. The offending
+    /// line is read back from the file; if that fails the diagnostic is rendered without it.
+    pub fn from_file(error: &LoadError, filename: &str) -> Self {
+        let source_line = error.span().and_then(|span| line_in_file(filename, span));
+        Diagnostic::from_error(error, filename, source_line)
+    }
+
+    /// Like [`Diagnostic::from_source`], for a warning.
+    pub fn warning_from_source(warning: &Warning, file: &str, source: &str) -> Self {
+        let source_line = line_in_source(source, warning.span);
+        Diagnostic::from_warning(warning, file, source_line)
+    }
+
+    /// Like [`Diagnostic::from_file`], for a warning.
+    pub fn warning_from_file(warning: &Warning, filename: &str) -> Self {
+        let source_line = line_in_file(filename, warning.span);
+        Diagnostic::from_warning(warning, filename, source_line)
+    }
+
+    fn from_error(error: &LoadError, file: &str, source_line: Option<String>) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            code: error.code(),
+            message: error.message(),
+            file: file.to_string(),
+            span: error.span(),
+            source_line,
+        }
+    }
+
+    fn from_warning(warning: &Warning, file: &str, source_line: Option<String>) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            code: warning.kind.code(),
+            message: warning.message.clone(),
+            file: file.to_string(),
+            span: Some(warning.span),
+            source_line,
+        }
+    }
+
+    /// Renders the diagnostic for a terminal, with the offending line underlined:
+    ///
+    /// ```text
+    /// error[E003]: date missing an $end
+    ///  --> dump.vcd:2:10
+    ///   |
+    /// 2 |     Date text
+    ///   |          ^^^^
+    /// ```
+    pub fn render(&self) -> String {
+        let mut out = format!(
+            "{}[{}]: {}\n",
+            self.severity.as_str(),
+            self.code,
+            self.message
+        );
+        let span = match self.span {
+            Some(span) => span,
+            None => {
+                out.push_str(&format!(" --> {}\n", self.file));
+                return out;
+            }
+        };
+        let gutter = " ".repeat(span.line.to_string().len());
+        out.push_str(&format!(
+            "{}--> {}:{}:{}\n",
+            gutter, self.file, span.line, span.column
+        ));
+        if let Some(source_line) = &self.source_line {
+            out.push_str(&format!("{} |\n", gutter));
+            out.push_str(&format!("{} | {}\n", span.line, source_line));
+            out.push_str(&format!(
+                "{} | {}{}\n",
+                gutter,
+                caret_indent(source_line, span.column),
+                "^".repeat(span.len.max(1))
+            ));
+        }
+        out
+    }
+
+    /// The diagnostic as a single line JSON object, for tools that consume diagnostics.
+    pub fn to_json(&self) -> String {
+        let mut fields = vec![
+            format!("\"severity\":{}", json_string(self.severity.as_str())),
+            format!("\"code\":{}", json_string(self.code)),
+            format!("\"message\":{}", json_string(&self.message)),
+            format!("\"file\":{}", json_string(&self.file)),
+        ];
+        if let Some(span) = self.span {
+            fields.push(format!("\"line\":{}", span.line));
+            fields.push(format!("\"column\":{}", span.column));
+            fields.push(format!("\"offset\":{}", span.offset));
+            fields.push(format!("\"length\":{}", span.len));
+        }
+        if let Some(source_line) = &self.source_line {
+            fields.push(format!("\"source_line\":{}", json_string(source_line)));
+        }
+        format!("{{{}}}", fields.join(","))
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.render())
+    }
+}
+
+fn line_in_source(source: &str, span: Span) -> Option<String> {
+    let start = span.offset.checked_sub(span.column.checked_sub(1)?)?;
+    let rest = source.get(start..)?;
+    let line = rest.split('\n').next().unwrap_or("");
+    Some(line.trim_end_matches('\r').to_string())
+}
+
+fn line_in_file(filename: &str, span: Span) -> Option<String> {
+    let start = span.offset.checked_sub(span.column.checked_sub(1)?)?;
+    let mut file = File::open(filename).ok()?;
+    file.seek(SeekFrom::Start(start as u64)).ok()?;
+    let mut line = String::new();
+    BufReader::new(file).read_line(&mut line).ok()?;
+    Some(line.trim_end_matches(&['\r', '\n'][..]).to_string())
+}
+
+/// Whitespace that lines a caret up under `column` of `line`. Tabs are kept so that the caret
+/// stays aligned however wide the terminal draws them.
+fn caret_indent(line: &str, column: usize) -> String {
+    line.bytes()
+        .take(column - 1)
+        .map(|byte| if byte == b'\t' { '\t' } else { ' ' })
+        .collect()
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::warning::WarningConfig;
+    use crate::{load_from_str, load_from_str_with_warnings};
+
+    #[test]
+    fn render_underlines_offending_token() {
+        let source = "$date\n    Date text";
+        let err = load_from_str(source).err().unwrap();
+        let diagnostic = Diagnostic::from_source(&err, "dump.vcd", source);
+        assert_eq!(
+            diagnostic.render(),
+            r#"error[E003]: date missing an $end
+ --> dump.vcd:2:10
+  |
+2 |     Date text
+  |          ^^^^
+"#
+        );
+    }
+
+    #[test]
+    fn render_keeps_tabs_in_caret_indent() {
+        let source = "$upscope\tbad $end";
+        let err = load_from_str(source).err().unwrap();
+        let diagnostic = Diagnostic::from_source(&err, "dump.vcd", source);
+        assert_eq!(
+            diagnostic.render(),
+            "error[E006]: Invalid parameter bad for command upscope\n --> dump.vcd:1:10\n  |\n1 | $upscope\tbad $end\n  |         \t^^^\n"
+        );
+    }
+
+    #[test]
+    fn render_warning() {
+        let source = "$date $end\n$timescale 1 ns $end";
+        let (_, warnings) = load_from_str_with_warnings(source, &WarningConfig::new()).unwrap();
+        let diagnostic = Diagnostic::warning_from_source(&warnings[0], "dump.vcd", source);
+        assert_eq!(
+            diagnostic.render(),
+            "warning[W002]: $date is empty\n --> dump.vcd:1:1\n  |\n1 | $date $end\n  | ^^^^^\n"
+        );
+        assert!(diagnostic
+            .to_json()
+            .starts_with(r#"{"severity":"warning","code":"W002""#));
+    }
+
+    #[test]
+    fn render_without_span() {
+        let err = LoadError::FileOpenError {
+            filename: "missing.vcd".to_string(),
+            error: "No such file".to_string(),
+        };
+        let diagnostic = Diagnostic::from_source(&err, "missing.vcd", "");
+        assert_eq!(
+            diagnostic.render(),
+            "error[E001]: Error opening file missing.vcd: No such file\n --> missing.vcd\n"
+        );
+    }
+
+    #[test]
+    fn json_form() {
+        let source = "$end\r\n";
+        let err = load_from_str(source).err().unwrap();
+        let diagnostic = Diagnostic::from_source(&err, "C:\\dump \"1\".vcd", source);
+        assert_eq!(
+            diagnostic.to_json(),
+            r#"{"severity":"error","code":"E005","message":"Dangling $end","file":"C:\\dump \"1\".vcd","line":1,"column":1,"offset":0,"length":4,"source_line":"$end"}"#
+        );
+    }
+}
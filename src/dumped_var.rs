@@ -1,5 +1,7 @@
-#[derive(Default, Eq, PartialEq, Clone)]
-pub struct DumpedVar {
-    pub value: usize,
-    pub identifier: String,
+use crate::types::value::Value;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DumpedVar<'a> {
+    pub value: Value,
+    pub identifier: &'a str,
 }
@@ -1,37 +1,83 @@
+use crate::dumped_var::DumpedVar;
 use crate::error::LoadError;
+use crate::tokenizer::Span;
+use crate::types::value::{Bit, PortState, Strength, Value};
 
-pub fn get_value_from_scalar(word: &str, line_num: usize) -> Result<usize, LoadError> {
-    const RADIX: u32 = 2;
-    match word.chars().collect::<Vec<char>>()[0].to_digit(RADIX) {
-        Some(value) => Ok(value as usize),
-        None => Err(LoadError::InvalidVarDump { line: line_num }),
-    }
+pub fn parse_scalar(word: &str, span: Span) -> Result<DumpedVar<'_>, LoadError> {
+    Ok(DumpedVar {
+        value: Value::Scalar(get_value_from_scalar(word, span)?),
+        identifier: get_identifier_from_scalar(word, span)?,
+    })
 }
 
-pub fn get_identifier_from_scalar(word: &str, line_num: usize) -> Result<String, LoadError> {
-    let scalar_chars = word.chars().collect::<Vec<char>>();
-    match scalar_chars.len() {
-        2 => Ok(scalar_chars[1].to_string()),
-        _ => Err(LoadError::InvalidVarDump { line: line_num }),
+pub fn get_value_from_scalar(word: &str, span: Span) -> Result<Bit, LoadError> {
+    match word.chars().next().and_then(Bit::from_char) {
+        Some(bit) => Ok(bit),
+        None => Err(LoadError::InvalidVarDump { span }),
     }
 }
 
-pub fn convert_vector_value_to_integer(word: &str, line_num: usize) -> Result<usize, LoadError> {
-    if word.len() <= 1 {
-        return Err(LoadError::InvalidVarDump { line: line_num });
+pub fn get_identifier_from_scalar(word: &str, span: Span) -> Result<&str, LoadError> {
+    get_value_from_scalar(word, span)?;
+    match &word[1..] {
+        "" => Err(LoadError::InvalidVarDump { span }),
+        identifier => Ok(identifier),
     }
+}
 
-    let value = &word[1..];
-    let radix;
+/// Parses the value part of a vector (`b0101`) or real (`r1.5`) value change. The identifier
+/// follows as a separate word.
+pub fn get_value_from_vector(word: &str, span: Span) -> Result<Value, LoadError> {
+    let value = match word.get(1..) {
+        Some(value) if !value.is_empty() => value,
+        _ => return Err(LoadError::InvalidVarDump { span }),
+    };
     match word.chars().next() {
-        Some('b') => radix = 2,
-        Some('r') => radix = 10,
-        _ => return Err(LoadError::InvalidVarDump { line: line_num }),
+        Some('b') | Some('B') => {
+            // Collecting into an Option doesn't know the length up front.
+            let mut bits = Vec::with_capacity(value.len());
+            for c in value.chars() {
+                bits.push(Bit::from_char(c).ok_or(LoadError::InvalidVarDump { span })?);
+            }
+            Ok(Value::Vector(bits))
+        }
+        Some('r') | Some('R') => value
+            .parse::<f64>()
+            .map(Value::Real)
+            .map_err(|_| LoadError::InvalidVarDump { span }),
+        _ => Err(LoadError::InvalidVarDump { span }),
+    }
+}
+
+/// Parses the state part of an extended VCD port value change (`pDU`), one state per bit. The
+/// strength components and the identifier follow as separate words.
+pub fn get_states_from_port(word: &str, span: Span) -> Result<Vec<PortState>, LoadError> {
+    match word.get(1..) {
+        Some(states) if word.starts_with('p') && !states.is_empty() => states
+            .chars()
+            .map(PortState::from_char)
+            .collect::<Option<Vec<PortState>>>()
+            .ok_or(LoadError::InvalidVarDump { span }),
+        _ => Err(LoadError::InvalidVarDump { span }),
     }
+}
 
-    match usize::from_str_radix(value, radix) {
-        Ok(value) => Ok(value),
-        Err(_) => Err(LoadError::InvalidVarDump { line: line_num }),
+/// Parses one strength component of a port value change for a port of `width` bits. It is either
+/// one digit per bit, most significant bit first, or a single digit that applies to every bit.
+pub fn get_strengths_from_port(
+    word: &str,
+    width: usize,
+    span: Span,
+) -> Result<Vec<Strength>, LoadError> {
+    let strengths = word
+        .chars()
+        .map(Strength::from_char)
+        .collect::<Option<Vec<Strength>>>()
+        .ok_or(LoadError::InvalidVarDump { span })?;
+    match strengths.len() {
+        1 => Ok(vec![strengths[0]; width]),
+        len if len == width => Ok(strengths),
+        _ => Err(LoadError::InvalidVarDump { span }),
     }
 }
 
@@ -41,21 +87,38 @@ mod tests {
 
     #[test]
     fn test_get_value_from_scalar() {
-        assert_eq!(0, get_value_from_scalar("0%", 0).unwrap());
-        assert_eq!(1, get_value_from_scalar("1{", 0).unwrap());
-        assert_eq!(0, get_value_from_scalar("0*", 0).unwrap());
+        assert_eq!(
+            Bit::Zero,
+            get_value_from_scalar("0%", Span::default()).unwrap()
+        );
+        assert_eq!(
+            Bit::One,
+            get_value_from_scalar("1{", Span::default()).unwrap()
+        );
+        assert_eq!(
+            Bit::X,
+            get_value_from_scalar("x*", Span::default()).unwrap()
+        );
+        assert_eq!(
+            Bit::Z,
+            get_value_from_scalar("Z*", Span::default()).unwrap()
+        );
     }
 
     #[test]
     fn test_invalid_var_dump_in_get_value_from_scalar_throws_error() {
         assert_eq!(
-            get_value_from_scalar("garbage", 1).err(),
-            Some(LoadError::InvalidVarDump { line: 1 }),
+            get_value_from_scalar("garbage", Span::new(0, 0, 1, 1)).err(),
+            Some(LoadError::InvalidVarDump {
+                span: Span::new(0, 0, 1, 1)
+            }),
         );
 
         assert_eq!(
-            get_value_from_scalar("also garbage", 2).err(),
-            Some(LoadError::InvalidVarDump { line: 2 }),
+            get_value_from_scalar("also garbage", Span::new(0, 0, 2, 1)).err(),
+            Some(LoadError::InvalidVarDump {
+                span: Span::new(0, 0, 2, 1)
+            }),
         );
     }
 
@@ -63,28 +126,106 @@ mod tests {
     fn test_get_identifier_from_scalar() {
         assert_eq!(
             "%".to_string(),
-            get_identifier_from_scalar("0%", 0).unwrap()
+            get_identifier_from_scalar("0%", Span::default()).unwrap()
         );
         assert_eq!(
             "{".to_string(),
-            get_identifier_from_scalar("1{", 0).unwrap()
+            get_identifier_from_scalar("1{", Span::default()).unwrap()
         );
         assert_eq!(
             "*".to_string(),
-            get_identifier_from_scalar("0*", 0).unwrap()
+            get_identifier_from_scalar("0*", Span::default()).unwrap()
+        );
+        assert_eq!(
+            "#a!".to_string(),
+            get_identifier_from_scalar("z#a!", Span::default()).unwrap()
         );
     }
 
     #[test]
     fn test_invalid_identifier_from_scalar_throws_error() {
         assert_eq!(
-            get_identifier_from_scalar("garbage", 1).err(),
-            Some(LoadError::InvalidVarDump { line: 1 }),
+            get_identifier_from_scalar("garbage", Span::new(0, 0, 1, 1)).err(),
+            Some(LoadError::InvalidVarDump {
+                span: Span::new(0, 0, 1, 1)
+            }),
         );
 
         assert_eq!(
-            get_identifier_from_scalar("also garbage", 2).err(),
-            Some(LoadError::InvalidVarDump { line: 2 }),
+            get_identifier_from_scalar("also garbage", Span::new(0, 0, 2, 1)).err(),
+            Some(LoadError::InvalidVarDump {
+                span: Span::new(0, 0, 2, 1)
+            }),
+        );
+
+        assert_eq!(
+            get_identifier_from_scalar("1", Span::new(0, 0, 3, 1)).err(),
+            Some(LoadError::InvalidVarDump {
+                span: Span::new(0, 0, 3, 1)
+            }),
+        );
+    }
+
+    #[test]
+    fn test_get_value_from_vector() {
+        use Bit::*;
+        assert_eq!(
+            get_value_from_vector("b10xz", Span::default()).unwrap(),
+            Value::Vector(vec![One, Zero, X, Z])
+        );
+        assert_eq!(
+            get_value_from_vector("r1.25", Span::default()).unwrap(),
+            Value::Real(1.25)
+        );
+    }
+
+    #[test]
+    fn test_invalid_vector_throws_error() {
+        for word in &["b", "b102", "r1.2.3", "q101", "é1"] {
+            assert_eq!(
+                get_value_from_vector(word, Span::default()).err(),
+                Some(LoadError::InvalidVarDump {
+                    span: Span::default()
+                }),
+            );
+        }
+    }
+
+    #[test]
+    fn test_get_states_from_port() {
+        use PortState::*;
+        assert_eq!(
+            get_states_from_port("pDuT?", Span::default()).unwrap(),
+            vec![InputLow, InputHighMulti, OutputZ, Unknown]
+        );
+        for word in &["p", "pDQ", "b01"] {
+            assert_eq!(
+                get_states_from_port(word, Span::default()).err(),
+                Some(LoadError::InvalidVarDump {
+                    span: Span::default()
+                }),
+            );
+        }
+    }
+
+    #[test]
+    fn test_get_strengths_from_port() {
+        use Strength::*;
+        assert_eq!(
+            get_strengths_from_port("6", 3, Span::default()).unwrap(),
+            vec![Strong, Strong, Strong]
+        );
+        assert_eq!(
+            get_strengths_from_port("607", 3, Span::default()).unwrap(),
+            vec![Strong, HighZ, Supply]
         );
+        for word in &["66", "8", "<0"] {
+            assert_eq!(
+                get_strengths_from_port(word, 3, Span::default()).err(),
+                Some(LoadError::InvalidVarDump {
+                    span: Span::default()
+                }),
+            );
+        }
     }
 }
@@ -1,53 +1,245 @@
+use crate::tokenizer::Span;
+use crate::warning::Warning;
+use std::fmt;
 use thiserror::Error;
 
 #[derive(Clone, PartialEq, Debug, Eq, Error)]
 pub enum LoadError {
-    #[error("Error opening file {}: {}", filename, error)]
-    FileOpenError { filename: String, error: String },
+    FileOpenError {
+        filename: String,
+        error: String,
+    },
 
-    #[error("line {}: Error reading file at this point", line)]
-    FileReadError { line: usize },
+    FileReadError {
+        span: Span,
+    },
 
-    #[error("line {}: {} missing an $end", line, command)]
-    MissingEnd { command: String, line: usize },
+    MissingEnd {
+        command: String,
+        span: Span,
+    },
 
-    #[error("line {}: More than one {} command is invalid", line, command)]
-    InvalidMultipleCommand { command: String, line: usize },
+    InvalidMultipleCommand {
+        command: String,
+        span: Span,
+    },
 
-    #[error("line {}: Dangling $end", line)]
-    DanglingEnd { line: usize },
+    DanglingEnd {
+        span: Span,
+    },
 
-    #[error(
-        "line {}: Invalid parameter {} for command {}",
-        line,
-        parameter,
-        command
-    )]
     InvalidParameterForCommand {
-        line: usize,
+        span: Span,
         parameter: String,
         command: String,
     },
 
-    #[error("line {}: {} has too few parameters", line, command)]
-    TooFewParameters { line: usize, command: String },
+    TooFewParameters {
+        span: Span,
+        command: String,
+    },
+
+    TooManyParameters {
+        span: Span,
+        command: String,
+    },
+
+    ScopeStackEmpty {
+        span: Span,
+        command: String,
+    },
+
+    InvalidTimeValue {
+        span: Span,
+        value: String,
+    },
+
+    InvalidTimeScale {
+        span: Span,
+        time_scale: String,
+    },
+
+    InvalidVarDump {
+        span: Span,
+    },
+
+    DeniedWarning {
+        warning: Warning,
+    },
+
+    UnknownIdentifier {
+        span: Span,
+        identifier: String,
+    },
+
+    UnsupportedCommand {
+        span: Span,
+        command: String,
+    },
+
+    InvalidBinaryFile {
+        format: String,
+        offset: usize,
+        reason: String,
+    },
+
+    Cancelled {
+        offset: u64,
+    },
 
-    #[error("line {}: {} has too many parameters", line, command)]
-    TooManyParameters { line: usize, command: String },
+    /// An error found in the file `filename`. The functions loading files wrap every error in
+    /// this, except a `FileOpenError`, which names the file already.
+    InFile {
+        filename: String,
+        error: Box<LoadError>,
+    },
+}
 
-    #[error("line {}: {} declared with empty scope", line, command)]
-    ScopeStackEmpty { line: usize, command: String },
+impl LoadError {
+    /// A short code identifying the kind of error. Codes are stable across releases, so they can
+    /// be matched on by tools consuming the rendered diagnostics.
+    pub fn code(&self) -> &'static str {
+        use LoadError::*;
+        match self {
+            FileOpenError { .. } => "E001",
+            FileReadError { .. } => "E002",
+            MissingEnd { .. } => "E003",
+            InvalidMultipleCommand { .. } => "E004",
+            DanglingEnd { .. } => "E005",
+            InvalidParameterForCommand { .. } => "E006",
+            TooFewParameters { .. } => "E007",
+            TooManyParameters { .. } => "E008",
+            ScopeStackEmpty { .. } => "E009",
+            InvalidTimeValue { .. } => "E010",
+            InvalidTimeScale { .. } => "E011",
+            InvalidVarDump { .. } => "E012",
+            DeniedWarning { .. } => "E013",
+            UnknownIdentifier { .. } => "E014",
+            UnsupportedCommand { .. } => "E015",
+            InvalidBinaryFile { .. } => "E016",
+            Cancelled { .. } => "E017",
+            InFile { error, .. } => error.code(),
+        }
+    }
 
-    #[error("line {}: Found time value {}, expected integer", line, value)]
-    InvalidTimeValue { line: usize, value: String },
+    /// Where in the input the error was found. Errors that are not tied to a location in the
+    /// input, like failing to open a file, have no span.
+    pub fn span(&self) -> Option<Span> {
+        use LoadError::*;
+        match self {
+            FileOpenError { .. } | InvalidBinaryFile { .. } | Cancelled { .. } => None,
+            FileReadError { span }
+            | MissingEnd { span, .. }
+            | InvalidMultipleCommand { span, .. }
+            | DanglingEnd { span }
+            | InvalidParameterForCommand { span, .. }
+            | TooFewParameters { span, .. }
+            | TooManyParameters { span, .. }
+            | ScopeStackEmpty { span, .. }
+            | InvalidTimeValue { span, .. }
+            | InvalidTimeScale { span, .. }
+            | InvalidVarDump { span }
+            | UnknownIdentifier { span, .. }
+            | UnsupportedCommand { span, .. } => Some(*span),
+            DeniedWarning { warning } => Some(warning.span),
+            InFile { error, .. } => error.span(),
+        }
+    }
 
-    #[error(
-        "line {}: Found timescale {}, expected one of: [ ms us ns ps ]",
-        line,
-        time_scale
-    )]
-    InvalidTimeScale { line: usize, time_scale: String },
+    /// The error message without its location.
+    pub fn message(&self) -> String {
+        use LoadError::*;
+        match self {
+            FileOpenError { filename, error } => {
+                format!("Error opening file {}: {}", filename, error)
+            }
+            FileReadError { .. } => "Error reading file at this point".to_string(),
+            MissingEnd { command, .. } => format!("{} missing an $end", command),
+            InvalidMultipleCommand { command, .. } => {
+                format!("More than one {} command is invalid", command)
+            }
+            DanglingEnd { .. } => "Dangling $end".to_string(),
+            InvalidParameterForCommand {
+                parameter, command, ..
+            } => format!("Invalid parameter {} for command {}", parameter, command),
+            TooFewParameters { command, .. } => format!("{} has too few parameters", command),
+            TooManyParameters { command, .. } => format!("{} has too many parameters", command),
+            ScopeStackEmpty { command, .. } => format!("{} declared with empty scope", command),
+            InvalidTimeValue { value, .. } => {
+                format!("Found time value {}, expected integer", value)
+            }
+            InvalidTimeScale { time_scale, .. } => format!(
+                "Found timescale {}, expected one of: [ s ms us ns ps fs ]",
+                time_scale
+            ),
+            InvalidVarDump { .. } => "Variable dump formatted improperly".to_string(),
+            DeniedWarning { warning } => {
+                format!(
+                    "{} (warning {} is denied)",
+                    warning.message,
+                    warning.kind.code()
+                )
+            }
+            UnknownIdentifier { identifier, .. } => {
+                format!("Value change for undeclared identifier {}", identifier)
+            }
+            UnsupportedCommand { command, .. } => {
+                format!("{} is not part of the selected standard revision", command)
+            }
+            InvalidBinaryFile {
+                format,
+                offset,
+                reason,
+            } => format!("Invalid {} file at byte {}: {}", format, offset, reason),
+            Cancelled { offset } => format!("Loading cancelled after {} bytes", offset),
+            InFile { error, .. } => error.message(),
+        }
+    }
+
+    /// The name of the file the error was found in, if it was loaded from one.
+    pub fn filename(&self) -> Option<&str> {
+        match self {
+            LoadError::FileOpenError { filename, .. } | LoadError::InFile { filename, .. } => {
+                Some(filename)
+            }
+            _ => None,
+        }
+    }
+
+    /// The error itself, without the name of the file it was found in.
+    pub fn without_filename(&self) -> &LoadError {
+        match self {
+            LoadError::InFile { error, .. } => error,
+            _ => self,
+        }
+    }
+
+    /// Wraps the error in an `InFile` naming `filename`, unless it names a file already.
+    pub fn in_file(self, filename: &str) -> LoadError {
+        match self.filename() {
+            Some(_) => self,
+            None => LoadError::InFile {
+                filename: filename.to_string(),
+                error: Box::new(self),
+            },
+        }
+    }
+}
 
-    #[error("line {}: Variable dump formatted improperly", line)]
-    InvalidVarDump { line: usize },
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let LoadError::InFile { filename, error } = self {
+            return write!(f, "{}: {}", filename, error);
+        }
+        match self.span() {
+            Some(span) => write!(
+                f,
+                "line {}, column {}: {}",
+                span.line,
+                span.column,
+                self.message()
+            ),
+            None => write!(f, "{}", self.message()),
+        }
+    }
 }
@@ -0,0 +1,298 @@
+//! Following a VCD file while a simulator is still writing it, to watch signals live.
+//!
+//! A [`Follower`] keeps its [`IncrementalParser`] between polls. Each poll parses what was
+//! appended to the file since the last one, up to the last complete word, and returns the new
+//! value changes. A word cut off at the end of the file, like half a timestamp, is left for the
+//! next poll, once the rest of it has been written.
+
+use crate::compression::Compression;
+use crate::error::LoadError;
+use crate::options::ParseOptions;
+use crate::state_machine::StateMachine;
+use crate::tokenizer::{ByteTokenizer, Position, Span};
+use crate::types::value::Value;
+use crate::vcd::VCD;
+use crate::warning::Warning;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Read;
+
+/// A value change found by a [`Follower`] or an [`IncrementalParser`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueChange {
+    pub time: u64,
+    pub identifier: String,
+    pub value: Value,
+}
+
+pub struct Follower {
+    filename: String,
+    file: File,
+    parser: IncrementalParser,
+}
+
+impl Follower {
+    /// Starts following `filename` from its start. The file can't be compressed, as a
+    /// compressed stream being written can't be decompressed up to its end.
+    pub fn open(filename: String, options: &ParseOptions) -> Result<Follower, LoadError> {
+        let file = File::open(filename.as_str()).map_err(|e| LoadError::FileOpenError {
+            filename: filename.clone(),
+            error: e.to_string(),
+        })?;
+        Ok(Follower {
+            filename,
+            file,
+            parser: IncrementalParser::new(options),
+        })
+    }
+
+    /// The header, once its `$enddefinitions` has been parsed.
+    pub fn header(&self) -> Option<&VCD> {
+        self.parser.header()
+    }
+
+    /// The time of the last timestamp parsed.
+    pub fn time(&self) -> u64 {
+        self.parser.time()
+    }
+
+    /// The warnings found since the last call.
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        self.parser.take_warnings()
+    }
+
+    /// Parses what was appended to the file since the last poll, and returns the value changes
+    /// found in it, in time order. Returns nothing if the file didn't grow.
+    pub fn poll(&mut self) -> Result<Vec<ValueChange>, LoadError> {
+        let appended = self.read_appended()?;
+        if self.parser.bytes_fed() == 0 && !appended.is_empty() {
+            self.check_not_compressed(&appended)?;
+        }
+        self.parser.feed(&appended)
+    }
+
+    /// Parses the rest of the file as it is now, taking a word cut off at its end as complete,
+    /// and checks that the dump ends properly. Returns the last value changes.
+    pub fn finish(&mut self) -> Result<Vec<ValueChange>, LoadError> {
+        let appended = self.read_appended()?;
+        let mut changes = self.parser.feed(&appended)?;
+        changes.extend(self.parser.finish()?);
+        Ok(changes)
+    }
+
+    fn read_appended(&mut self) -> Result<Vec<u8>, LoadError> {
+        let mut appended = vec![];
+        match self.file.read_to_end(&mut appended) {
+            Ok(_) => Ok(appended),
+            Err(_) => Err(self.parser.read_error()),
+        }
+    }
+
+    fn check_not_compressed(&self, start: &[u8]) -> Result<(), LoadError> {
+        match Compression::detect(start) {
+            Compression::None => Ok(()),
+            _ => Err(LoadError::FileOpenError {
+                filename: self.filename.clone(),
+                error: "compressed files can't be followed".to_string(),
+            }),
+        }
+    }
+}
+
+/// Parses a VCD fed to it piece by piece, however it's cut, and returns the value changes found
+/// in each piece. Only the last change of each variable is kept in memory, and warnings only until
+/// they're taken, so the input can be endless.
+pub struct IncrementalParser {
+    state_machine: StateMachine,
+    /// Bytes fed but not parsed yet, as they don't end with a complete word.
+    unparsed: Vec<u8>,
+    /// Where `unparsed` starts in the input.
+    position: Position,
+    /// Identifiers whose last change, which the state machine keeps, has been returned already.
+    returned_last: HashSet<String>,
+}
+
+impl IncrementalParser {
+    pub fn new(options: &ParseOptions) -> Self {
+        IncrementalParser {
+            state_machine: StateMachine::with_options(options.clone()),
+            unparsed: vec![],
+            position: Position::new(),
+            returned_last: HashSet::new(),
+        }
+    }
+
+    /// The header, once its `$enddefinitions` has been parsed.
+    pub fn header(&self) -> Option<&VCD> {
+        match self.state_machine.definitions_done() {
+            true => Some(&self.state_machine.visitor.vcd),
+            false => None,
+        }
+    }
+
+    /// The time of the last timestamp parsed.
+    pub fn time(&self) -> u64 {
+        self.state_machine.time()
+    }
+
+    /// The warnings found since the last call. They're kept until taken, so a caller following an
+    /// endless input should take them every so often.
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        std::mem::take(&mut self.state_machine.warnings)
+    }
+
+    /// How many bytes were fed so far.
+    pub fn bytes_fed(&self) -> u64 {
+        (self.position.offset + self.unparsed.len()) as u64
+    }
+
+    /// Parses `bytes` up to their last complete word, and returns the value changes found, in
+    /// time order. A word cut off at their end is parsed once the rest of it is fed. After an
+    /// error, the words up to the one that caused it aren't parsed again by the next feed, and the
+    /// changes they hold are returned with its.
+    pub fn feed(&mut self, bytes: &[u8]) -> Result<Vec<ValueChange>, LoadError> {
+        self.unparsed.extend_from_slice(bytes);
+        let complete = self
+            .unparsed
+            .iter()
+            .rposition(|c| c.is_ascii_whitespace())
+            .map_or(0, |last_space| last_space + 1);
+        self.parse(complete)
+    }
+
+    /// Parses what's left, taking a word cut off at the end as complete, and checks that the
+    /// dump ends properly. Returns the last value changes.
+    pub fn finish(&mut self) -> Result<Vec<ValueChange>, LoadError> {
+        let changes = self.parse(self.unparsed.len())?;
+        self.state_machine.cleanup()?;
+        Ok(changes)
+    }
+
+    /// An error for failing to read the input after what was fed so far.
+    pub fn read_error(&self) -> LoadError {
+        let position = self.position;
+        LoadError::FileReadError {
+            span: Span::new(position.offset, 0, position.line, position.column),
+        }
+    }
+
+    /// Parses the first `len` bytes of `unparsed`, which end between two words. On an error, only
+    /// the words up to the one that caused it are dropped from `unparsed`.
+    fn parse(&mut self, len: usize) -> Result<Vec<ValueChange>, LoadError> {
+        let mut tokens = ByteTokenizer::resume(&self.unparsed[..len], self.position);
+        let state_machine = &mut self.state_machine;
+        let mut result = Ok(());
+        for (word, span) in &mut tokens {
+            result = std::str::from_utf8(word)
+                .map_err(|_| LoadError::FileReadError { span })
+                .and_then(|word| state_machine.parse_word(word, span));
+            if result.is_err() {
+                break;
+            }
+        }
+        let position = tokens.position();
+        self.unparsed
+            .drain(..position.offset - self.position.offset);
+        self.position = position;
+        result?;
+        Ok(self.new_changes())
+    }
+
+    /// The changes parsed since the last call. All but the last change of each variable are
+    /// dropped from the state machine.
+    fn new_changes(&mut self) -> Vec<ValueChange> {
+        let mut changes = vec![];
+        for (identifier, waveform) in self.state_machine.waveforms() {
+            let returned = self.returned_last.contains(identifier) as usize;
+            for (time, value) in &waveform.changes()[returned.min(waveform.len())..] {
+                changes.push(ValueChange {
+                    time: *time,
+                    identifier: identifier.to_string(),
+                    value: value.clone(),
+                });
+            }
+        }
+        for change in &changes {
+            self.returned_last.insert(change.identifier.clone());
+        }
+        self.state_machine.forget_changes();
+        changes.sort_by(|a, b| (a.time, &a.identifier).cmp(&(b.time, &b.identifier)));
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::value::Bit;
+    use std::io::Write;
+
+    #[test]
+    fn follows_a_growing_file() {
+        let path = std::env::temp_dir().join(format!("vcd_rust_follow_{}.vcd", std::process::id()));
+        let mut file = File::create(&path).unwrap();
+        let options = ParseOptions::default();
+        let mut follower = Follower::open(path.display().to_string(), &options).unwrap();
+        let mut append = |text: &str| {
+            file.write_all(text.as_bytes()).unwrap();
+            file.flush().unwrap();
+        };
+        let change = |time, identifier: &str, value| ValueChange {
+            time,
+            identifier: identifier.to_string(),
+            value,
+        };
+
+        append("$scope module top $end\n$var wire 1 ! clk $end\n$var wire 2 \" d $end\n$upsc");
+        assert_eq!(follower.poll(), Ok(vec![]));
+        assert!(follower.header().is_none());
+        append("ope $end\n$enddefinitions $end\n#0\n0!\nb1");
+        assert_eq!(
+            follower.poll(),
+            Ok(vec![change(0, "!", Value::Scalar(Bit::Zero))])
+        );
+        assert!(follower.header().unwrap().variables.contains_key("\""));
+        append("0 \" \n#1");
+        let d = Value::Vector(vec![Bit::One, Bit::Zero]);
+        assert_eq!(follower.poll(), Ok(vec![change(0, "\"", d)]));
+        assert_eq!(follower.time(), 0);
+        append("5\n1!\n");
+        assert_eq!(
+            follower.poll(),
+            Ok(vec![change(15, "!", Value::Scalar(Bit::One))])
+        );
+        assert_eq!(follower.poll(), Ok(vec![]));
+        append("#20\n0");
+        assert_eq!(follower.poll(), Ok(vec![]));
+        append("!");
+        assert_eq!(follower.poll(), Ok(vec![]));
+        let last = follower.finish();
+        assert_eq!(last, Ok(vec![change(20, "!", Value::Scalar(Bit::Zero))]));
+        assert_eq!(follower.time(), 20);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn words_before_an_error_are_not_parsed_again() {
+        let mut parser = IncrementalParser::new(&ParseOptions::default());
+        let header = "$scope module top $end\n$var wire 1 ! clk $end\n$upscope $end\n\
+                      $enddefinitions $end\n";
+        assert_eq!(parser.feed(header.as_bytes()), Ok(vec![]));
+        let err = parser.feed(b"#0\n0!\n#x\n").err();
+        assert_eq!(err.map(|err| err.code()), Some("E010"));
+        let changes = parser.feed(b"#5\n1!\n").unwrap();
+        let times: Vec<u64> = changes.iter().map(|change| change.time).collect();
+        assert_eq!(times, vec![0, 5]);
+    }
+
+    #[test]
+    fn warnings_are_taken_once() {
+        let mut parser = IncrementalParser::new(&ParseOptions::default());
+        parser
+            .feed(b"$scope module top $end\n$var wire 1 ! clk $end\n$upscope $end\n")
+            .unwrap();
+        parser.feed(b"$enddefinitions $end\n#0\n").unwrap();
+        assert!(!parser.take_warnings().is_empty());
+        assert!(parser.take_warnings().is_empty());
+    }
+}
@@ -0,0 +1,218 @@
+//! Reading GTKWave's FST format into the same [`VCD`] model the VCD parser fills, and writing
+//! that model back out as FST.
+//!
+//! FST files are identified by handles instead of identifier codes. Each handle is given the
+//! identifier code GTKWave's `fst2vcd` would give it, so a VCD converted from an FST file loads
+//! into the same model.
+
+mod fastlz;
+mod reader;
+mod writer;
+
+pub use writer::{Compression, WriteOptions};
+
+use crate::error::LoadError;
+use crate::string_helpers::{handle_for_identifier, identifier_for_handle};
+use crate::types::{
+    scope::ScopeType,
+    value::Bit,
+    variable::{VarDirection, VarType},
+};
+use crate::vcd::VCD;
+
+/// Loads an FST file. Errors are wrapped in a [`LoadError::InFile`] naming the file.
+pub fn load_from_file(filename: String) -> Result<VCD, LoadError> {
+    let bytes = std::fs::read(&filename).map_err(|err| LoadError::FileOpenError {
+        filename: filename.clone(),
+        error: err.to_string(),
+    })?;
+    load_from_bytes(&bytes).map_err(|err| err.in_file(&filename))
+}
+
+/// Loads FST data that is already in memory.
+pub fn load_from_bytes(bytes: &[u8]) -> Result<VCD, LoadError> {
+    reader::read(bytes)
+}
+
+/// Writes `vcd` to an FST file. Variables get handles in the order of their identifier codes,
+/// so identifier codes other than those `fst2vcd` writes don't survive the round trip. Fails
+/// with [`std::io::ErrorKind::InvalidInput`] if the `$timescale` number isn't 1, 10 or 100.
+pub fn write_to_file(vcd: &VCD, filename: String, options: &WriteOptions) -> std::io::Result<()> {
+    std::fs::write(filename, write_to_bytes(vcd, options)?)
+}
+
+/// Writes `vcd` as FST data in memory.
+pub fn write_to_bytes(vcd: &VCD, options: &WriteOptions) -> std::io::Result<Vec<u8>> {
+    writer::write(vcd, options)
+}
+
+const BLOCK_HEADER: u8 = 0;
+const BLOCK_VALUE_CHANGES: u8 = 1;
+const BLOCK_BLACKOUT: u8 = 2;
+const BLOCK_GEOMETRY: u8 = 3;
+const BLOCK_HIERARCHY: u8 = 4;
+const BLOCK_VALUE_CHANGES_ALIAS: u8 = 5;
+const BLOCK_HIERARCHY_LZ4: u8 = 6;
+const BLOCK_HIERARCHY_LZ4_DUO: u8 = 7;
+const BLOCK_VALUE_CHANGES_ALIAS2: u8 = 8;
+const BLOCK_GZIP_WRAPPER: u8 = 254;
+const BLOCK_SKIP: u8 = 255;
+
+const HIERARCHY_ATTRIBUTE_BEGIN: u8 = 252;
+const HIERARCHY_ATTRIBUTE_END: u8 = 253;
+const HIERARCHY_SCOPE: u8 = 254;
+const HIERARCHY_UPSCOPE: u8 = 255;
+
+/// Written into the header as a native double, so readers can tell the writer's byte order.
+const ENDIAN_TEST: f64 = std::f64::consts::E;
+
+/// Geometry entry of a variable length signal.
+const VARIABLE_LENGTH: u64 = 0xFFFF_FFFF;
+
+/// The 1-bit value change codes that aren't 0 or 1.
+const ONE_BIT_STATES: &[u8; 8] = b"xzhuwl-?";
+
+/// How the values of one signal are stored.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Signal {
+    Bits(usize),
+    Real,
+    VariableLength,
+}
+
+impl Signal {
+    /// Bytes a signal's initial value takes up in a frame.
+    fn frame_len(self) -> usize {
+        match self {
+            Signal::Bits(len) => len,
+            Signal::Real => 8,
+            Signal::VariableLength => 0,
+        }
+    }
+}
+
+/// Scope types, indexed by their FST code.
+const SCOPE_TYPES: [ScopeType; 22] = {
+    use ScopeType::*;
+    [
+        Module,
+        Task,
+        Function,
+        Begin,
+        Fork,
+        Generate,
+        Struct,
+        Union,
+        Class,
+        Interface,
+        Package,
+        Program,
+        VhdlArchitecture,
+        VhdlProcedure,
+        VhdlFunction,
+        VhdlRecord,
+        VhdlProcess,
+        VhdlBlock,
+        VhdlForGenerate,
+        VhdlIfGenerate,
+        VhdlGenerate,
+        VhdlPackage,
+    ]
+};
+
+/// Var types, indexed by their FST code.
+const VAR_TYPES: [VarType; 30] = {
+    use VarType::*;
+    [
+        Event,
+        Integer,
+        Parameter,
+        Real,
+        RealParameter,
+        Reg,
+        Supply0,
+        Supply1,
+        Time,
+        Tri,
+        TriAnd,
+        TriOr,
+        TriReg,
+        Tri0,
+        Tri1,
+        WAnd,
+        Wire,
+        WOr,
+        Port,
+        SparseArray,
+        RealTime,
+        String,
+        Bit,
+        Logic,
+        Int,
+        ShortInt,
+        LongInt,
+        Byte,
+        Enum,
+        ShortReal,
+    ]
+};
+
+fn scope_type_from_fst(code: u8) -> Option<ScopeType> {
+    SCOPE_TYPES.get(code as usize).cloned()
+}
+
+fn scope_type_to_fst(scope_type: &ScopeType) -> u8 {
+    SCOPE_TYPES.iter().position(|t| t == scope_type).unwrap() as u8
+}
+
+fn var_type_from_fst(code: u8) -> Option<VarType> {
+    VAR_TYPES.get(code as usize).cloned()
+}
+
+fn var_type_to_fst(var_type: &VarType) -> u8 {
+    VAR_TYPES.iter().position(|t| t == var_type).unwrap() as u8
+}
+
+/// Var directions, indexed by their FST code. Variables that aren't ports are implicit.
+const VAR_DIRECTIONS: [Option<VarDirection>; 6] = {
+    use VarDirection::*;
+    [
+        None,
+        Some(Input),
+        Some(Output),
+        Some(InOut),
+        Some(Buffer),
+        Some(Linkage),
+    ]
+};
+
+fn direction_from_fst(code: u8) -> Option<Option<VarDirection>> {
+    VAR_DIRECTIONS.get(code as usize).cloned()
+}
+
+fn direction_to_fst(direction: Option<VarDirection>) -> u8 {
+    VAR_DIRECTIONS.iter().position(|d| *d == direction).unwrap() as u8
+}
+
+/// FST values use the nine VHDL `std_logic` states. Weak levels become their strong
+/// counterparts, and everything that isn't a level becomes `x`.
+fn bit_from_fst(c: u8) -> Bit {
+    match c {
+        b'0' | b'l' | b'L' => Bit::Zero,
+        b'1' | b'h' | b'H' => Bit::One,
+        b'z' | b'Z' => Bit::Z,
+        _ => Bit::X,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn std_logic_values_map_to_bits() {
+        let bits: Vec<Bit> = b"01xzhlUW-".iter().map(|c| bit_from_fst(*c)).collect();
+        use Bit::*;
+        assert_eq!(bits, vec![Zero, One, X, Z, One, Zero, X, X, X]);
+    }
+}
@@ -0,0 +1,86 @@
+//! Decompression of FastLZ blocks, which FST writers can use for value change data.
+
+/// Distance added to far matches in level 2 blocks.
+const MAX_L2_DISTANCE: usize = 8191;
+
+/// Decompresses a FastLZ block of either level. The level is in the top three bits of the first
+/// byte. Returns `None` if the block is malformed.
+pub fn decompress(input: &[u8], size_hint: usize) -> Option<Vec<u8>> {
+    let level = (input.first()? >> 5) + 1;
+    let mut out = Vec::with_capacity(size_hint);
+    let mut pos = 0;
+    let mut ctrl = (input[pos] & 31) as usize;
+    pos += 1;
+    loop {
+        if ctrl >= 32 {
+            let mut len = (ctrl >> 5) + 2;
+            let high = (ctrl & 31) << 8;
+            if len == 9 {
+                loop {
+                    let code = *input.get(pos)?;
+                    pos += 1;
+                    len += code as usize;
+                    if level == 1 || code != 255 {
+                        break;
+                    }
+                }
+            }
+            let low = *input.get(pos)? as usize;
+            pos += 1;
+            let mut distance = high + low + 1;
+            if level == 2 && low == 255 && high == 31 << 8 {
+                let far = ((*input.get(pos)? as usize) << 8) + *input.get(pos + 1)? as usize;
+                pos += 2;
+                distance = far + MAX_L2_DISTANCE + 1;
+            }
+            let start = out.len().checked_sub(distance)?;
+            for i in start..start + len {
+                out.push(out[i]);
+            }
+        } else {
+            let literals = input.get(pos..pos + ctrl + 1)?;
+            out.extend_from_slice(literals);
+            pos += ctrl + 1;
+        }
+        if pos >= input.len() {
+            return Some(out);
+        }
+        ctrl = input[pos] as usize;
+        pos += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn level1_literals_and_matches() {
+        // "abcd", then a copy of 5 bytes from 4 back, then "e".
+        let input = [3, b'a', b'b', b'c', b'd', (3 << 5), 3, 0, b'e'];
+        assert_eq!(decompress(&input, 0).unwrap(), b"abcdabcdae".to_vec());
+    }
+
+    #[test]
+    fn level1_long_match() {
+        // "ab", then a copy of 9 + 3 bytes from 2 back.
+        let input = [1, b'a', b'b', (7 << 5), 3, 1];
+        assert_eq!(decompress(&input, 0).unwrap(), b"ababababababab".to_vec());
+    }
+
+    #[test]
+    fn level2_long_match() {
+        // "ab", then a copy of 9 + 255 + 1 bytes from 2 back.
+        let input = [(1 << 5) | 1, b'a', b'b', (7 << 5), 255, 1, 1];
+        let out = decompress(&input, 0).unwrap();
+        assert_eq!(out.len(), 2 + 9 + 256);
+        assert!(out.chunks(2).all(|pair| pair[0] == b'a'));
+    }
+
+    #[test]
+    fn malformed_block() {
+        assert_eq!(decompress(&[], 0), None);
+        assert_eq!(decompress(&[5, b'a'], 0), None);
+        assert_eq!(decompress(&[0, b'a', (1 << 5), 5], 0), None);
+    }
+}
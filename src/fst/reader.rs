@@ -0,0 +1,878 @@
+use super::*;
+use crate::types::{
+    attribute::{Attribute, AttributeType, MiscKind},
+    scope::Scope,
+    timescale::{TimeScale, TimeUnit},
+    value::Value,
+    variable::{VarType, VariableBuilder},
+};
+use crate::vcd::VCD;
+use flate2::read::{GzDecoder, ZlibDecoder};
+use std::collections::HashMap;
+use std::io::Read;
+
+/// Where a block starts and what kind it is. `start` is the offset of its section length,
+/// right after the block type.
+struct Block {
+    block_type: u8,
+    start: usize,
+    len: usize,
+}
+
+pub fn read(bytes: &[u8]) -> Result<VCD, LoadError> {
+    if bytes.first() == Some(&BLOCK_GZIP_WRAPPER) {
+        let mut cursor = Cursor::new(bytes, 1);
+        let _section_len = cursor.u64()?;
+        let uncompressed_len = cursor.u64()? as usize;
+        let unwrapped = gunzip(cursor.rest(), uncompressed_len, 1)?;
+        return read(&unwrapped);
+    }
+    FstReader::new(bytes)?.read()
+}
+
+struct FstReader<'a> {
+    bytes: &'a [u8],
+    blocks: Vec<Block>,
+    vcd: VCD,
+    little_endian: bool,
+    signals: Vec<Signal>,
+}
+
+impl<'a> FstReader<'a> {
+    fn new(bytes: &'a [u8]) -> Result<Self, LoadError> {
+        let mut blocks = vec![];
+        let mut cursor = Cursor::new(bytes, 0);
+        while !cursor.at_end() {
+            let block_type = cursor.u8()?;
+            let start = cursor.pos;
+            let len = cursor.u64()? as usize;
+            if block_type == BLOCK_SKIP && len == 0 {
+                return Err(invalid(start, "file was not finished by its writer"));
+            }
+            if len < 8 {
+                return Err(invalid(start, "block is too short"));
+            }
+            cursor.skip(len - 8)?;
+            blocks.push(Block {
+                block_type,
+                start,
+                len,
+            });
+        }
+        Ok(FstReader {
+            bytes,
+            blocks,
+            vcd: VCD::default(),
+            little_endian: true,
+            signals: vec![],
+        })
+    }
+
+    fn read(mut self) -> Result<VCD, LoadError> {
+        let blocks = std::mem::take(&mut self.blocks);
+        match blocks.first() {
+            Some(block) if block.block_type == BLOCK_HEADER => self.read_header(block)?,
+            _ => return Err(invalid(0, "missing header block")),
+        }
+        for block in &blocks {
+            if block.block_type == BLOCK_GEOMETRY {
+                self.read_geometry(block)?;
+            }
+        }
+        for block in &blocks {
+            match block.block_type {
+                BLOCK_HIERARCHY | BLOCK_HIERARCHY_LZ4 | BLOCK_HIERARCHY_LZ4_DUO => {
+                    self.read_hierarchy(block)?
+                }
+                _ => {}
+            }
+        }
+        let mut first = true;
+        for block in &blocks {
+            match block.block_type {
+                BLOCK_VALUE_CHANGES | BLOCK_VALUE_CHANGES_ALIAS | BLOCK_VALUE_CHANGES_ALIAS2 => {
+                    self.read_value_changes(block, first)?;
+                    first = false;
+                }
+                BLOCK_HEADER | BLOCK_BLACKOUT | BLOCK_GEOMETRY | BLOCK_SKIP => {}
+                BLOCK_HIERARCHY | BLOCK_HIERARCHY_LZ4 | BLOCK_HIERARCHY_LZ4_DUO => {}
+                _ => return Err(invalid(block.start - 1, "unknown block type")),
+            }
+        }
+        Ok(self.vcd)
+    }
+
+    fn cursor(&self, block: &Block) -> Cursor<'a> {
+        // Skip the section length.
+        Cursor::new(&self.bytes[..block.start + block.len], block.start + 8)
+    }
+
+    fn read_header(&mut self, block: &Block) -> Result<(), LoadError> {
+        let mut cursor = self.cursor(block);
+        let _start_time = cursor.u64()?;
+        let _end_time = cursor.u64()?;
+        let endian_test = cursor.take(8)?;
+        let mut raw = [0; 8];
+        raw.copy_from_slice(endian_test);
+        self.little_endian = match (f64::from_le_bytes(raw), f64::from_be_bytes(raw)) {
+            (le, _) if le == ENDIAN_TEST => true,
+            (_, be) if be == ENDIAN_TEST => false,
+            _ => return Err(invalid(cursor.pos - 8, "unknown byte order")),
+        };
+        let _memory_used = cursor.u64()?;
+        let _scope_count = cursor.u64()?;
+        let _var_count = cursor.u64()?;
+        let _max_handle = cursor.u64()?;
+        let _value_change_blocks = cursor.u64()?;
+        let exponent = cursor.u8()? as i8;
+        self.vcd.timescale = timescale_from_exponent(exponent)
+            .ok_or_else(|| invalid(cursor.pos - 1, "timescale out of range"))?;
+        self.vcd.version = fixed_string(cursor.take(128)?);
+        self.vcd.date = fixed_string(cursor.take(119)?);
+        Ok(())
+    }
+
+    fn read_geometry(&mut self, block: &Block) -> Result<(), LoadError> {
+        let mut cursor = self.cursor(block);
+        let uncompressed_len = cursor.u64()? as usize;
+        let max_handle = cursor.u64()? as usize;
+        let data = cursor.rest();
+        let data = match data.len() == uncompressed_len {
+            true => data.to_vec(),
+            false => inflate(data, uncompressed_len, block.start)?,
+        };
+        // Each handle takes at least a byte.
+        if max_handle > data.len() {
+            return Err(invalid(
+                block.start,
+                "more handles than the geometry describes",
+            ));
+        }
+        let mut geometry = Cursor::new(&data, 0);
+        self.signals = (0..max_handle)
+            .map(|_| {
+                Ok(match geometry.varint()? {
+                    0 => Signal::Real,
+                    VARIABLE_LENGTH => Signal::VariableLength,
+                    len => Signal::Bits(len as usize),
+                })
+            })
+            .collect::<Result<Vec<Signal>, LoadError>>()?;
+        Ok(())
+    }
+
+    fn read_hierarchy(&mut self, block: &Block) -> Result<(), LoadError> {
+        let mut cursor = self.cursor(block);
+        let uncompressed_len = cursor.u64()? as usize;
+        let data = match block.block_type {
+            BLOCK_HIERARCHY => gunzip(cursor.rest(), uncompressed_len, block.start)?,
+            BLOCK_HIERARCHY_LZ4 => unlz4(cursor.rest(), uncompressed_len, block.start)?,
+            _ => {
+                let once_len = cursor.varint()? as usize;
+                let once = unlz4(cursor.rest(), once_len, block.start)?;
+                unlz4(&once, uncompressed_len, block.start)?
+            }
+        };
+        HierarchyReader::new(&mut self.vcd, &mut self.signals).read(&data)
+    }
+
+    fn read_value_changes(&mut self, block: &Block, first: bool) -> Result<(), LoadError> {
+        let end = block.start + block.len;
+        let mut cursor = self.cursor(block);
+        let begin_time = cursor.u64()?;
+        let _end_time = cursor.u64()?;
+        let _memory_required = cursor.u64()?;
+
+        let tail_start = end
+            .checked_sub(24)
+            .ok_or_else(|| invalid(block.start, "block is too short"))?;
+        let mut tail = Cursor::new(self.bytes, tail_start);
+        let times_uncompressed_len = tail.u64()? as usize;
+        let times_len = tail.u64()? as usize;
+        let time_count = tail.u64()?;
+        let times_start = tail_start
+            .checked_sub(times_len)
+            .filter(|start| *start >= cursor.pos)
+            .ok_or_else(|| invalid(block.start, "time table out of bounds"))?;
+        let times = Cursor::new(&self.bytes[..tail_start], times_start).rest();
+        let times = match times_len == times_uncompressed_len {
+            true => times.to_vec(),
+            false => inflate(times, times_uncompressed_len, times_start)?,
+        };
+        let mut times_cursor = Cursor::new(&times, 0);
+        let mut time: u64 = 0;
+        let time_table = (0..time_count)
+            .map(|_| {
+                time = time
+                    .checked_add(times_cursor.varint()?)
+                    .ok_or_else(|| invalid(times_start, "time out of range"))?;
+                Ok(time)
+            })
+            .collect::<Result<Vec<u64>, LoadError>>()?;
+
+        let frame_uncompressed_len = cursor.varint()? as usize;
+        let frame_len = cursor.varint()? as usize;
+        let frame_max_handle = cursor.varint()? as usize;
+        let frame_start = cursor.pos;
+        let frame = cursor.take(frame_len)?;
+        if first && time_table.first().is_none_or(|time| *time > begin_time) {
+            let frame = match frame_len == frame_uncompressed_len {
+                true => frame.to_vec(),
+                false => inflate(frame, frame_uncompressed_len, frame_start)?,
+            };
+            self.read_frame(&frame, frame_max_handle, begin_time, frame_start)?;
+        }
+
+        let max_handle = cursor.varint()? as usize;
+        let values_start = cursor.pos;
+        let pack_type = cursor.u8()?;
+        let mut chain_len_cursor = Cursor::new(self.bytes, times_start - 8);
+        let chain_len = chain_len_cursor.u64()? as usize;
+        let chain_start = (times_start - 8)
+            .checked_sub(chain_len)
+            .filter(|start| *start >= values_start)
+            .ok_or_else(|| invalid(block.start, "offset table out of bounds"))?;
+        let chain = &self.bytes[chain_start..times_start - 8];
+        let locations = match block.block_type {
+            BLOCK_VALUE_CHANGES_ALIAS2 => read_locations_alias2(chain, max_handle, chain_start)?,
+            _ => read_locations(chain, max_handle, chain_start)?,
+        };
+        let locations = resolve_locations(locations, chain_start - values_start, chain_start)?;
+
+        for (index, location) in locations.iter().enumerate() {
+            let (offset, len) = match location {
+                Some(location) => *location,
+                None => continue,
+            };
+            let start = values_start + offset;
+            let data = Cursor::new(&self.bytes[..start + len], start).rest();
+            let data = unpack(data, pack_type, start)?;
+            self.read_signal_changes(index, &data, &time_table, start)?;
+        }
+        Ok(())
+    }
+
+    fn read_frame(
+        &mut self,
+        frame: &[u8],
+        max_handle: usize,
+        time: u64,
+        start: usize,
+    ) -> Result<(), LoadError> {
+        let mut cursor = Cursor::new(frame, 0);
+        for index in 0..max_handle.min(self.signals.len()) {
+            let signal = self.signals[index];
+            let raw = cursor
+                .take(signal.frame_len())
+                .map_err(|_| invalid(start, "frame is too short"))?;
+            if let Some(value) = self.value(signal, raw) {
+                self.push(index, time, value);
+            }
+        }
+        Ok(())
+    }
+
+    fn read_signal_changes(
+        &mut self,
+        index: usize,
+        data: &[u8],
+        time_table: &[u64],
+        start: usize,
+    ) -> Result<(), LoadError> {
+        let signal = *self
+            .signals
+            .get(index)
+            .ok_or_else(|| invalid(start, "value changes for unknown handle"))?;
+        let mut cursor = Cursor::new(data, 0);
+        let mut time_index = 0;
+        while !cursor.at_end() {
+            let code = cursor.varint()?;
+            let value = match signal {
+                Signal::Bits(1) => {
+                    time_index = add(time_index, (code >> (2 << (code & 1))) as usize, start)?;
+                    let state = match code & 1 {
+                        0 => b'0' + ((code >> 1) & 1) as u8,
+                        _ => ONE_BIT_STATES[((code >> 1) & 7) as usize],
+                    };
+                    self.value(signal, &[state])
+                }
+                Signal::Bits(len) => {
+                    time_index = add(time_index, (code >> 1) as usize, start)?;
+                    match code & 1 {
+                        0 => {
+                            let packed = cursor.take(len.div_ceil(8))?;
+                            let chars: Vec<u8> = (0..len)
+                                .map(|bit| b'0' + ((packed[bit / 8] >> (7 - bit % 8)) & 1))
+                                .collect();
+                            self.value(signal, &chars)
+                        }
+                        _ => self.value(signal, cursor.take(len)?),
+                    }
+                }
+                Signal::Real => {
+                    time_index = add(time_index, (code >> 1) as usize, start)?;
+                    match code & 1 {
+                        1 => self.value(signal, cursor.take(8)?),
+                        _ => return Err(invalid(start, "unsupported real value encoding")),
+                    }
+                }
+                Signal::VariableLength => {
+                    time_index = add(time_index, (code >> 1) as usize, start)?;
+                    let len = cursor.varint()? as usize;
+                    cursor.take(len)?;
+                    None
+                }
+            };
+            let time = *time_table
+                .get(time_index)
+                .ok_or_else(|| invalid(start, "value change after the last time"))?;
+            if let Some(value) = value {
+                self.push(index, time, value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Turns the bytes of a value into a [`Value`]. Variable length values, like strings, have no
+    /// equivalent in the model and are dropped.
+    fn value(&self, signal: Signal, raw: &[u8]) -> Option<Value> {
+        match signal {
+            Signal::Bits(1) => Some(Value::Scalar(bit_from_fst(raw[0]))),
+            Signal::Bits(_) => Some(Value::Vector(
+                raw.iter().map(|c| bit_from_fst(*c)).collect(),
+            )),
+            Signal::Real => {
+                let mut bytes = [0; 8];
+                bytes.copy_from_slice(raw);
+                Some(Value::Real(match self.little_endian {
+                    true => f64::from_le_bytes(bytes),
+                    false => f64::from_be_bytes(bytes),
+                }))
+            }
+            Signal::VariableLength => None,
+        }
+    }
+
+    fn push(&mut self, index: usize, time: u64, value: Value) {
+        let identifier = identifier_for_handle(index as u64 + 1);
+        if self.vcd.variables.contains_key(&identifier) {
+            self.vcd
+                .waveforms
+                .entry(identifier)
+                .or_default()
+                .push(time, value);
+        }
+    }
+}
+
+/// Builds the variables and scopes of the [`VCD`] from the uncompressed hierarchy.
+struct HierarchyReader<'v> {
+    vcd: &'v mut VCD,
+    signals: &'v mut Vec<Signal>,
+    scope_stack: Vec<Scope>,
+    pending_attributes: Vec<Attribute>,
+    path_names: HashMap<u64, String>,
+    handle_count: u64,
+}
+
+impl<'v> HierarchyReader<'v> {
+    fn new(vcd: &'v mut VCD, signals: &'v mut Vec<Signal>) -> Self {
+        HierarchyReader {
+            vcd,
+            signals,
+            scope_stack: vec![],
+            pending_attributes: vec![],
+            path_names: HashMap::new(),
+            handle_count: 0,
+        }
+    }
+
+    fn read(&mut self, data: &[u8]) -> Result<(), LoadError> {
+        let mut cursor = Cursor::new(data, 0);
+        while !cursor.at_end() {
+            let tag = cursor.u8()?;
+            match tag {
+                HIERARCHY_SCOPE => self.read_scope(&mut cursor)?,
+                HIERARCHY_UPSCOPE => {
+                    self.scope_stack.pop();
+                }
+                HIERARCHY_ATTRIBUTE_BEGIN => self.read_attribute(&mut cursor)?,
+                HIERARCHY_ATTRIBUTE_END => self.pending_attributes.clear(),
+                _ => self.read_var(tag, &mut cursor)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn read_scope(&mut self, cursor: &mut Cursor) -> Result<(), LoadError> {
+        let code = cursor.u8()?;
+        let scope_type = scope_type_from_fst(code)
+            .ok_or_else(|| invalid(cursor.pos - 1, "unknown scope type"))?;
+        let mut scope = Scope::init(scope_type, cursor.c_string()?);
+        let _component = cursor.c_string()?;
+        scope.attributes = std::mem::take(&mut self.pending_attributes);
+        self.scope_stack.push(scope);
+        Ok(())
+    }
+
+    fn read_attribute(&mut self, cursor: &mut Cursor) -> Result<(), LoadError> {
+        let attribute_type = match cursor.u8()? {
+            0 => AttributeType::Misc,
+            1 => AttributeType::Array,
+            2 => AttributeType::Enum,
+            3 => AttributeType::Class,
+            _ => return Err(invalid(cursor.pos - 1, "unknown attribute type")),
+        };
+        let subtype = cursor.u8()? as u32;
+        let mut attribute = Attribute::new(attribute_type, subtype, String::new(), 0);
+        let misc_kind = attribute.misc_kind();
+        let path_id = match misc_kind {
+            // Source locations refer to a path name by its id, written where the name would be.
+            Some(MiscKind::SourceStem) | Some(MiscKind::SourceInstantiationStem) => {
+                let path_id = cursor.varint()?;
+                cursor.u8()?;
+                Some(path_id)
+            }
+            _ => {
+                attribute.name = cursor.c_string()?;
+                None
+            }
+        };
+        attribute.argument = cursor.varint()?;
+        if let Some(path_id) = path_id {
+            attribute.name = self.path_names.get(&path_id).cloned().unwrap_or_default();
+        }
+        match (misc_kind, attribute.enum_table()) {
+            (Some(MiscKind::PathName), _) => {
+                self.path_names.insert(attribute.argument, attribute.name);
+            }
+            (_, Some(table)) => {
+                self.vcd.enum_tables.insert(attribute.argument, table);
+            }
+            _ => self.pending_attributes.push(attribute),
+        }
+        Ok(())
+    }
+
+    fn read_var(&mut self, code: u8, cursor: &mut Cursor) -> Result<(), LoadError> {
+        let var_type =
+            var_type_from_fst(code).ok_or_else(|| invalid(cursor.pos - 1, "unknown var type"))?;
+        let direction = direction_from_fst(cursor.u8()?)
+            .ok_or_else(|| invalid(cursor.pos - 1, "unknown var direction"))?;
+        let reference = cursor.c_string()?;
+        let mut len = cursor.varint()? as usize;
+        let alias = cursor.varint()?;
+        if var_type == VarType::Port {
+            // Port lengths count the characters of an extended VCD value change.
+            len = len.saturating_sub(2) / 3;
+        }
+        let handle = match alias {
+            0 => {
+                self.handle_count += 1;
+                self.handle_count
+            }
+            alias => alias,
+        };
+        let index = handle as usize - 1;
+        if alias == 0 && index >= self.signals.len() {
+            self.signals.push(Signal::Bits(len));
+        }
+        let variable = VariableBuilder::default()
+            .scope(self.scope_stack.clone())
+            .var_type(var_type)
+            .bit_width(len)
+            .ascii_identifier(identifier_for_handle(handle))
+            .reference(reference)
+            .attributes(std::mem::take(&mut self.pending_attributes))
+            .direction(direction)
+            .build()
+            .expect("every field of the variable is set");
+        match self.vcd.variables.contains_key(&variable.ascii_identifier) {
+            true => self.vcd.aliases.push(variable),
+            false => {
+                self.vcd
+                    .variables
+                    .insert(variable.ascii_identifier.clone(), variable);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Where the value changes of a handle are in a value change block: either at an offset from the
+/// pack type byte, or the same as those of an earlier handle.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Location {
+    None,
+    Offset(usize),
+    Alias(usize),
+}
+
+fn read_locations(
+    chain: &[u8],
+    max_handle: usize,
+    start: usize,
+) -> Result<Vec<Location>, LoadError> {
+    let mut cursor = Cursor::new(chain, 0);
+    let mut locations = Vec::with_capacity(max_handle.min(chain.len()));
+    let mut offset = 0;
+    while !cursor.at_end() {
+        let value = cursor.varint()? as usize;
+        if value == 0 {
+            let alias = (cursor.varint()? as usize)
+                .checked_sub(1)
+                .ok_or_else(|| invalid(start, "invalid alias in offset table"))?;
+            locations.push(Location::Alias(alias));
+        } else if value & 1 == 1 {
+            offset = add(offset, value >> 1, start)?;
+            locations.push(Location::Offset(offset));
+        } else {
+            skip_handles(&mut locations, value >> 1, max_handle, start)?;
+        }
+    }
+    Ok(locations)
+}
+
+fn read_locations_alias2(
+    chain: &[u8],
+    max_handle: usize,
+    start: usize,
+) -> Result<Vec<Location>, LoadError> {
+    let mut cursor = Cursor::new(chain, 0);
+    let mut locations = Vec::with_capacity(max_handle.min(chain.len()));
+    let mut offset = 0;
+    let mut previous_alias = None;
+    while !cursor.at_end() {
+        if cursor.peek()? & 1 == 1 {
+            let value = cursor.signed_varint()? >> 1;
+            if value > 0 {
+                offset = add(offset, value as usize, start)?;
+                locations.push(Location::Offset(offset));
+            } else {
+                if value < 0 {
+                    previous_alias = Some((-(value + 1)) as usize);
+                }
+                let alias = previous_alias
+                    .ok_or_else(|| invalid(start, "invalid alias in offset table"))?;
+                locations.push(Location::Alias(alias));
+            }
+        } else {
+            let skip = (cursor.varint()? >> 1) as usize;
+            skip_handles(&mut locations, skip, max_handle, start)?;
+        }
+    }
+    Ok(locations)
+}
+
+/// Adds `count` handles without value changes, which can't take the table past `max_handle`.
+fn skip_handles(
+    locations: &mut Vec<Location>,
+    count: usize,
+    max_handle: usize,
+    start: usize,
+) -> Result<(), LoadError> {
+    if count > max_handle.saturating_sub(locations.len()) {
+        return Err(invalid(
+            start,
+            "offset table has more handles than the block",
+        ));
+    }
+    locations.extend(std::iter::repeat_n(Location::None, count));
+    Ok(())
+}
+
+/// Turns locations into the offset and length of each handle's value changes. The value changes
+/// of a handle end where those of the next handle with its own offset begin, and those of the
+/// last one where the offset table begins, at `end`.
+fn resolve_locations(
+    locations: Vec<Location>,
+    end: usize,
+    start: usize,
+) -> Result<Vec<Option<(usize, usize)>>, LoadError> {
+    let mut offsets: Vec<usize> = locations
+        .iter()
+        .filter_map(|location| match location {
+            Location::Offset(offset) => Some(*offset),
+            _ => None,
+        })
+        .collect();
+    offsets.push(end);
+    let mut next = offsets.iter().skip(1);
+    let mut resolved: Vec<Option<(usize, usize)>> = vec![];
+    for location in &locations {
+        let entry = match location {
+            Location::None => None,
+            Location::Offset(offset) => {
+                let next = *next.next().unwrap_or(&end);
+                let len = next
+                    .checked_sub(*offset)
+                    .ok_or_else(|| invalid(start, "offsets in offset table are out of order"))?;
+                Some((*offset, len))
+            }
+            Location::Alias(alias) => match resolved.get(*alias) {
+                Some(entry) => *entry,
+                None => return Err(invalid(start, "alias to a later handle in offset table")),
+            },
+        };
+        resolved.push(entry);
+    }
+    Ok(resolved)
+}
+
+/// Unpacks the value changes of one handle. They start with their uncompressed length, which is
+/// zero if they are stored uncompressed.
+fn unpack(data: &[u8], pack_type: u8, start: usize) -> Result<Vec<u8>, LoadError> {
+    let mut cursor = Cursor::new(data, 0);
+    let uncompressed_len = cursor.varint()? as usize;
+    let packed = cursor.rest();
+    match (uncompressed_len, pack_type) {
+        (0, _) => Ok(packed.to_vec()),
+        (_, b'4') => unlz4(packed, uncompressed_len, start),
+        (_, b'F') => fastlz::decompress(packed, uncompressed_len.min(packed.len()))
+            .filter(|data| data.len() == uncompressed_len)
+            .ok_or_else(|| invalid(start, "invalid FastLZ data")),
+        _ => inflate(packed, uncompressed_len, start),
+    }
+}
+
+fn inflate(data: &[u8], len: usize, start: usize) -> Result<Vec<u8>, LoadError> {
+    decompress(
+        ZlibDecoder::new(data),
+        data.len(),
+        len,
+        start,
+        "invalid zlib data",
+    )
+}
+
+fn gunzip(data: &[u8], len: usize, start: usize) -> Result<Vec<u8>, LoadError> {
+    decompress(
+        GzDecoder::new(data),
+        data.len(),
+        len,
+        start,
+        "invalid gzip data",
+    )
+}
+
+/// Decompresses `len` bytes out of `compressed_len` ones. Both lengths come from the file, so
+/// memory is only reserved for as many bytes as were compressed, and no more than `len` are read.
+fn decompress(
+    decoder: impl Read,
+    compressed_len: usize,
+    len: usize,
+    start: usize,
+    reason: &str,
+) -> Result<Vec<u8>, LoadError> {
+    let mut out = Vec::with_capacity(len.min(compressed_len));
+    match decoder.take(len as u64 + 1).read_to_end(&mut out) {
+        Ok(_) if out.len() == len => Ok(out),
+        _ => Err(invalid(start, reason)),
+    }
+}
+
+fn unlz4(data: &[u8], len: usize, start: usize) -> Result<Vec<u8>, LoadError> {
+    // The output is allocated up front, and LZ4 makes at most 255 bytes out of each one.
+    if len > data.len().saturating_mul(255) {
+        return Err(invalid(start, "invalid LZ4 data"));
+    }
+    lz4_flex::block::decompress(data, len).map_err(|_| invalid(start, "invalid LZ4 data"))
+}
+
+fn timescale_from_exponent(exponent: i8) -> Option<TimeScale> {
+    let unit = match exponent.div_euclid(3) {
+        0 => TimeUnit::S,
+        -1 => TimeUnit::MS,
+        -2 => TimeUnit::US,
+        -3 => TimeUnit::NS,
+        -4 => TimeUnit::PS,
+        -5 => TimeUnit::FS,
+        _ => return None,
+    };
+    Some(TimeScale::new(
+        10usize.pow(exponent.rem_euclid(3) as u32),
+        unit,
+    ))
+}
+
+/// A string in a fixed size, zero padded field.
+fn fixed_string(field: &[u8]) -> String {
+    let len = field.iter().position(|b| *b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..len]).into_owned()
+}
+
+/// `a + b`, for numbers read from the file.
+fn add(a: usize, b: usize, start: usize) -> Result<usize, LoadError> {
+    a.checked_add(b)
+        .ok_or_else(|| invalid(start, "number out of range"))
+}
+
+fn invalid(offset: usize, reason: &str) -> LoadError {
+    LoadError::InvalidBinaryFile {
+        format: "FST".to_string(),
+        offset,
+        reason: reason.to_string(),
+    }
+}
+
+/// Reads the big endian integers, varints and strings FST is made of. Offsets in errors are
+/// relative to the start of `bytes`.
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(bytes: &'a [u8], pos: usize) -> Self {
+        Cursor { bytes, pos }
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], LoadError> {
+        let bytes = self
+            .pos
+            .checked_add(len)
+            .and_then(|end| self.bytes.get(self.pos..end))
+            .ok_or_else(|| invalid(self.pos, "unexpected end of data"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, len: usize) -> Result<(), LoadError> {
+        self.take(len).map(|_| ())
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        let rest = self.bytes.get(self.pos..).unwrap_or_default();
+        self.pos = self.bytes.len();
+        rest
+    }
+
+    fn peek(&self) -> Result<u8, LoadError> {
+        self.bytes
+            .get(self.pos)
+            .copied()
+            .ok_or_else(|| invalid(self.pos, "unexpected end of data"))
+    }
+
+    fn u8(&mut self) -> Result<u8, LoadError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u64(&mut self) -> Result<u64, LoadError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_be_bytes(bytes))
+    }
+
+    fn varint(&mut self) -> Result<u64, LoadError> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(invalid(self.pos, "varint is too long"))
+    }
+
+    fn signed_varint(&mut self) -> Result<i64, LoadError> {
+        let mut value: i64 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            value |= ((byte & 0x7f) as i64) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    value |= -1 << shift;
+                }
+                return Ok(value);
+            }
+            if shift >= 64 {
+                return Err(invalid(self.pos, "varint is too long"));
+            }
+        }
+    }
+
+    fn c_string(&mut self) -> Result<String, LoadError> {
+        let rest = &self.bytes[self.pos.min(self.bytes.len())..];
+        let len = rest
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or_else(|| invalid(self.pos, "unterminated string"))?;
+        let string = String::from_utf8_lossy(&rest[..len]).into_owned();
+        self.pos += len + 1;
+        Ok(string)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn varints() {
+        let mut cursor = Cursor::new(&[0x05, 0xac, 0x02, 0x7f, 0x80, 0x7f], 0);
+        assert_eq!(cursor.varint().unwrap(), 5);
+        assert_eq!(cursor.varint().unwrap(), 300);
+        assert_eq!(cursor.signed_varint().unwrap(), -1);
+        assert_eq!(cursor.signed_varint().unwrap(), -128);
+        assert!(cursor.varint().is_err());
+    }
+
+    #[test]
+    fn timescales() {
+        assert_eq!(
+            timescale_from_exponent(-9),
+            Some(TimeScale::new(1, TimeUnit::NS))
+        );
+        assert_eq!(
+            timescale_from_exponent(-8),
+            Some(TimeScale::new(10, TimeUnit::NS))
+        );
+        assert_eq!(
+            timescale_from_exponent(2),
+            Some(TimeScale::new(100, TimeUnit::S))
+        );
+        assert_eq!(timescale_from_exponent(-16), None);
+    }
+
+    #[test]
+    fn offset_table_with_aliases() {
+        // handle 1 at 1, handles 2 and 3 without changes, handle 4 at 4, handle 5 aliases 1.
+        let chain = [0x03, 0x04, 0x07, 0x00, 0x01];
+        let locations = read_locations(&chain, 5, 0).unwrap();
+        assert_eq!(
+            resolve_locations(locations, 10, 0).unwrap(),
+            vec![Some((1, 3)), None, None, Some((4, 6)), Some((1, 3))]
+        );
+    }
+
+    #[test]
+    fn offset_table_alias2() {
+        // handle 1 at 1, handle 2 aliases 1, handle 3 aliases the same, handle 4 at 3.
+        let chain = [0x03, 0x7f, 0x01, 0x05];
+        let locations = read_locations_alias2(&chain, 4, 0).unwrap();
+        assert_eq!(
+            resolve_locations(locations, 8, 0).unwrap(),
+            vec![Some((1, 2)), Some((1, 2)), Some((1, 2)), Some((3, 5))]
+        );
+    }
+
+    #[test]
+    fn truncated_file_throws_error() {
+        assert_eq!(
+            read(&[0, 0, 0]).err(),
+            Some(LoadError::InvalidBinaryFile {
+                format: "FST".to_string(),
+                offset: 1,
+                reason: "unexpected end of data".to_string(),
+            })
+        );
+    }
+}
@@ -0,0 +1,756 @@
+use super::*;
+use crate::types::{
+    attribute::{Attribute, AttributeType, EnumTable, MiscKind},
+    scope::Scope,
+    timescale::TimeUnit,
+    value::Value,
+    variable::Variable,
+};
+use flate2::write::{GzEncoder, ZlibEncoder};
+use std::collections::{BTreeSet, HashMap};
+use std::io::{Error, ErrorKind, Write};
+
+/// How the value changes, time tables and hierarchy of an FST file are compressed.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Compression {
+    /// Nothing is compressed, except the hierarchy, which FST always compresses.
+    None,
+    /// zlib for the value changes, gzip for the hierarchy. Smallest files, and what GTKWave
+    /// writes by default.
+    Zlib,
+    /// LZ4 for the value changes and the hierarchy. Faster to write and read than zlib.
+    Lz4,
+}
+
+/// Options for writing FST files.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct WriteOptions {
+    /// The most time steps in one value change block. Readers decompress a whole block to get at
+    /// any value in it, so smaller blocks make seeking cheaper, and larger blocks compress better.
+    pub block_size: usize,
+    pub compression: Compression,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        WriteOptions {
+            block_size: 4096,
+            compression: Compression::Zlib,
+        }
+    }
+}
+
+pub fn write(vcd: &VCD, options: &WriteOptions) -> std::io::Result<Vec<u8>> {
+    let exponent = timescale_exponent(vcd)?;
+    let mut variables: Vec<&Variable> = vcd.variables.values().collect();
+    variables.sort_by_key(|variable| identifier_order(&variable.ascii_identifier));
+    let mut tree = ScopeTree::default();
+    for variable in variables.into_iter().chain(&vcd.aliases) {
+        tree.insert(&variable.scope, variable);
+    }
+
+    let mut hierarchy = HierarchyWriter::default();
+    hierarchy.write_enum_tables(&vcd.enum_tables);
+    hierarchy.write_tree(&tree);
+    // Aliases of identifiers no variable has have no declaration to take their signal from.
+    let declared = |variable: &&Variable| match vcd.variables.get(&variable.ascii_identifier) {
+        Some(declared) => signal_of(declared),
+        None => signal_of(variable),
+    };
+    let signals: Vec<Signal> = hierarchy.handles.iter().map(declared).collect();
+    let waveforms: Vec<&[(u64, Value)]> = hierarchy
+        .handles
+        .iter()
+        .map(
+            |variable| match vcd.waveforms.get(&variable.ascii_identifier) {
+                Some(waveform) => waveform.changes(),
+                None => &[],
+            },
+        )
+        .collect();
+
+    let times: Vec<u64> = waveforms
+        .iter()
+        .flat_map(|changes| changes.iter().map(|(time, _)| *time))
+        .collect::<BTreeSet<u64>>()
+        .into_iter()
+        .collect();
+    let blocks: Vec<&[u64]> = times.chunks(options.block_size.max(1)).collect();
+
+    let mut out = vec![];
+    out.extend(header(
+        vcd,
+        exponent,
+        &times,
+        &hierarchy,
+        signals.len(),
+        blocks.len(),
+    ));
+    let mut cursors = vec![0; signals.len()];
+    let mut current: Vec<Option<&Value>> = vec![None; signals.len()];
+    for block_times in &blocks {
+        out.extend(value_change_block(
+            block_times,
+            &signals,
+            &waveforms,
+            &mut cursors,
+            &mut current,
+            options.compression,
+        ));
+    }
+    out.extend(geometry(&signals, options.compression));
+    out.extend(hierarchy.finish(options.compression));
+    Ok(out)
+}
+
+/// Variables are put in the hierarchy, and so given handles, in the order of their identifiers,
+/// so that identifiers written by `fst2vcd`, or by this crate's FST reader, keep their handles.
+/// Only a variable in a scope that was already written with others comes earlier.
+fn identifier_order(identifier: &str) -> (u64, &str) {
+    (
+        handle_for_identifier(identifier).unwrap_or(u64::MAX),
+        identifier,
+    )
+}
+
+fn signal_of(variable: &Variable) -> Signal {
+    match variable.var_type {
+        VarType::Real | VarType::RealTime | VarType::RealParameter | VarType::ShortReal => {
+            Signal::Real
+        }
+        VarType::String => Signal::VariableLength,
+        _ => Signal::Bits(variable.bit_width.max(1)),
+    }
+}
+
+fn header(
+    vcd: &VCD,
+    exponent: i8,
+    times: &[u64],
+    hierarchy: &HierarchyWriter,
+    max_handle: usize,
+    block_count: usize,
+) -> Vec<u8> {
+    let mut out = vec![BLOCK_HEADER];
+    out.extend(&329u64.to_be_bytes());
+    out.extend(&times.first().copied().unwrap_or(0).to_be_bytes());
+    out.extend(&times.last().copied().unwrap_or(0).to_be_bytes());
+    out.extend(&ENDIAN_TEST.to_le_bytes());
+    // Memory the writer used, which readers ignore.
+    out.extend(&0u64.to_be_bytes());
+    out.extend(&hierarchy.scope_count.to_be_bytes());
+    out.extend(&hierarchy.var_count.to_be_bytes());
+    out.extend(&(max_handle as u64).to_be_bytes());
+    out.extend(&(block_count as u64).to_be_bytes());
+    out.push(exponent as u8);
+    out.extend(fixed_string(&vcd.version, 128));
+    out.extend(fixed_string(&vcd.date, 119));
+    // A Verilog file, with time starting at zero.
+    out.push(0);
+    out.extend(&0u64.to_be_bytes());
+    out
+}
+
+/// FST keeps the timescale as a power of ten, so its number has to be 1, 10 or 100. A VCD without
+/// a `$timescale` has a number of 0, and is written as 1.
+fn timescale_exponent(vcd: &VCD) -> std::io::Result<i8> {
+    let magnitude = match vcd.timescale.value {
+        0 | 1 => 0,
+        10 => 1,
+        100 => 2,
+        value => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "FST files can't hold a timescale of {} {}",
+                    value, vcd.timescale.unit
+                ),
+            ))
+        }
+    };
+    let unit = match vcd.timescale.unit {
+        TimeUnit::S => 0,
+        TimeUnit::MS => -3,
+        TimeUnit::US => -6,
+        TimeUnit::NS => -9,
+        TimeUnit::PS => -12,
+        TimeUnit::FS => -15,
+    };
+    Ok(unit + magnitude)
+}
+
+fn fixed_string(s: &str, len: usize) -> Vec<u8> {
+    let mut bytes: Vec<u8> = s.bytes().take(len - 1).collect();
+    bytes.resize(len, 0);
+    bytes
+}
+
+fn value_change_block<'a>(
+    times: &[u64],
+    signals: &[Signal],
+    waveforms: &[&'a [(u64, Value)]],
+    cursors: &mut [usize],
+    current: &mut [Option<&'a Value>],
+    compression: Compression,
+) -> Vec<u8> {
+    let begin = times[0];
+    let end = times[times.len() - 1];
+    let time_index: HashMap<u64, usize> = times
+        .iter()
+        .enumerate()
+        .map(|(index, time)| (*time, index))
+        .collect();
+
+    let frame: Vec<u8> = signals
+        .iter()
+        .zip(current.iter())
+        .flat_map(|(signal, value)| frame_value(*signal, *value))
+        .collect();
+
+    let mut data = vec![vec![]; signals.len()];
+    for (index, changes) in waveforms.iter().enumerate() {
+        let mut previous = 0;
+        for (time, value) in &changes[cursors[index]..] {
+            if *time > end {
+                break;
+            }
+            cursors[index] += 1;
+            let step = time_index[time];
+            if encode_change(signals[index], step - previous, value, &mut data[index]) {
+                current[index] = Some(value);
+                previous = step;
+            }
+        }
+    }
+
+    let pack_type = match compression {
+        Compression::Lz4 => b'4',
+        _ => b'Z',
+    };
+    let mut values = vec![pack_type];
+    let mut offsets = vec![];
+    let mut memory_required = 0;
+    for raw in &data {
+        if raw.is_empty() {
+            offsets.push(None);
+            continue;
+        }
+        offsets.push(Some(values.len()));
+        memory_required += raw.len();
+        let packed = match compression {
+            Compression::None => None,
+            Compression::Zlib => Some(deflate(raw)),
+            Compression::Lz4 => Some(lz4_flex::block::compress(raw)),
+        };
+        match packed {
+            Some(packed) if packed.len() < raw.len() => {
+                write_varint(&mut values, raw.len() as u64);
+                values.extend(packed);
+            }
+            _ => {
+                write_varint(&mut values, 0);
+                values.extend(raw);
+            }
+        }
+    }
+
+    let mut out = vec![];
+    out.extend(&begin.to_be_bytes());
+    out.extend(&end.to_be_bytes());
+    out.extend(&(memory_required as u64).to_be_bytes());
+    let packed_frame = compress_if_smaller(&frame, compression);
+    write_varint(&mut out, frame.len() as u64);
+    write_varint(&mut out, packed_frame.len() as u64);
+    write_varint(&mut out, signals.len() as u64);
+    out.extend(packed_frame);
+    write_varint(&mut out, signals.len() as u64);
+    out.extend(values);
+    let chain = offset_table(&offsets, signals.len());
+    out.extend(&chain);
+    out.extend(&(chain.len() as u64).to_be_bytes());
+    let mut time_table = vec![];
+    let mut previous = 0;
+    for time in times {
+        write_varint(&mut time_table, time - previous);
+        previous = *time;
+    }
+    let packed_times = compress_if_smaller(&time_table, compression);
+    out.extend(&packed_times);
+    out.extend(&(time_table.len() as u64).to_be_bytes());
+    out.extend(&(packed_times.len() as u64).to_be_bytes());
+    out.extend(&(times.len() as u64).to_be_bytes());
+    block(BLOCK_VALUE_CHANGES_ALIAS2, out)
+}
+
+/// The value of a signal at the start of a block, as it is stored in the frame. Signals without
+/// a value yet are `x`.
+fn frame_value(signal: Signal, value: Option<&Value>) -> Vec<u8> {
+    match (signal, value) {
+        (Signal::Bits(width), Some(value)) => match bit_chars(value, width) {
+            Some(chars) => chars,
+            None => vec![b'x'; width],
+        },
+        (Signal::Bits(width), None) => vec![b'x'; width],
+        (Signal::Real, Some(Value::Real(real))) => real.to_le_bytes().to_vec(),
+        (Signal::Real, _) => f64::NAN.to_le_bytes().to_vec(),
+        (Signal::VariableLength, _) => vec![],
+    }
+}
+
+/// The value of a bit signal as `width` characters, extended or cut to fit.
+fn bit_chars(value: &Value, width: usize) -> Option<Vec<u8>> {
+    let bits: Vec<u8> = match value {
+        Value::Scalar(bit) => vec![bit.to_char() as u8],
+        Value::Vector(bits) => bits.iter().map(|bit| bit.to_char() as u8).collect(),
+        Value::Port(ports) => ports
+            .iter()
+            .map(|port| port.state.to_bit().to_char() as u8)
+            .collect(),
+        Value::Real(_) => return None,
+    };
+    let fill = match bits.first() {
+        Some(b'x') => b'x',
+        Some(b'z') => b'z',
+        _ => b'0',
+    };
+    let mut chars = vec![fill; width.saturating_sub(bits.len())];
+    chars.extend(&bits[bits.len().saturating_sub(width)..]);
+    Some(chars)
+}
+
+/// Appends one value change, `delta` time steps after the previous one, to the value changes of
+/// a signal. Values that don't fit the signal, like a real value of a vector, are left out, and
+/// `false` is returned for them.
+fn encode_change(signal: Signal, delta: usize, value: &Value, out: &mut Vec<u8>) -> bool {
+    let delta = delta as u64;
+    match (signal, value) {
+        (Signal::Bits(1), value) => {
+            let c = match bit_chars(value, 1) {
+                Some(chars) => chars[0],
+                None => return false,
+            };
+            match c {
+                b'0' | b'1' => write_varint(out, (delta << 2) | (((c - b'0') as u64) << 1)),
+                _ => {
+                    let state = ONE_BIT_STATES.iter().position(|s| *s == c).unwrap() as u64;
+                    write_varint(out, (delta << 4) | (state << 1) | 1)
+                }
+            }
+        }
+        (Signal::Bits(width), value) => {
+            let chars = match bit_chars(value, width) {
+                Some(chars) => chars,
+                None => return false,
+            };
+            if chars.iter().all(|c| *c == b'0' || *c == b'1') {
+                // Two state values are packed eight bits to a byte.
+                write_varint(out, delta << 1);
+                let mut packed = vec![0u8; width.div_ceil(8)];
+                for (bit, c) in chars.iter().enumerate() {
+                    packed[bit / 8] |= (c - b'0') << (7 - bit % 8);
+                }
+                out.extend(packed);
+            } else {
+                write_varint(out, (delta << 1) | 1);
+                out.extend(chars);
+            }
+        }
+        (Signal::Real, Value::Real(real)) => {
+            write_varint(out, (delta << 1) | 1);
+            out.extend(&real.to_le_bytes());
+        }
+        _ => return false,
+    }
+    true
+}
+
+/// The offset table of a value change block: where each handle's value changes start, relative
+/// to the pack type, with runs of handles that have none collapsed.
+fn offset_table(offsets: &[Option<usize>], max_handle: usize) -> Vec<u8> {
+    let mut out = vec![];
+    let mut previous = 0;
+    let mut skipped = 0;
+    for offset in offsets
+        .iter()
+        .chain(std::iter::repeat_n(&None, max_handle - offsets.len()))
+    {
+        match offset {
+            Some(offset) => {
+                if skipped > 0 {
+                    write_varint(&mut out, skipped << 1);
+                    skipped = 0;
+                }
+                write_signed_varint(&mut out, (((offset - previous) as i64) << 1) | 1);
+                previous = *offset;
+            }
+            None => skipped += 1,
+        }
+    }
+    if skipped > 0 {
+        write_varint(&mut out, skipped << 1);
+    }
+    out
+}
+
+fn geometry(signals: &[Signal], compression: Compression) -> Vec<u8> {
+    let mut lengths = vec![];
+    for signal in signals {
+        let len = match signal {
+            Signal::Bits(len) => *len as u64,
+            Signal::Real => 0,
+            Signal::VariableLength => VARIABLE_LENGTH,
+        };
+        write_varint(&mut lengths, len);
+    }
+    let mut out = vec![];
+    out.extend(&(lengths.len() as u64).to_be_bytes());
+    out.extend(&(signals.len() as u64).to_be_bytes());
+    out.extend(compress_if_smaller(&lengths, compression));
+    block(BLOCK_GEOMETRY, out)
+}
+
+/// The variables of a VCD by scope, in the order they were inserted, so that each scope is
+/// written once even when its variables aren't next to each other in handle order.
+#[derive(Default)]
+struct ScopeTree<'a> {
+    items: Vec<TreeItem<'a>>,
+    /// Where each child scope is in `items`, by identifier.
+    children: HashMap<&'a str, usize>,
+}
+
+enum TreeItem<'a> {
+    Var(&'a Variable),
+    Scope(&'a Scope, ScopeTree<'a>),
+}
+
+impl<'a> ScopeTree<'a> {
+    fn insert(&mut self, path: &'a [Scope], variable: &'a Variable) {
+        let (scope, rest) = match path.split_first() {
+            Some(split) => split,
+            None => return self.items.push(TreeItem::Var(variable)),
+        };
+        let items = &mut self.items;
+        let index = *self
+            .children
+            .entry(scope.identifier.as_str())
+            .or_insert_with(|| {
+                items.push(TreeItem::Scope(scope, ScopeTree::default()));
+                items.len() - 1
+            });
+        if let TreeItem::Scope(_, child) = &mut self.items[index] {
+            child.insert(rest, variable);
+        }
+    }
+}
+
+/// Writes the scopes, variables and attributes, each scope once with everything declared in it.
+#[derive(Default)]
+struct HierarchyWriter<'a> {
+    out: Vec<u8>,
+    path_ids: HashMap<&'a str, u64>,
+    /// The first variable written with each handle; later ones with its identifier are aliases.
+    handles: Vec<&'a Variable>,
+    handle_of: HashMap<&'a str, u64>,
+    scope_count: u64,
+    var_count: u64,
+}
+
+impl<'a> HierarchyWriter<'a> {
+    fn write_enum_tables(&mut self, enum_tables: &HashMap<u64, EnumTable>) {
+        let mut handles: Vec<&u64> = enum_tables.keys().collect();
+        handles.sort();
+        for handle in handles {
+            let table = &enum_tables[handle];
+            let names = table.entries.iter().map(|(_, name)| name.clone());
+            let values = table.entries.iter().map(|(value, _)| {
+                let width = match value {
+                    Value::Vector(bits) => bits.len(),
+                    _ => 1,
+                };
+                String::from_utf8(bit_chars(value, width).unwrap_or_default()).unwrap()
+            });
+            let name = std::iter::once(table.name.clone())
+                .chain(std::iter::once(table.entries.len().to_string()))
+                .chain(names)
+                .chain(values)
+                .collect::<Vec<String>>()
+                .join(" ");
+            let table = Attribute::new(AttributeType::Misc, 7, name, *handle);
+            self.write_attribute_entry(&table, None);
+        }
+    }
+
+    fn write_tree(&mut self, tree: &ScopeTree<'a>) {
+        for item in &tree.items {
+            match item {
+                TreeItem::Var(variable) => self.write_var(variable),
+                TreeItem::Scope(scope, child) => {
+                    for attribute in &scope.attributes {
+                        self.write_attribute(attribute);
+                    }
+                    self.out.push(HIERARCHY_SCOPE);
+                    self.out.push(scope_type_to_fst(&scope.scope_type));
+                    write_c_string(&mut self.out, &scope.identifier);
+                    write_c_string(&mut self.out, "");
+                    self.scope_count += 1;
+                    self.write_tree(child);
+                    self.out.push(HIERARCHY_UPSCOPE);
+                }
+            }
+        }
+    }
+
+    /// Writes a variable, with a handle of its own if it's the first with its identifier.
+    fn write_var(&mut self, variable: &'a Variable) {
+        let alias = match self.handle_of.get(variable.ascii_identifier.as_str()) {
+            Some(handle) => *handle,
+            None => {
+                self.handles.push(variable);
+                let handle = self.handles.len() as u64;
+                self.handle_of.insert(&variable.ascii_identifier, handle);
+                0
+            }
+        };
+        for attribute in &variable.attributes {
+            self.write_attribute(attribute);
+        }
+        self.out.push(var_type_to_fst(&variable.var_type));
+        self.out.push(direction_to_fst(variable.direction));
+        write_c_string(&mut self.out, &variable.reference);
+        let len = match variable.var_type {
+            VarType::Port => variable.bit_width as u64 * 3 + 2,
+            _ => variable.bit_width as u64,
+        };
+        write_varint(&mut self.out, len);
+        write_varint(&mut self.out, alias);
+        self.var_count += 1;
+    }
+
+    fn write_attribute(&mut self, attribute: &'a Attribute) {
+        let path_id = match attribute.misc_kind() {
+            // Source locations refer to their file by the id of a path name attribute.
+            Some(MiscKind::SourceStem) | Some(MiscKind::SourceInstantiationStem) => {
+                let next_id = self.path_ids.len() as u64 + 1;
+                let path_id = *self.path_ids.entry(&attribute.name).or_insert(next_id);
+                if path_id == next_id {
+                    let path =
+                        Attribute::new(AttributeType::Misc, 3, attribute.name.clone(), path_id);
+                    self.write_attribute_entry(&path, None);
+                }
+                Some(path_id)
+            }
+            _ => None,
+        };
+        self.write_attribute_entry(attribute, path_id);
+    }
+
+    fn write_attribute_entry(&mut self, attribute: &Attribute, path_id: Option<u64>) {
+        self.out.push(HIERARCHY_ATTRIBUTE_BEGIN);
+        self.out.push(match attribute.attribute_type {
+            AttributeType::Misc => 0,
+            AttributeType::Array => 1,
+            AttributeType::Enum => 2,
+            AttributeType::Class => 3,
+        });
+        self.out.push(attribute.subtype as u8);
+        match path_id {
+            Some(path_id) => {
+                write_varint(&mut self.out, path_id);
+                self.out.push(0);
+            }
+            None => write_c_string(&mut self.out, &attribute.name),
+        }
+        write_varint(&mut self.out, attribute.argument);
+    }
+
+    fn finish(self, compression: Compression) -> Vec<u8> {
+        let mut out = vec![];
+        out.extend(&(self.out.len() as u64).to_be_bytes());
+        match compression {
+            Compression::Lz4 => {
+                out.extend(lz4_flex::block::compress(&self.out));
+                block(BLOCK_HIERARCHY_LZ4, out)
+            }
+            _ => {
+                let level = match compression {
+                    Compression::None => flate2::Compression::none(),
+                    _ => flate2::Compression::default(),
+                };
+                let mut encoder = GzEncoder::new(out, level);
+                encoder.write_all(&self.out).unwrap();
+                block(BLOCK_HIERARCHY, encoder.finish().unwrap())
+            }
+        }
+    }
+}
+
+/// Prefixes `contents` with the block type and the section length, which counts itself.
+fn block(block_type: u8, contents: Vec<u8>) -> Vec<u8> {
+    let mut out = vec![block_type];
+    out.extend(&(contents.len() as u64 + 8).to_be_bytes());
+    out.extend(contents);
+    out
+}
+
+/// zlib compresses `data`, unless that doesn't make it smaller. Readers tell the two apart by
+/// comparing the length with the uncompressed length.
+fn compress_if_smaller(data: &[u8], compression: Compression) -> Vec<u8> {
+    if compression != Compression::None {
+        let compressed = deflate(data);
+        if compressed.len() < data.len() {
+            return compressed;
+        }
+    }
+    data.to_vec()
+}
+
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(vec![], flate2::Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn write_signed_varint(out: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn write_c_string(out: &mut Vec<u8>, s: &str) {
+    out.extend(s.bytes().filter(|b| *b != 0));
+    out.push(0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::value::Bit;
+
+    #[test]
+    fn varints() {
+        let mut out = vec![];
+        write_varint(&mut out, 5);
+        write_varint(&mut out, 300);
+        write_signed_varint(&mut out, -1);
+        write_signed_varint(&mut out, -128);
+        write_signed_varint(&mut out, 64);
+        assert_eq!(out, vec![0x05, 0xac, 0x02, 0x7f, 0x80, 0x7f, 0xc0, 0x00]);
+    }
+
+    #[test]
+    fn bit_values_are_fitted_to_the_signal() {
+        use Bit::*;
+        assert_eq!(
+            bit_chars(&Value::Vector(vec![One, Zero]), 4),
+            Some(b"0010".to_vec())
+        );
+        assert_eq!(bit_chars(&Value::Scalar(Z), 3), Some(b"zzz".to_vec()));
+        assert_eq!(
+            bit_chars(&Value::Vector(vec![One, Zero, X]), 2),
+            Some(b"0x".to_vec())
+        );
+        assert_eq!(bit_chars(&Value::Real(1.0), 2), None);
+    }
+
+    #[test]
+    fn encode_value_changes() {
+        use Bit::*;
+        let mut out = vec![];
+        assert!(encode_change(
+            Signal::Bits(1),
+            2,
+            &Value::Scalar(One),
+            &mut out
+        ));
+        assert!(encode_change(
+            Signal::Bits(1),
+            1,
+            &Value::Scalar(Z),
+            &mut out
+        ));
+        assert!(encode_change(
+            Signal::Bits(3),
+            0,
+            &Value::Vector(vec![One, Zero, One]),
+            &mut out
+        ));
+        assert!(encode_change(
+            Signal::Bits(2),
+            1,
+            &Value::Vector(vec![X, One]),
+            &mut out
+        ));
+        assert!(!encode_change(
+            Signal::Real,
+            1,
+            &Value::Scalar(One),
+            &mut out
+        ));
+        assert_eq!(out, vec![0x0a, 0x13, 0x00, 0b1010_0000, 0x03, b'x', b'1']);
+    }
+
+    #[test]
+    fn offset_table_skips_handles_without_changes() {
+        let table = offset_table(&[Some(1), None, None, Some(4)], 5);
+        assert_eq!(table, vec![0x03, 0x04, 0x07, 0x02]);
+    }
+
+    #[test]
+    fn scopes_are_written_once() {
+        let vcd = crate::load_from_str(
+            "$scope module top $end $scope module a $end $var wire 1 ! x $end $upscope $end \
+             $scope module b $end $var wire 1 \" y $end $upscope $end \
+             $scope module a $end $var wire 1 # z $end $upscope $end $upscope $end \
+             $enddefinitions $end #0 0! 1\" 1#",
+        )
+        .unwrap();
+        use std::convert::TryInto;
+        let fst = write(&vcd, &WriteOptions::default()).unwrap();
+        let scope_count = u64::from_be_bytes(fst[41..49].try_into().unwrap());
+        assert_eq!(scope_count, 3);
+
+        let loaded = crate::fst::load_from_bytes(&fst).unwrap();
+        let mut references: Vec<(&str, &str, &str)> = loaded
+            .variables
+            .values()
+            .map(|variable| {
+                let scope = variable.scope[1].identifier.as_str();
+                let identifier = variable.ascii_identifier.as_str();
+                (identifier, scope, variable.reference.as_str())
+            })
+            .collect();
+        references.sort();
+        let expected = vec![("!", "a", "x"), ("\"", "a", "z"), ("#", "b", "y")];
+        assert_eq!(references, expected);
+        assert_eq!(loaded.waveforms["\""], vcd.waveforms["#"]);
+    }
+
+    #[test]
+    fn timescale_must_be_a_power_of_ten() {
+        for (magnitude, exponent) in &[(1, -9), (10, -8), (100, -7)] {
+            let text = format!("$timescale {} ns $end $enddefinitions $end", magnitude);
+            let vcd = crate::load_from_str(&text).unwrap();
+            assert_eq!(timescale_exponent(&vcd).unwrap(), *exponent);
+            let fst = write(&vcd, &WriteOptions::default()).unwrap();
+            let loaded = crate::fst::load_from_bytes(&fst).unwrap();
+            assert_eq!(loaded.timescale, vcd.timescale);
+        }
+        let vcd = crate::load_from_str("$timescale 42 ns $end $enddefinitions $end").unwrap();
+        let err = write(&vcd, &WriteOptions::default()).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,1128 @@
+//! Reading GHDL's GHW format into the same [`VCD`] model the VCD parser fills.
+//!
+//! GHW keeps the VHDL types of signals. A record becomes a [`ScopeType::VhdlRecord`] scope holding
+//! its fields, an array of `std_ulogic` or `bit` becomes a single vector and any other array is
+//! split into its elements, named `name[index]`. Enums other than `std_ulogic` and `bit` get an
+//! enum table with their literals, so their values can be shown by name. Every variable carries
+//! the name of its VHDL type in a supplemental variable attribute.
+//!
+//! The model only has four-state bits, so `std_ulogic` values are mapped the way GHDL's VCD writer
+//! maps them: `L` and `H` become `0` and `1`, `Z` stays `Z` and the others become `X`. GHW has no
+//! identifier codes, so each variable gets the code GTKWave's `fst2vcd` would give the n-th
+//! variable. Times are in femtoseconds.
+
+use crate::error::LoadError;
+use crate::string_helpers::identifier_for_handle;
+use crate::types::{
+    attribute::{Attribute, AttributeType, EnumTable},
+    scope::{Scope, ScopeType},
+    timescale::{TimeScale, TimeUnit},
+    value::{Bit, Value},
+    variable::{VarType, VariableBuilder},
+};
+use crate::vcd::VCD;
+use std::collections::HashMap;
+
+/// Loads a GHW file. Errors are wrapped in a [`LoadError::InFile`] naming the file.
+pub fn load_from_file(filename: String) -> Result<VCD, LoadError> {
+    let bytes = std::fs::read(&filename).map_err(|err| LoadError::FileOpenError {
+        filename: filename.clone(),
+        error: err.to_string(),
+    })?;
+    load_from_bytes(&bytes).map_err(|err| err.in_file(&filename))
+}
+
+/// Loads GHW data that is already in memory.
+pub fn load_from_bytes(bytes: &[u8]) -> Result<VCD, LoadError> {
+    GhwReader::new(bytes)?.read()
+}
+
+const MAGIC: &[u8] = b"GHDLwave\n";
+const HEADER_LEN: usize = 16;
+
+const SECTION_STRINGS: &[u8] = b"STR\0";
+const SECTION_END_OF_STRINGS: &[u8] = b"EOS\0";
+const SECTION_TYPES: &[u8] = b"TYP\0";
+const SECTION_WELL_KNOWN_TYPES: &[u8] = b"WKT\0";
+const SECTION_HIERARCHY: &[u8] = b"HIE\0";
+const SECTION_END_OF_HEADER: &[u8] = b"EOH\0";
+const SECTION_SNAPSHOT: &[u8] = b"SNP\0";
+const SECTION_END_OF_SNAPSHOT: &[u8] = b"ESN\0";
+const SECTION_CYCLE: &[u8] = b"CYC\0";
+const SECTION_END_OF_CYCLE: &[u8] = b"ECY\0";
+const SECTION_DIRECTORY: &[u8] = b"DIR\0";
+const SECTION_END_OF_DIRECTORY: &[u8] = b"EOD\0";
+const SECTION_TAILER: &[u8] = b"TAI\0";
+
+const TYPE_B2: u8 = 22;
+const TYPE_E8: u8 = 23;
+const TYPE_I32: u8 = 25;
+const TYPE_I64: u8 = 26;
+const TYPE_F64: u8 = 27;
+const TYPE_P32: u8 = 28;
+const TYPE_P64: u8 = 29;
+const TYPE_ARRAY: u8 = 31;
+const TYPE_RECORD: u8 = 32;
+const SUBTYPE_SCALAR: u8 = 34;
+const SUBTYPE_ARRAY: u8 = 35;
+const SUBTYPE_UNBOUNDED_ARRAY: u8 = 37;
+const SUBTYPE_RECORD: u8 = 38;
+const SUBTYPE_UNBOUNDED_RECORD: u8 = 39;
+
+const HIERARCHY_END: u8 = 0;
+const HIERARCHY_BLOCK: u8 = 3;
+const HIERARCHY_GENERATE_IF: u8 = 4;
+const HIERARCHY_GENERATE_FOR: u8 = 5;
+const HIERARCHY_INSTANCE: u8 = 6;
+const HIERARCHY_PACKAGE: u8 = 7;
+const HIERARCHY_PROCESS: u8 = 13;
+const HIERARCHY_GENERIC: u8 = 14;
+const HIERARCHY_END_OF_SCOPE: u8 = 15;
+const HIERARCHY_SIGNAL: u8 = 16;
+const HIERARCHY_LINKAGE: u8 = 21;
+
+/// The bits `std_ulogic`'s literals `U X 0 1 Z W L H -` map to, in that order.
+const STD_ULOGIC_BITS: [Bit; 9] = [
+    Bit::X,
+    Bit::X,
+    Bit::Zero,
+    Bit::One,
+    Bit::Z,
+    Bit::X,
+    Bit::Zero,
+    Bit::One,
+    Bit::X,
+];
+
+/// A VHDL type, with the bounds of its subtype applied.
+#[derive(Debug, Clone)]
+struct Type {
+    name: String,
+    kind: Kind,
+}
+
+#[derive(Debug, Clone)]
+enum Kind {
+    /// `std_ulogic` and its subtypes, like `std_logic`.
+    StdULogic,
+    Bit,
+    /// Any other enum. Its values are the positions of its literals, `width` bits wide, and
+    /// `table` is the handle of the enum table naming them.
+    Enum {
+        width: usize,
+        table: u64,
+    },
+    Integer {
+        width: usize,
+    },
+    Real,
+    /// An array with the index range of each dimension, once a subtype bounds it. `element` is
+    /// the element type as bounded by the subtype, `unbounded_element` that of the array type
+    /// itself, which subtypes put their bounds on.
+    Array {
+        element: Box<Type>,
+        unbounded_element: Box<Type>,
+        dims: Vec<Option<Range>>,
+    },
+    Record(Vec<(String, Type)>),
+}
+
+impl Type {
+    fn is_bounded(&self) -> bool {
+        match &self.kind {
+            Kind::Array { element, dims, .. } => {
+                dims.iter().all(Option::is_some) && element.is_bounded()
+            }
+            Kind::Record(fields) => fields.iter().all(|(_, field)| field.is_bounded()),
+            _ => true,
+        }
+    }
+
+    fn encoding(&self) -> Option<Encoding> {
+        match self.kind {
+            Kind::StdULogic | Kind::Bit | Kind::Enum { .. } => Some(Encoding::Byte),
+            Kind::Integer { .. } => Some(Encoding::Integer),
+            Kind::Real => Some(Encoding::Real),
+            Kind::Array { .. } | Kind::Record(_) => None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct Range {
+    left: i64,
+    right: i64,
+    downto: bool,
+}
+
+impl Range {
+    /// The number of indices. Ranges are checked when they are read, so it doesn't overflow.
+    fn len(&self) -> i64 {
+        match self.downto {
+            true => self.left - self.right + 1,
+            false => self.right - self.left + 1,
+        }
+        .max(0)
+    }
+
+    /// The indices of the range, from left to right.
+    fn indices(self) -> impl Iterator<Item = i64> {
+        (0..self.len()).map(move |i| match self.downto {
+            true => self.left - i,
+            false => self.left + i,
+        })
+    }
+}
+
+/// How the value of a scalar signal is written.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Encoding {
+    /// The position of an enum literal, as a byte.
+    Byte,
+    /// A signed LEB128 integer.
+    Integer,
+    /// A little endian `f64`.
+    Real,
+}
+
+/// The value of a scalar signal: an enum position or integer, or a real.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Sample {
+    Integer(i64),
+    Real(f64),
+}
+
+/// A variable of the model, and the GHW signals its value is made of, most significant first.
+struct Leaf {
+    identifier: String,
+    kind: LeafKind,
+    signals: Vec<usize>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum LeafKind {
+    StdULogic,
+    Bit,
+    Enum(usize),
+    Integer(usize),
+    Real,
+}
+
+struct GhwReader<'a> {
+    bytes: &'a [u8],
+    little_endian: bool,
+    version: u8,
+    vcd: VCD,
+    strings: Vec<String>,
+    types: Vec<Type>,
+    scope_stack: Vec<Scope>,
+    /// The encoding of each signal, by signal id minus one.
+    encodings: Vec<Option<Encoding>>,
+    values: Vec<Option<Sample>>,
+    leaves: Vec<Leaf>,
+    leaves_by_signals: HashMap<Vec<usize>, usize>,
+    /// The leaves each signal is part of.
+    listeners: Vec<Vec<usize>>,
+    changed: Vec<usize>,
+}
+
+impl<'a> GhwReader<'a> {
+    fn new(bytes: &'a [u8]) -> Result<Self, LoadError> {
+        if !bytes.starts_with(MAGIC) {
+            return Err(invalid(0, "not a GHW file"));
+        }
+        let header = bytes
+            .get(MAGIC.len()..HEADER_LEN)
+            .ok_or_else(|| invalid(MAGIC.len(), "unexpected end of data"))?;
+        let version = header[2];
+        if header[0] != 16 || header[1] != 0 || version > 1 || header[6] != 0 {
+            return Err(invalid(MAGIC.len(), "unsupported header"));
+        }
+        let little_endian = match header[3] {
+            1 => true,
+            2 => false,
+            _ => return Err(invalid(MAGIC.len() + 3, "unknown byte order")),
+        };
+        let vcd = VCD {
+            timescale: TimeScale::new(1, TimeUnit::FS),
+            ..VCD::default()
+        };
+        Ok(GhwReader {
+            bytes,
+            little_endian,
+            version,
+            vcd,
+            strings: vec![String::new()],
+            types: vec![],
+            scope_stack: vec![],
+            encodings: vec![],
+            values: vec![],
+            leaves: vec![],
+            leaves_by_signals: HashMap::new(),
+            listeners: vec![],
+            changed: vec![],
+        })
+    }
+
+    fn read(mut self) -> Result<VCD, LoadError> {
+        let mut cursor = Cursor::new(self.bytes, HEADER_LEN, self.little_endian);
+        loop {
+            let start = cursor.pos;
+            match cursor.take(4)? {
+                SECTION_STRINGS => self.read_strings(&mut cursor)?,
+                SECTION_TYPES => self.read_types(&mut cursor)?,
+                SECTION_WELL_KNOWN_TYPES => read_well_known_types(&mut cursor)?,
+                SECTION_HIERARCHY => self.read_hierarchy(&mut cursor)?,
+                SECTION_END_OF_HEADER => break,
+                _ => return Err(invalid(start, "unknown section")),
+            }
+        }
+        // A simulation that was stopped leaves a file without a tailer, so the end of the data
+        // also ends the value changes.
+        while !cursor.at_end() {
+            let start = cursor.pos;
+            match cursor.take(4)? {
+                SECTION_SNAPSHOT => self.read_snapshot(&mut cursor)?,
+                SECTION_CYCLE => self.read_cycle(&mut cursor)?,
+                SECTION_DIRECTORY => read_directory(&mut cursor)?,
+                SECTION_TAILER => break,
+                _ => return Err(invalid(start, "unknown section")),
+            }
+        }
+        Ok(self.vcd)
+    }
+
+    /// Reads the string table. Each string is followed by a byte, continued in more bytes if it
+    /// has its high bit set, giving the length of the prefix the next string shares with it.
+    fn read_strings(&mut self, cursor: &mut Cursor) -> Result<(), LoadError> {
+        cursor.skip(4)?;
+        let count = cursor.u32()?;
+        let _total_len = cursor.u32()?;
+        let mut string = vec![];
+        for _ in 0..count {
+            let mut c = cursor.u8()?;
+            while c > 31 && !(128..=159).contains(&c) {
+                string.push(c);
+                c = cursor.u8()?;
+            }
+            self.strings
+                .push(String::from_utf8_lossy(&string).into_owned());
+            let mut prefix_len = (c & 0x1f) as usize;
+            let mut shift = 5;
+            while c >= 128 {
+                if shift > 25 {
+                    return Err(invalid(cursor.pos, "string prefix is too long"));
+                }
+                c = cursor.u8()?;
+                prefix_len |= ((c & 0x1f) as usize) << shift;
+                shift += 5;
+            }
+            string.truncate(prefix_len);
+        }
+        expect_section_end(cursor, SECTION_END_OF_STRINGS)
+    }
+
+    fn read_types(&mut self, cursor: &mut Cursor) -> Result<(), LoadError> {
+        cursor.skip(4)?;
+        let count = cursor.u32()?;
+        for _ in 0..count {
+            let start = cursor.pos;
+            let kind = cursor.u8()?;
+            let name = self.string(cursor)?;
+            let new_type = match kind {
+                TYPE_B2 | TYPE_E8 => {
+                    let literal_count = cursor.varint()?;
+                    let literals = (0..literal_count)
+                        .map(|_| self.string(cursor))
+                        .collect::<Result<Vec<String>, LoadError>>()?;
+                    self.enum_type(name, literals)
+                }
+                TYPE_I32 | TYPE_I64 | TYPE_P32 | TYPE_P64 => {
+                    if (kind == TYPE_P32 || kind == TYPE_P64) && self.version > 0 {
+                        for _ in 0..cursor.varint()? {
+                            let _unit_name = self.string(cursor)?;
+                            let _unit_value = cursor.signed_varint()?;
+                        }
+                    }
+                    let width = match kind {
+                        TYPE_I32 | TYPE_P32 => 32,
+                        _ => 64,
+                    };
+                    Type {
+                        name,
+                        kind: Kind::Integer { width },
+                    }
+                }
+                TYPE_F64 => Type {
+                    name,
+                    kind: Kind::Real,
+                },
+                SUBTYPE_SCALAR => {
+                    let base = self.type_ref(cursor)?.clone();
+                    read_range(cursor)?;
+                    named(base, name)
+                }
+                TYPE_ARRAY => {
+                    let element = Box::new(self.type_ref(cursor)?.clone());
+                    let dim_count = cursor.varint()?;
+                    if dim_count == 0 {
+                        return Err(invalid(start, "array has no dimensions"));
+                    }
+                    for _ in 0..dim_count {
+                        self.type_ref(cursor)?;
+                    }
+                    Type {
+                        name,
+                        kind: Kind::Array {
+                            unbounded_element: element.clone(),
+                            element,
+                            dims: vec![None; dim_count as usize],
+                        },
+                    }
+                }
+                TYPE_RECORD => {
+                    let field_count = cursor.varint()?;
+                    let fields = (0..field_count)
+                        .map(|_| Ok((self.string(cursor)?, self.type_ref(cursor)?.clone())))
+                        .collect::<Result<Vec<(String, Type)>, LoadError>>()?;
+                    Type {
+                        name,
+                        kind: Kind::Record(fields),
+                    }
+                }
+                SUBTYPE_ARRAY | SUBTYPE_RECORD => {
+                    let base = self.type_ref(cursor)?.clone();
+                    named(bounded(base, cursor)?, name)
+                }
+                SUBTYPE_UNBOUNDED_ARRAY | SUBTYPE_UNBOUNDED_RECORD => {
+                    named(self.type_ref(cursor)?.clone(), name)
+                }
+                _ => return Err(invalid(start, "unknown type kind")),
+            };
+            self.types.push(new_type);
+        }
+        match cursor.u8()? {
+            0 => Ok(()),
+            _ => Err(invalid(cursor.pos - 1, "type section is not terminated")),
+        }
+    }
+
+    /// Makes the type of an enum. Enums with the literals of `std_ulogic` or `bit` hold bits,
+    /// other enums get an enum table.
+    fn enum_type(&mut self, name: String, literals: Vec<String>) -> Type {
+        let kind = if has_literals(&literals, "UX01ZWLH-") {
+            Kind::StdULogic
+        } else if has_literals(&literals, "01") {
+            Kind::Bit
+        } else {
+            let width = enum_width(literals.len());
+            let table = self.vcd.enum_tables.len() as u64 + 1;
+            let entries = literals
+                .into_iter()
+                .enumerate()
+                .map(|(position, literal)| {
+                    (Value::Vector(int_bits(position as i64, width)), literal)
+                })
+                .collect();
+            self.vcd.enum_tables.insert(
+                table,
+                EnumTable {
+                    name: name.clone(),
+                    entries,
+                },
+            );
+            Kind::Enum { width, table }
+        };
+        Type { name, kind }
+    }
+
+    fn read_hierarchy(&mut self, cursor: &mut Cursor) -> Result<(), LoadError> {
+        cursor.skip(4)?;
+        let _scope_count = cursor.u32()?;
+        let _signal_declaration_count = cursor.u32()?;
+        let signal_count = cursor.u32()? as usize;
+        // Every signal is declared in the rest of the hierarchy, which takes at least a byte.
+        if signal_count > cursor.remaining() {
+            return Err(invalid(cursor.pos - 4, "more signals than declared"));
+        }
+        self.encodings = vec![None; signal_count];
+        self.values = vec![None; signal_count];
+        self.listeners = vec![vec![]; signal_count];
+        loop {
+            let start = cursor.pos;
+            match cursor.u8()? {
+                HIERARCHY_END => return Ok(()),
+                HIERARCHY_END_OF_SCOPE => {
+                    self.scope_stack.pop();
+                }
+                // Processes have no signals of their own, so they are left out of the scopes.
+                HIERARCHY_PROCESS => {
+                    self.string(cursor)?;
+                }
+                kind @ HIERARCHY_BLOCK..=HIERARCHY_PACKAGE | kind @ HIERARCHY_GENERIC => {
+                    self.read_scope(kind, cursor)?
+                }
+                HIERARCHY_SIGNAL..=HIERARCHY_LINKAGE => {
+                    let name = self.string(cursor)?;
+                    let signal_type = self.type_ref(cursor)?.clone();
+                    self.read_signal(name, &signal_type, cursor)?;
+                }
+                _ => return Err(invalid(start, "unknown hierarchy entry")),
+            }
+        }
+    }
+
+    fn read_scope(&mut self, kind: u8, cursor: &mut Cursor) -> Result<(), LoadError> {
+        let mut name = self.string(cursor)?;
+        let scope_type = match kind {
+            HIERARCHY_BLOCK | HIERARCHY_GENERIC => ScopeType::VhdlBlock,
+            HIERARCHY_GENERATE_IF => ScopeType::VhdlIfGenerate,
+            HIERARCHY_GENERATE_FOR => ScopeType::VhdlForGenerate,
+            HIERARCHY_INSTANCE => ScopeType::VhdlArchitecture,
+            _ => ScopeType::VhdlPackage,
+        };
+        if kind == HIERARCHY_GENERATE_FOR {
+            // Each iteration of a for generate is a scope named after its parameter's value.
+            let parameter_type = self.type_ref(cursor)?.clone();
+            let start = cursor.pos;
+            let encoding = parameter_type
+                .encoding()
+                .ok_or_else(|| invalid(start, "generate parameter is not a scalar"))?;
+            let value = match (read_sample(cursor, encoding)?, &parameter_type.kind) {
+                (Sample::Integer(position), Kind::Enum { table, .. }) => self.vcd.enum_tables
+                    [table]
+                    .entries
+                    .get(position as usize)
+                    .map(|(_, literal)| literal.clone())
+                    .unwrap_or_else(|| position.to_string()),
+                (Sample::Integer(value), _) => value.to_string(),
+                (Sample::Real(value), _) => value.to_string(),
+            };
+            name = format!("{}({})", name, value);
+        }
+        self.scope_stack.push(Scope::init(scope_type, name));
+        Ok(())
+    }
+
+    /// Reads the signal ids of a signal of type `signal_type`, one for each scalar in it, and
+    /// declares the variables they make up.
+    fn read_signal(
+        &mut self,
+        name: String,
+        signal_type: &Type,
+        cursor: &mut Cursor,
+    ) -> Result<(), LoadError> {
+        match &signal_type.kind {
+            Kind::Record(fields) => {
+                let mut scope = Scope::init(ScopeType::VhdlRecord, name);
+                scope.attributes.push(type_attribute(&signal_type.name));
+                self.scope_stack.push(scope);
+                for (field_name, field_type) in fields {
+                    self.read_signal(field_name.clone(), field_type, cursor)?;
+                }
+                self.scope_stack.pop();
+            }
+            Kind::Array { element, dims, .. } => {
+                let range = dims[0].ok_or_else(|| invalid(cursor.pos, "array is not bounded"))?;
+                // Each element declares a signal at least, which takes at least a byte.
+                if range.len() as u64 > cursor.remaining() as u64 {
+                    return Err(invalid(cursor.pos, "array is longer than the hierarchy"));
+                }
+                match (&element.kind, dims.len()) {
+                    (Kind::StdULogic, 1) | (Kind::Bit, 1) => {
+                        let signals = range
+                            .indices()
+                            .map(|_| self.signal_id(cursor, Encoding::Byte))
+                            .collect::<Result<Vec<usize>, LoadError>>()?;
+                        self.declare(name, &signal_type.name, &element.kind, signals);
+                    }
+                    _ => {
+                        let element = match dims.len() {
+                            1 => (**element).clone(),
+                            _ => Type {
+                                name: signal_type.name.clone(),
+                                kind: Kind::Array {
+                                    element: element.clone(),
+                                    unbounded_element: element.clone(),
+                                    dims: dims[1..].to_vec(),
+                                },
+                            },
+                        };
+                        for index in range.indices() {
+                            self.read_signal(format!("{}[{}]", name, index), &element, cursor)?;
+                        }
+                    }
+                }
+            }
+            _ => {
+                let encoding = signal_type
+                    .encoding()
+                    .expect("scalar types have an encoding");
+                let signal = self.signal_id(cursor, encoding)?;
+                self.declare(name, &signal_type.name, &signal_type.kind, vec![signal]);
+            }
+        }
+        Ok(())
+    }
+
+    fn signal_id(&mut self, cursor: &mut Cursor, encoding: Encoding) -> Result<usize, LoadError> {
+        let start = cursor.pos;
+        let id = cursor.varint()? as usize;
+        if id == 0 || id > self.encodings.len() {
+            return Err(invalid(start, "signal id out of range"));
+        }
+        self.encodings[id - 1] = Some(encoding);
+        Ok(id - 1)
+    }
+
+    /// Declares a variable made of `signals`. A variable made of the same signals as an earlier
+    /// one, like a port connected to a signal, is an alias of it.
+    fn declare(&mut self, name: String, type_name: &str, kind: &Kind, signals: Vec<usize>) {
+        let (var_type, leaf_kind, bit_width) = match *kind {
+            Kind::StdULogic => (VarType::Logic, LeafKind::StdULogic, signals.len()),
+            Kind::Bit => (VarType::Bit, LeafKind::Bit, signals.len()),
+            Kind::Enum { width, .. } => (VarType::Enum, LeafKind::Enum(width), width),
+            Kind::Integer { width } => (VarType::Integer, LeafKind::Integer(width), width),
+            _ => (VarType::Real, LeafKind::Real, 64),
+        };
+        let mut attributes = vec![type_attribute(type_name)];
+        if let Kind::Enum { table, .. } = kind {
+            attributes.push(Attribute::new(
+                AttributeType::Misc,
+                7,
+                String::new(),
+                *table,
+            ));
+        }
+        let alias = self.leaves_by_signals.get(&signals).copied();
+        let identifier = match alias {
+            Some(leaf) => self.leaves[leaf].identifier.clone(),
+            None => {
+                let leaf = self.leaves.len();
+                for signal in &signals {
+                    self.listeners[*signal].push(leaf);
+                }
+                let identifier = identifier_for_handle(leaf as u64 + 1);
+                self.leaves_by_signals.insert(signals.clone(), leaf);
+                self.leaves.push(Leaf {
+                    identifier: identifier.clone(),
+                    kind: leaf_kind,
+                    signals,
+                });
+                identifier
+            }
+        };
+        let variable = VariableBuilder::default()
+            .scope(self.scope_stack.clone())
+            .var_type(var_type)
+            .bit_width(bit_width)
+            .ascii_identifier(identifier)
+            .reference(name)
+            .attributes(attributes)
+            .build()
+            .expect("every field of the variable is set");
+        match alias {
+            Some(_) => self.vcd.aliases.push(variable),
+            None => {
+                self.vcd
+                    .variables
+                    .insert(variable.ascii_identifier.clone(), variable);
+            }
+        }
+    }
+
+    /// Reads a snapshot, which holds the value of every signal.
+    fn read_snapshot(&mut self, cursor: &mut Cursor) -> Result<(), LoadError> {
+        cursor.skip(4)?;
+        let time = cursor.i64()? as u64;
+        for signal in 0..self.encodings.len() {
+            self.read_value(signal, cursor)?;
+        }
+        self.finish_time_step(time);
+        expect_section_end(cursor, SECTION_END_OF_SNAPSHOT)
+    }
+
+    /// Reads a cycle: time steps holding the values of the signals that changed, each given by
+    /// its distance to the previous one, and the time to the next step. A negative time ends it.
+    fn read_cycle(&mut self, cursor: &mut Cursor) -> Result<(), LoadError> {
+        let mut time = cursor.i64()? as u64;
+        loop {
+            let mut signal: usize = 0;
+            loop {
+                let start = cursor.pos;
+                signal = match cursor.varint()? as usize {
+                    0 => break,
+                    delta => signal
+                        .checked_add(delta)
+                        .filter(|signal| *signal <= self.encodings.len())
+                        .ok_or_else(|| invalid(start, "signal id out of range"))?,
+                };
+                self.read_value(signal - 1, cursor)?;
+            }
+            self.finish_time_step(time);
+            let start = cursor.pos;
+            time = match cursor.signed_varint()? {
+                delta if delta < 0 => break,
+                delta => time
+                    .checked_add(delta as u64)
+                    .ok_or_else(|| invalid(start, "time out of range"))?,
+            };
+        }
+        expect_section_end(cursor, SECTION_END_OF_CYCLE)
+    }
+
+    fn read_value(&mut self, signal: usize, cursor: &mut Cursor) -> Result<(), LoadError> {
+        let encoding = self.encodings[signal]
+            .ok_or_else(|| invalid(cursor.pos, "value of a signal without a type"))?;
+        self.values[signal] = Some(read_sample(cursor, encoding)?);
+        for leaf in &self.listeners[signal] {
+            if !self.changed.contains(leaf) {
+                self.changed.push(*leaf);
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the values of the variables whose signals changed in a time step. Snapshots repeat
+    /// values that didn't change, so only values that differ from the previous one are kept.
+    fn finish_time_step(&mut self, time: u64) {
+        for leaf in std::mem::take(&mut self.changed) {
+            let leaf = &self.leaves[leaf];
+            let value = match self.value(leaf) {
+                Some(value) => value,
+                None => continue,
+            };
+            let waveform = self
+                .vcd
+                .waveforms
+                .entry(leaf.identifier.clone())
+                .or_default();
+            if waveform.last_value() != Some(&value) {
+                waveform.push(time, value);
+            }
+        }
+    }
+
+    /// The value of a variable, once all its signals have one.
+    fn value(&self, leaf: &Leaf) -> Option<Value> {
+        let samples = leaf
+            .signals
+            .iter()
+            .map(|signal| self.values[*signal])
+            .collect::<Option<Vec<Sample>>>()?;
+        let mut positions = samples.iter().map(|sample| match sample {
+            Sample::Integer(position) => *position,
+            Sample::Real(_) => -1,
+        });
+        let bits: Vec<Bit> = match leaf.kind {
+            LeafKind::StdULogic => positions
+                .map(|position| *STD_ULOGIC_BITS.get(position as usize).unwrap_or(&Bit::X))
+                .collect(),
+            LeafKind::Bit => positions
+                .map(|position| match position {
+                    0 => Bit::Zero,
+                    1 => Bit::One,
+                    _ => Bit::X,
+                })
+                .collect(),
+            LeafKind::Enum(width) | LeafKind::Integer(width) => int_bits(positions.next()?, width),
+            LeafKind::Real => {
+                return match samples[0] {
+                    Sample::Real(value) => Some(Value::Real(value)),
+                    Sample::Integer(_) => None,
+                }
+            }
+        };
+        match bits.len() {
+            1 => Some(Value::Scalar(bits[0])),
+            _ => Some(Value::Vector(bits)),
+        }
+    }
+
+    fn string(&self, cursor: &mut Cursor) -> Result<String, LoadError> {
+        let start = cursor.pos;
+        self.strings
+            .get(cursor.varint()? as usize)
+            .cloned()
+            .ok_or_else(|| invalid(start, "string id out of range"))
+    }
+
+    /// The type a type id refers to. Type ids count from one.
+    fn type_ref(&self, cursor: &mut Cursor) -> Result<&Type, LoadError> {
+        let start = cursor.pos;
+        (cursor.varint()? as usize)
+            .checked_sub(1)
+            .and_then(|index| self.types.get(index))
+            .ok_or_else(|| invalid(start, "type id out of range"))
+    }
+}
+
+/// Reads the bounds a subtype puts on an array or record type: a range for each dimension of an
+/// array and the bounds of its element if that isn't bounded yet, or the bounds of each record
+/// field that isn't.
+fn bounded(base: Type, cursor: &mut Cursor) -> Result<Type, LoadError> {
+    let kind = match base.kind {
+        Kind::Array {
+            unbounded_element,
+            dims,
+            ..
+        } => {
+            let dims = dims
+                .iter()
+                .map(|_| {
+                    let start = cursor.pos;
+                    match read_range(cursor)? {
+                        Some(range) => Ok(Some(range)),
+                        None => Err(invalid(start, "array index range is not discrete")),
+                    }
+                })
+                .collect::<Result<Vec<Option<Range>>, LoadError>>()?;
+            let element = match unbounded_element.is_bounded() {
+                true => unbounded_element.clone(),
+                false => Box::new(bounded((*unbounded_element).clone(), cursor)?),
+            };
+            Kind::Array {
+                element,
+                unbounded_element,
+                dims,
+            }
+        }
+        Kind::Record(fields) => Kind::Record(
+            fields
+                .into_iter()
+                .map(|(name, field)| match field.is_bounded() {
+                    true => Ok((name, field)),
+                    false => Ok((name, bounded(field, cursor)?)),
+                })
+                .collect::<Result<Vec<(String, Type)>, LoadError>>()?,
+        ),
+        _ => return Err(invalid(cursor.pos, "subtype bounds on a scalar type")),
+    };
+    Ok(Type {
+        name: base.name,
+        kind,
+    })
+}
+
+/// Reads a range. Real ranges can't bound an array, so only their size is checked.
+fn read_range(cursor: &mut Cursor) -> Result<Option<Range>, LoadError> {
+    let start = cursor.pos;
+    let kind = cursor.u8()?;
+    let downto = kind & 0x80 != 0;
+    let (left, right) = match kind & 0x7f {
+        TYPE_B2 | TYPE_E8 => (cursor.u8()? as i64, cursor.u8()? as i64),
+        TYPE_I32 | TYPE_I64 | TYPE_P32 | TYPE_P64 => {
+            (cursor.signed_varint()?, cursor.signed_varint()?)
+        }
+        TYPE_F64 => {
+            cursor.skip(16)?;
+            return Ok(None);
+        }
+        _ => return Err(invalid(start, "unknown range kind")),
+    };
+    let (high, low) = match downto {
+        true => (left, right),
+        false => (right, left),
+    };
+    let len = high.checked_sub(low).and_then(|len| len.checked_add(1));
+    if len.is_none() {
+        return Err(invalid(start, "range is too long"));
+    }
+    Ok(Some(Range {
+        left,
+        right,
+        downto,
+    }))
+}
+
+fn read_well_known_types(cursor: &mut Cursor) -> Result<(), LoadError> {
+    cursor.skip(4)?;
+    while cursor.u8()? != 0 {
+        cursor.varint()?;
+    }
+    Ok(())
+}
+
+/// Skips the directory of section offsets, which is only needed to seek in the file.
+fn read_directory(cursor: &mut Cursor) -> Result<(), LoadError> {
+    cursor.skip(4)?;
+    let count = cursor.u32()? as usize;
+    cursor.skip(count.saturating_mul(8))?;
+    expect_section_end(cursor, SECTION_END_OF_DIRECTORY)
+}
+
+fn read_sample(cursor: &mut Cursor, encoding: Encoding) -> Result<Sample, LoadError> {
+    Ok(match encoding {
+        Encoding::Byte => Sample::Integer(cursor.u8()? as i64),
+        Encoding::Integer => Sample::Integer(cursor.signed_varint()?),
+        Encoding::Real => Sample::Real(cursor.f64()?),
+    })
+}
+
+fn expect_section_end(cursor: &mut Cursor, end: &[u8]) -> Result<(), LoadError> {
+    let start = cursor.pos;
+    match cursor.take(4)? == end {
+        true => Ok(()),
+        false => Err(invalid(start, "section is not terminated")),
+    }
+}
+
+/// `base` renamed to `name`, unless the subtype is anonymous.
+fn named(base: Type, name: String) -> Type {
+    match name.is_empty() {
+        true => base,
+        false => Type { name, ..base },
+    }
+}
+
+/// Whether an enum's literals are the characters of `chars`. GHDL writes character literals
+/// with their quotes.
+fn has_literals(literals: &[String], chars: &str) -> bool {
+    literals.len() == chars.len()
+        && literals.iter().zip(chars.chars()).all(|(literal, c)| {
+            let mut literal_chars = literal.trim_matches('\'').chars();
+            literal_chars
+                .next()
+                .is_some_and(|l| l.eq_ignore_ascii_case(&c))
+                && literal_chars.next().is_none()
+        })
+}
+
+/// The number of bits needed for the positions of `literal_count` literals.
+fn enum_width(literal_count: usize) -> usize {
+    let largest = literal_count.saturating_sub(1);
+    ((usize::BITS - largest.leading_zeros()) as usize).max(1)
+}
+
+/// `value` as a two's complement number of `width` bits, most significant first.
+fn int_bits(value: i64, width: usize) -> Vec<Bit> {
+    (0..width)
+        .rev()
+        .map(|bit| match (value >> bit.min(63)) & 1 {
+            1 => Bit::One,
+            _ => Bit::Zero,
+        })
+        .collect()
+}
+
+/// The supplemental variable attribute giving the name of a VHDL type.
+fn type_attribute(type_name: &str) -> Attribute {
+    Attribute::new(AttributeType::Misc, 2, type_name.to_string(), 0)
+}
+
+fn invalid(offset: usize, reason: &str) -> LoadError {
+    LoadError::InvalidBinaryFile {
+        format: "GHW".to_string(),
+        offset,
+        reason: reason.to_string(),
+    }
+}
+
+/// Reads the integers, LEB128 varints and reals GHW is made of. Fixed size integers are in the
+/// byte order the header gives, reals are always little endian.
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    little_endian: bool,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(bytes: &'a [u8], pos: usize, little_endian: bool) -> Self {
+        Cursor {
+            bytes,
+            pos,
+            little_endian,
+        }
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len().saturating_sub(self.pos)
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], LoadError> {
+        let bytes = self
+            .pos
+            .checked_add(len)
+            .and_then(|end| self.bytes.get(self.pos..end))
+            .ok_or_else(|| invalid(self.pos, "unexpected end of data"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, len: usize) -> Result<(), LoadError> {
+        self.take(len).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8, LoadError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, LoadError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(match self.little_endian {
+            true => u32::from_le_bytes(bytes),
+            false => u32::from_be_bytes(bytes),
+        })
+    }
+
+    fn i64(&mut self) -> Result<i64, LoadError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(match self.little_endian {
+            true => i64::from_le_bytes(bytes),
+            false => i64::from_be_bytes(bytes),
+        })
+    }
+
+    fn f64(&mut self) -> Result<f64, LoadError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(f64::from_le_bytes(bytes))
+    }
+
+    fn varint(&mut self) -> Result<u64, LoadError> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(invalid(self.pos, "varint is too long"))
+    }
+
+    fn signed_varint(&mut self) -> Result<i64, LoadError> {
+        let mut value: i64 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            value |= ((byte & 0x7f) as i64) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    value |= -1 << shift;
+                }
+                return Ok(value);
+            }
+            if shift >= 64 {
+                return Err(invalid(self.pos, "varint is too long"));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefix_compressed_strings() {
+        let mut bytes = b"GHDLwave\n\x10\x00\x01\x01\x04\x00\x00".to_vec();
+        bytes.extend_from_slice(b"STR\0\0\0\0\0\x03\0\0\0\x0b\0\0\0");
+        bytes.extend_from_slice(b"state\x05_t\x00run\x00EOS\0");
+        let mut reader = GhwReader::new(&bytes).unwrap();
+        let mut cursor = Cursor::new(&bytes, HEADER_LEN + 4, true);
+        reader.read_strings(&mut cursor).unwrap();
+        assert_eq!(reader.strings, vec!["", "state", "state_t", "run"]);
+        assert!(cursor.at_end());
+    }
+
+    #[test]
+    fn subtype_bounds_an_array_of_unbounded_arrays() {
+        let std_ulogic = Type {
+            name: "std_ulogic".to_string(),
+            kind: Kind::StdULogic,
+        };
+        let vector = Type {
+            name: "std_ulogic_vector".to_string(),
+            kind: Kind::Array {
+                element: Box::new(std_ulogic.clone()),
+                unbounded_element: Box::new(std_ulogic),
+                dims: vec![None],
+            },
+        };
+        let memory = Type {
+            name: "mem_t".to_string(),
+            kind: Kind::Array {
+                element: Box::new(vector.clone()),
+                unbounded_element: Box::new(vector),
+                dims: vec![None],
+            },
+        };
+        assert!(!memory.is_bounded());
+        let bounds = [TYPE_I32, 0, 3, TYPE_I32 | 0x80, 7, 0];
+        let mut cursor = Cursor::new(&bounds, 0, true);
+        let memory = bounded(memory, &mut cursor).unwrap();
+        assert!(memory.is_bounded());
+        assert!(cursor.at_end());
+        match memory.kind {
+            Kind::Array { element, dims, .. } => {
+                assert_eq!(
+                    dims[0].unwrap().indices().collect::<Vec<i64>>(),
+                    [0, 1, 2, 3]
+                );
+                assert_eq!(element.name, "std_ulogic_vector");
+                assert!(element.is_bounded());
+            }
+            _ => panic!("a subtype of an array is an array"),
+        }
+    }
+
+    #[test]
+    fn array_without_dimensions_throws_error() {
+        let bytes = b"GHDLwave\n\x10\x00\x01\x01\x04\x00\x00";
+        let mut reader = GhwReader::new(bytes).unwrap();
+        reader.strings = vec!["".to_string()];
+        reader.types = vec![Type {
+            name: "std_ulogic".to_string(),
+            kind: Kind::StdULogic,
+        }];
+        let section = [b'T', b'Y', b'P', 0, 1, 0, 0, 0, TYPE_ARRAY, 0, 1, 0];
+        let mut cursor = Cursor::new(&section, 0, true);
+        let err = reader.read_types(&mut cursor).err();
+        assert_eq!(err, Some(invalid(8, "array has no dimensions")));
+    }
+
+    #[test]
+    fn range_too_long_throws_error() {
+        // From -2^62 to 2^62, which has more indices than an i64 holds.
+        let mut bytes = vec![TYPE_I64];
+        bytes.extend_from_slice(&[0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x40]);
+        bytes.extend_from_slice(&[0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0xc0, 0x00]);
+        let err = read_range(&mut Cursor::new(&bytes, 0, true)).err();
+        assert_eq!(err, Some(invalid(0, "range is too long")));
+        // The other way round, it's a null range.
+        bytes[0] |= 0x80;
+        let range = read_range(&mut Cursor::new(&bytes, 0, true)).unwrap();
+        assert_eq!(range.map(|range| range.len()), Some(0));
+    }
+
+    #[test]
+    fn enum_literals() {
+        let std_ulogic: Vec<String> = "UX01ZWLH-".chars().map(|c| format!("'{}'", c)).collect();
+        assert!(has_literals(&std_ulogic, "UX01ZWLH-"));
+        assert!(!has_literals(&std_ulogic, "01"));
+        let state = vec!["idle".to_string(), "run".to_string()];
+        assert!(!has_literals(&state, "01"));
+        assert_eq!(enum_width(1), 1);
+        assert_eq!(enum_width(2), 1);
+        assert_eq!(enum_width(3), 2);
+        assert_eq!(enum_width(9), 4);
+        assert_eq!(
+            int_bits(-3, 4),
+            vec![Bit::One, Bit::One, Bit::Zero, Bit::One]
+        );
+    }
+
+    #[test]
+    fn wrong_magic_throws_error() {
+        let err = load_from_bytes(b"$date today $end").err();
+        assert_eq!(err.map(|err| err.code()), Some("E016"));
+    }
+}
@@ -0,0 +1,402 @@
+//! A seek index kept in a sidecar file next to a VCD file, to load a window of time without
+//! parsing the file from the start.
+//!
+//! The index holds checkpoints at regular intervals of the value change section: where a
+//! timestamp is, and the last change of every variable before it. Loading a window parses the
+//! header, restores the checkpoint before the start of the window and parses on from there up to
+//! its end. The index also records the size and modification time of the file it was built for,
+//! and [`SeekIndex::open`] builds it again once they change.
+
+use crate::compression::Compression;
+use crate::error::LoadError;
+use crate::options::ParseOptions;
+use crate::parser::{parse_bytes, LineReader};
+use crate::state_machine::StateMachine;
+use crate::tokenizer::{Position, Span, Tokenizer};
+use crate::types::value::Value;
+use crate::vcd::VCD;
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+const MAGIC: &[u8] = b"VCDINDX1";
+
+/// How many bytes of value changes there are between two checkpoints of [`SeekIndex::open`].
+pub const DEFAULT_INTERVAL: u64 = 4 * 1024 * 1024;
+
+/// The size and modification time of an indexed file, to tell whether it changed since.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct SourceStamp {
+    pub len: u64,
+    /// Nanoseconds since the Unix epoch, or 0 if the file system doesn't keep it.
+    pub modified: u128,
+}
+
+impl SourceStamp {
+    pub fn of_file(filename: &str) -> Result<SourceStamp, LoadError> {
+        let metadata = std::fs::metadata(filename).map_err(|e| open_error(filename, e))?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |since_epoch| since_epoch.as_nanos());
+        Ok(SourceStamp {
+            len: metadata.len(),
+            modified,
+        })
+    }
+}
+
+/// A timestamp in the value change section that parsing can start from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    pub time: u64,
+    /// Where the timestamp is.
+    pub position: Position,
+    /// The identifier, time and value of the last change of every variable dumped before the
+    /// timestamp.
+    pub changes: Vec<(String, u64, Value)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeekIndex {
+    pub source: SourceStamp,
+    /// The length of the header, up to the `$end` of `$enddefinitions`.
+    pub header_len: usize,
+    pub checkpoints: Vec<Checkpoint>,
+}
+
+/// Where the index of `filename` is kept: next to it, with `.idx` appended to its name.
+pub fn sidecar_path(filename: &str) -> PathBuf {
+    PathBuf::from(format!("{}.idx", filename))
+}
+
+impl SeekIndex {
+    /// The index of `filename` from its sidecar file, if there is one for the file as it is now.
+    /// Otherwise the file is indexed, and the index written to the sidecar file for next time;
+    /// failing to write it, e.g. in a read-only directory, isn't an error. A sidecar file that
+    /// isn't a seek index is left alone.
+    pub fn open(filename: &str, options: &ParseOptions) -> Result<SeekIndex, LoadError> {
+        let source = SourceStamp::of_file(filename)?;
+        let sidecar = sidecar_path(filename);
+        let existing = std::fs::read(&sidecar);
+        let current = existing
+            .as_ref()
+            .ok()
+            .and_then(|bytes| SeekIndex::from_bytes(bytes).ok())
+            .filter(|index| index.source == source);
+        if let Some(index) = current {
+            return Ok(index);
+        }
+        let index = SeekIndex::build(filename, options, DEFAULT_INTERVAL)?;
+        let replaceable = match existing {
+            Ok(bytes) => bytes.starts_with(MAGIC),
+            Err(e) => e.kind() == std::io::ErrorKind::NotFound,
+        };
+        if replaceable {
+            let _ = std::fs::write(&sidecar, index.to_bytes());
+        }
+        Ok(index)
+    }
+
+    /// Parses all of `filename`, which can't be compressed, putting a checkpoint at the first
+    /// timestamp of the value change section and then at the first one at least `interval` bytes
+    /// after the previous checkpoint. Only the last change of each variable is kept in memory.
+    pub fn build(
+        filename: &str,
+        options: &ParseOptions,
+        interval: u64,
+    ) -> Result<SeekIndex, LoadError> {
+        let source = SourceStamp::of_file(filename)?;
+        let mut lines = LineReader::new(open_uncompressed(filename)?);
+        let mut state_machine = StateMachine::with_options(options.clone());
+        let mut header_len = None;
+        let mut checkpoints = vec![];
+        let mut next_checkpoint = 0;
+        while let Some(line) = lines.next_line() {
+            let (line, position) = line?;
+            for token in Tokenizer::resume(line, position) {
+                let span = token.span;
+                let checkpoint_time = timestamp(token.text).filter(|_| {
+                    span.offset as u64 >= next_checkpoint
+                        && state_machine.at_value_change_boundary()
+                });
+                if let Some(time) = checkpoint_time {
+                    checkpoints.push(Checkpoint {
+                        time,
+                        position: Position {
+                            offset: span.offset,
+                            line: span.line,
+                            column: span.column,
+                        },
+                        changes: state_machine.last_changes(),
+                    });
+                    state_machine.forget_changes();
+                    next_checkpoint = span.offset as u64 + interval;
+                }
+                state_machine.parse_word(token.text, span)?;
+                if header_len.is_none() && state_machine.at_value_change_boundary() {
+                    header_len = Some(span.offset + span.len);
+                }
+            }
+        }
+        state_machine.cleanup()?;
+        Ok(SeekIndex {
+            source,
+            header_len: header_len.unwrap_or(source.len as usize),
+            checkpoints,
+        })
+    }
+
+    /// Loads the header of `filename` and its value changes from the last checkpoint before
+    /// `start` up to `end`. Each variable dumped before the checkpoint starts off with its last
+    /// change before it, so values are right from `start` on. Commands in the value change
+    /// section before the checkpoint, like comments, are left out.
+    pub fn load_window(
+        &self,
+        filename: &str,
+        options: &ParseOptions,
+        start: u64,
+        end: u64,
+    ) -> Result<VCD, LoadError> {
+        if SourceStamp::of_file(filename)? != self.source {
+            return Err(LoadError::FileOpenError {
+                filename: filename.to_string(),
+                error: "the file changed since it was indexed".to_string(),
+            });
+        }
+        let mut file = File::open(filename).map_err(|e| open_error(filename, e))?;
+        let mut header = vec![];
+        let read_error = |_| LoadError::FileReadError {
+            span: Span::new(0, 0, 1, 1),
+        };
+        (&mut file)
+            .take(self.header_len as u64)
+            .read_to_end(&mut header)
+            .map_err(read_error)?;
+        let mut state_machine = StateMachine::with_options(options.clone());
+        parse_bytes(&mut state_machine, &header, Position::new())?;
+
+        let checkpoint = match self.checkpoints.iter().rposition(|c| c.time < start) {
+            Some(index) => self.checkpoints.get(index),
+            None => self.checkpoints.first(),
+        };
+        if let Some(checkpoint) = checkpoint {
+            state_machine.restore(checkpoint.time, &checkpoint.changes);
+            let position = checkpoint.position;
+            file.seek(SeekFrom::Start(position.offset as u64))
+                .map_err(|_| LoadError::FileReadError {
+                    span: Span::new(position.offset, 0, position.line, position.column),
+                })?;
+            let mut lines = LineReader::resume(BufReader::new(file), position);
+            'lines: while let Some(line) = lines.next_line() {
+                let (line, position) = line?;
+                for token in Tokenizer::resume(line, position) {
+                    let after_end = timestamp(token.text).is_some_and(|time| time > end);
+                    if after_end && state_machine.at_value_change_boundary() {
+                        break 'lines;
+                    }
+                    state_machine.parse_word(token.text, token.span)?;
+                }
+            }
+        }
+        state_machine.cleanup()?;
+        Ok(state_machine.visitor.vcd)
+    }
+
+    pub fn read(path: &Path) -> Result<SeekIndex, LoadError> {
+        let bytes = std::fs::read(path).map_err(|e| open_error(&path.display().to_string(), e))?;
+        SeekIndex::from_bytes(&bytes)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(&self.source.len.to_le_bytes());
+        bytes.extend(&self.source.modified.to_le_bytes());
+        put_u64(&mut bytes, self.header_len as u64);
+        put_u64(&mut bytes, self.checkpoints.len() as u64);
+        for checkpoint in &self.checkpoints {
+            put_u64(&mut bytes, checkpoint.time);
+            put_u64(&mut bytes, checkpoint.position.offset as u64);
+            put_u64(&mut bytes, checkpoint.position.line as u64);
+            put_u64(&mut bytes, checkpoint.position.column as u64);
+            put_u64(&mut bytes, checkpoint.changes.len() as u64);
+            for (identifier, time, value) in &checkpoint.changes {
+                put_u64(&mut bytes, identifier.len() as u64);
+                bytes.extend(identifier.as_bytes());
+                put_u64(&mut bytes, *time);
+                value.write_to(&mut bytes);
+            }
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<SeekIndex, LoadError> {
+        let mut reader = Reader { bytes, pos: 0 };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(invalid(0, "not a seek index"));
+        }
+        let source = SourceStamp {
+            len: reader.u64()?,
+            modified: u128::from_le_bytes(reader.take(16)?.try_into().unwrap()),
+        };
+        let header_len = reader.u64()? as usize;
+        let mut checkpoints = vec![];
+        for _ in 0..reader.u64()? {
+            let time = reader.u64()?;
+            let position = Position {
+                offset: reader.u64()? as usize,
+                line: reader.u64()? as usize,
+                column: reader.u64()? as usize,
+            };
+            let mut changes = vec![];
+            for _ in 0..reader.u64()? {
+                let len = reader.u64()? as usize;
+                let identifier = std::str::from_utf8(reader.take(len)?)
+                    .map_err(|_| invalid(reader.pos, "identifier isn't UTF-8"))?;
+                changes.push((identifier.to_string(), reader.u64()?, reader.value()?));
+            }
+            checkpoints.push(Checkpoint {
+                time,
+                position,
+                changes,
+            });
+        }
+        match reader.pos == bytes.len() {
+            true => Ok(SeekIndex {
+                source,
+                header_len,
+                checkpoints,
+            }),
+            false => Err(invalid(reader.pos, "trailing data")),
+        }
+    }
+}
+
+fn open_uncompressed(filename: &str) -> Result<BufReader<File>, LoadError> {
+    let file = File::open(filename).map_err(|e| open_error(filename, e))?;
+    let mut reader = BufReader::new(file);
+    let header = reader.fill_buf().map_err(|e| open_error(filename, e))?;
+    match Compression::detect(header) {
+        Compression::None => Ok(reader),
+        _ => Err(LoadError::FileOpenError {
+            filename: filename.to_string(),
+            error: "compressed files can't be indexed".to_string(),
+        }),
+    }
+}
+
+fn timestamp(word: &str) -> Option<u64> {
+    match word.strip_prefix('#') {
+        Some(digits) if digits.bytes().all(|c| c.is_ascii_digit()) => digits.parse().ok(),
+        _ => None,
+    }
+}
+
+fn open_error(filename: &str, e: std::io::Error) -> LoadError {
+    LoadError::FileOpenError {
+        filename: filename.to_string(),
+        error: e.to_string(),
+    }
+}
+
+fn invalid(offset: usize, reason: &str) -> LoadError {
+    LoadError::InvalidBinaryFile {
+        format: "seek index".to_string(),
+        offset,
+        reason: reason.to_string(),
+    }
+}
+
+fn put_u64(bytes: &mut Vec<u8>, value: u64) {
+    bytes.extend(&value.to_le_bytes());
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], LoadError> {
+        let bytes = self
+            .pos
+            .checked_add(len)
+            .and_then(|end| self.bytes.get(self.pos..end))
+            .ok_or_else(|| invalid(self.pos, "unexpected end of data"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u64(&mut self) -> Result<u64, LoadError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn value(&mut self) -> Result<Value, LoadError> {
+        let mut pos = self.pos;
+        let value = Value::read_from(self.bytes, &mut pos);
+        let value = value.ok_or_else(|| invalid(self.pos, "invalid value"))?;
+        self.pos = pos;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::value::{Bit, PortState, PortValue, Strength};
+
+    fn index() -> SeekIndex {
+        let port = PortValue {
+            state: PortState::InputHigh,
+            strength0: Strength::Strong,
+            strength1: Strength::Supply,
+        };
+        SeekIndex {
+            source: SourceStamp {
+                len: 1000,
+                modified: 1_700_000_000_123_456_789,
+            },
+            header_len: 120,
+            checkpoints: vec![Checkpoint {
+                time: 50,
+                position: Position {
+                    offset: 500,
+                    line: 40,
+                    column: 1,
+                },
+                changes: vec![
+                    ("!".to_string(), 0, Value::Scalar(Bit::Z)),
+                    ("\"".to_string(), 10, Value::Vector(vec![Bit::One, Bit::X])),
+                    ("#".to_string(), 20, Value::Real(-1.5)),
+                    ("<0".to_string(), 30, Value::Port(vec![port; 2])),
+                ],
+            }],
+        }
+    }
+
+    #[test]
+    fn index_bytes_round_trip() {
+        let index = index();
+        assert_eq!(SeekIndex::from_bytes(&index.to_bytes()), Ok(index));
+    }
+
+    #[test]
+    fn truncated_index_throws_error() {
+        let bytes = index().to_bytes();
+        let err = SeekIndex::from_bytes(&bytes[..bytes.len() - 3]).err();
+        assert_eq!(err.map(|err| err.code()), Some("E016"));
+        let err = SeekIndex::from_bytes(b"VCDINDX0").err();
+        assert_eq!(err.map(|err| err.code()), Some("E016"));
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(timestamp("#120"), Some(120));
+        assert_eq!(timestamp("#"), None);
+        assert_eq!(timestamp("#1a"), None);
+        assert_eq!(timestamp("1#"), None);
+    }
+}
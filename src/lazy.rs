@@ -0,0 +1,203 @@
+//! Loading a VCD file one signal at a time, the way waveform viewers show the hierarchy first and
+//! load signals when they're added.
+//!
+//! Opening a file parses its header and skims the value change section, splitting it into
+//! segments at timestamps and noting which segments each identifier changes in. The values of a
+//! signal are parsed the first time they're asked for, from its segments only.
+
+use crate::body::{Scanner, Word};
+use crate::compression::Compression;
+use crate::error::LoadError;
+use crate::index::SourceStamp;
+use crate::options::ParseOptions;
+use crate::parser::LineReader;
+use crate::state_machine::StateMachine;
+use crate::tokenizer::{ByteTokenizer, Position, Span, Tokenizer};
+use crate::types::waveform::Waveform;
+use crate::vcd::VCD;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+
+/// How many bytes of value changes there are in a segment, at least.
+const SEGMENT_LEN: usize = 256 * 1024;
+
+/// A VCD file whose waveforms are loaded on first access.
+pub struct LazyVcd {
+    filename: String,
+    source: SourceStamp,
+    /// The machine that parsed the header, which loading a signal starts from a fork of.
+    header: StateMachine,
+    vcd: VCD,
+    /// Where each segment starts. The first one starts right after the header, and the others
+    /// at a timestamp.
+    segments: Vec<Position>,
+    /// The segments each identifier changes in.
+    changes_in: HashMap<String, Vec<usize>>,
+}
+
+impl LazyVcd {
+    /// Parses the header of `filename`, which can't be compressed, and skims its value changes.
+    /// Only the header is checked now; errors in the value changes of a signal are found when it
+    /// is loaded.
+    pub fn open(filename: &str, options: &ParseOptions) -> Result<LazyVcd, LoadError> {
+        let source = SourceStamp::of_file(filename)?;
+        let open_error = |e: std::io::Error| LoadError::FileOpenError {
+            filename: filename.to_string(),
+            error: e.to_string(),
+        };
+        let mut reader = BufReader::new(File::open(filename).map_err(open_error)?);
+        if Compression::detect(reader.fill_buf().map_err(open_error)?) != Compression::None {
+            return Err(LoadError::FileOpenError {
+                filename: filename.to_string(),
+                error: "compressed files can't be loaded lazily".to_string(),
+            });
+        }
+        let mut header = StateMachine::with_options(options.clone());
+        let mut segments = vec![];
+        let mut changes_in: HashMap<String, Vec<usize>> = HashMap::new();
+        let mut scanner = Scanner::default();
+        let mut lines = LineReader::new(reader);
+        while let Some(line) = lines.next_line() {
+            let (line, position) = line?;
+            for token in Tokenizer::resume(line, position) {
+                let span = token.span;
+                let segment_start = match segments.last() {
+                    Some(&start) => start,
+                    None => {
+                        header.parse_word(token.text, span)?;
+                        if header.at_value_change_boundary() {
+                            segments.push(Position {
+                                offset: span.offset + span.len,
+                                line: span.line,
+                                column: span.column + span.len,
+                            });
+                        }
+                        continue;
+                    }
+                };
+                match scanner.next(token.text) {
+                    Word::Timestamp if span.offset >= segment_start.offset + SEGMENT_LEN => {
+                        segments.push(Position {
+                            offset: span.offset,
+                            line: span.line,
+                            column: span.column,
+                        });
+                    }
+                    Word::Change(identifier) => {
+                        let segment = segments.len() - 1;
+                        let changes = changes_in.entry(identifier.to_string()).or_default();
+                        if changes.last() != Some(&segment) {
+                            changes.push(segment);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        if segments.is_empty() {
+            header.cleanup()?;
+        }
+        let vcd = std::mem::take(&mut header.visitor.vcd);
+        Ok(LazyVcd {
+            filename: filename.to_string(),
+            source,
+            header,
+            vcd,
+            segments,
+            changes_in,
+        })
+    }
+
+    /// The header, and the waveforms loaded so far.
+    pub fn vcd(&self) -> &VCD {
+        &self.vcd
+    }
+
+    pub fn is_loaded(&self, identifier: &str) -> bool {
+        self.vcd.waveforms.contains_key(identifier)
+    }
+
+    /// The waveform of the variable with identifier `identifier`, loaded from the file if it
+    /// hasn't been yet. `None` if the variable is never dumped. Fails if the file changed since
+    /// it was opened.
+    pub fn waveform(&mut self, identifier: &str) -> Result<Option<&Waveform>, LoadError> {
+        if !self.is_loaded(identifier) {
+            if let Some(waveform) = self.load(identifier)? {
+                self.vcd.waveforms.insert(identifier.to_string(), waveform);
+            }
+        }
+        Ok(self.vcd.waveforms.get(identifier))
+    }
+
+    /// Drops the waveform of `identifier` from memory. It is loaded again on next access.
+    pub fn unload(&mut self, identifier: &str) {
+        self.vcd.waveforms.remove(identifier);
+    }
+
+    fn load(&self, identifier: &str) -> Result<Option<Waveform>, LoadError> {
+        let segments = match self.changes_in.get(identifier) {
+            Some(segments) => segments,
+            None => return Ok(None),
+        };
+        if SourceStamp::of_file(&self.filename)? != self.source {
+            return Err(LoadError::FileOpenError {
+                filename: self.filename.clone(),
+                error: "the file changed since it was opened".to_string(),
+            });
+        }
+        let mut file = File::open(&self.filename).map_err(|e| LoadError::FileOpenError {
+            filename: self.filename.clone(),
+            error: e.to_string(),
+        })?;
+        let mut fork = self.header.fork();
+        for &segment in segments {
+            let start = self.segments[segment];
+            let end = match self.segments.get(segment + 1) {
+                Some(end) => end.offset as u64,
+                None => self.source.len,
+            };
+            let mut bytes = vec![];
+            file.seek(SeekFrom::Start(start.offset as u64))
+                .and_then(|_| {
+                    (&mut file)
+                        .take(end - start.offset as u64)
+                        .read_to_end(&mut bytes)
+                })
+                .map_err(|_| LoadError::FileReadError {
+                    span: Span::new(start.offset, 0, start.line, start.column),
+                })?;
+            parse_changes_of(&mut fork, identifier, &bytes, start)?;
+        }
+        Ok(fork.take_waveform(identifier))
+    }
+}
+
+/// Parses the words of `bytes` into `fork`, leaving out value changes of other identifiers than
+/// `identifier`.
+fn parse_changes_of(
+    fork: &mut StateMachine,
+    identifier: &str,
+    bytes: &[u8],
+    position: Position,
+) -> Result<(), LoadError> {
+    let mut scanner = Scanner::default();
+    let mut value = None;
+    for (word, span) in ByteTokenizer::resume(bytes, position) {
+        let word = std::str::from_utf8(word).map_err(|_| LoadError::FileReadError { span })?;
+        match scanner.next(word) {
+            Word::Value => value = Some((word, span)),
+            Word::Change(changed) => {
+                if changed == identifier {
+                    if let Some((value, span)) = value {
+                        fork.parse_word(value, span)?;
+                    }
+                    fork.parse_word(word, span)?;
+                }
+                value = None;
+            }
+            _ => fork.parse_word(word, span)?,
+        }
+    }
+    Ok(())
+}
@@ -3,66 +3,349 @@ extern crate derive_builder;
 extern crate strum;
 #[macro_use]
 extern crate strum_macros;
+#[cfg(feature = "tokio")]
+pub mod async_reader;
+pub mod body;
+pub mod compact;
+pub mod compression;
+pub mod diagnostic;
 mod dumped_var;
 mod dumped_var_parser;
 pub mod error;
+pub mod follow;
+#[cfg(feature = "fst")]
+pub mod fst;
+#[cfg(feature = "ghw")]
+pub mod ghw;
+pub mod index;
+pub mod lazy;
+pub mod options;
+pub mod parallel;
 pub mod parser;
+pub mod report;
+pub mod spill;
 pub mod state_machine;
 pub mod string_helpers;
+pub mod tokenizer;
 pub mod types;
 pub mod vcd;
+pub mod visitor;
+pub mod warning;
 
 use crate::error::LoadError;
-use crate::error::LoadError::{FileOpenError, FileReadError};
-use crate::parser::parse;
+use crate::error::LoadError::FileOpenError;
+use crate::options::ParseOptions;
+use crate::parallel::parse_bytes_parallel;
+use crate::parser::{parse, parse_bytes, parse_bytes_recovering, parse_recovering, LineReader};
+use crate::report::LoadReport;
 use crate::state_machine::StateMachine;
+use crate::tokenizer::Position;
 use crate::vcd::VCD;
+use crate::visitor::VcdVisitor;
+use crate::warning::{Warning, WarningConfig};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 
 pub fn load_from_str(s: &str) -> Result<VCD, LoadError> {
-    let mut state_machine = StateMachine::new();
-    let mut line_num = 1;
-    for line in s.lines() {
-        parse(&mut state_machine, line.to_string(), line_num)?;
-        line_num += 1;
-    }
-    line_num -= 1;
-    state_machine.cleanup(line_num)?;
-    Ok(state_machine.vcd)
+    Ok(load_from_str_with_options(s, &ParseOptions::default())?.vcd)
 }
 
 pub fn load_from_file(filename: String) -> Result<VCD, LoadError> {
-    match File::open(filename.as_str()) {
-        Ok(file) => {
-            let mut state_machine = StateMachine::new();
-            let mut line_num = 1;
-            for line in BufReader::new(file).lines() {
-                match line {
-                    Ok(line) => parse(&mut state_machine, line, line_num)?,
-                    Err(_) => return Err(FileReadError { line: line_num }),
-                };
-                line_num += 1;
+    Ok(load_from_file_with_options(filename, &ParseOptions::default())?.vcd)
+}
+
+/// Loads a VCD like [`load_from_str`], and also returns the warnings found along the way. How
+/// each kind of warning is treated is set by `config`; denied warnings fail the load.
+pub fn load_from_str_with_warnings(
+    s: &str,
+    config: &WarningConfig,
+) -> Result<(VCD, Vec<Warning>), LoadError> {
+    let report = load_from_str_with_options(s, &options_with_warnings(config))?;
+    Ok((report.vcd, report.warnings))
+}
+
+/// The file equivalent of [`load_from_str_with_warnings`].
+pub fn load_from_file_with_warnings(
+    filename: String,
+    config: &WarningConfig,
+) -> Result<(VCD, Vec<Warning>), LoadError> {
+    let report = load_from_file_with_options(filename, &options_with_warnings(config))?;
+    Ok((report.vcd, report.warnings))
+}
+
+/// Loads a VCD without stopping at the first error. Every error is recorded, and parsing picks
+/// up again at the next command or timestamp, so the report holds as much of the VCD as could
+/// be parsed along with everything that is wrong with it.
+pub fn load_from_str_recovering(s: &str) -> LoadReport {
+    into_report(load_from_str_with_options(s, &recovering_options()))
+}
+
+/// The file equivalent of [`load_from_str_recovering`].
+pub fn load_from_file_recovering(filename: String) -> LoadReport {
+    into_report(load_from_file_with_options(filename, &recovering_options()))
+}
+
+/// Loads a VCD the way `options` say. Unless `options.recover` is set, the first error fails
+/// the load and the report never holds any errors.
+pub fn load_from_str_with_options(
+    s: &str,
+    options: &ParseOptions,
+) -> Result<LoadReport, LoadError> {
+    let mut state_machine = StateMachine::with_options(options.clone());
+    let mut errors = vec![];
+    parse_text(&mut state_machine, s, Position::new(), options, &mut errors)?;
+    finish(state_machine, options, errors)
+}
+
+/// The file equivalent of [`load_from_str_with_options`]. Errors are wrapped in a
+/// [`LoadError::InFile`] naming the file.
+pub fn load_from_file_with_options(
+    filename: String,
+    options: &ParseOptions,
+) -> Result<LoadReport, LoadError> {
+    let report = read_file(filename.clone(), options);
+    in_file(report, &filename)
+}
+
+fn read_file(filename: String, options: &ParseOptions) -> Result<LoadReport, LoadError> {
+    let mut errors = vec![];
+    let reader = match open_file(filename) {
+        Ok(reader) => reader,
+        Err(err) => {
+            record(err, options, &mut errors)?;
+            return Ok(LoadReport {
+                errors,
+                ..LoadReport::default()
+            });
+        }
+    };
+    let mut reader = LineReader::new(reader);
+    let mut state_machine = StateMachine::with_options(options.clone());
+    while let Some(line) = reader.next_line() {
+        match line {
+            Ok((line, position)) => {
+                parse_text(&mut state_machine, line, position, options, &mut errors)?
             }
-            line_num -= 1;
-            state_machine.cleanup(line_num)?;
-            Ok(state_machine.vcd)
+            Err(err) => record(err, options, &mut errors)?,
+        }
+        if options.header_only && state_machine.at_value_change_boundary() {
+            break;
+        }
+    }
+    finish(state_machine, options, errors)
+}
+
+/// Loads only the header of a VCD file: everything up to `$enddefinitions`, like the variables
+/// and their scopes, without reading the value changes. Compressed files are decompressed only
+/// as far as the header goes. [`body::body_info`] tells roughly how much is left out.
+pub fn load_header_from_file(filename: String) -> Result<VCD, LoadError> {
+    let options = ParseOptions {
+        header_only: true,
+        ..ParseOptions::default()
+    };
+    Ok(load_from_file_with_options(filename, &options)?.vcd)
+}
+
+pub fn load_from_bytes(bytes: &[u8]) -> Result<VCD, LoadError> {
+    Ok(load_from_bytes_with_options(bytes, &ParseOptions::default())?.vcd)
+}
+
+/// Loads a VCD held in memory as bytes, tokenizing them in place. Unlike
+/// [`load_from_str_with_options`], the input doesn't need to be valid UTF-8 as a whole; a token
+/// that isn't is a [`LoadError::FileReadError`].
+pub fn load_from_bytes_with_options(
+    bytes: &[u8],
+    options: &ParseOptions,
+) -> Result<LoadReport, LoadError> {
+    let mut state_machine = StateMachine::with_options(options.clone());
+    let mut errors = vec![];
+    match options.recover {
+        true => parse_bytes_recovering(&mut state_machine, bytes, Position::new(), &mut errors)?,
+        false => parse_bytes(&mut state_machine, bytes, Position::new())?,
+    }
+    finish(state_machine, options, errors)
+}
+
+#[cfg(feature = "mmap")]
+pub fn load_from_file_mmap(filename: String) -> Result<VCD, LoadError> {
+    Ok(load_from_file_mmap_with_options(filename, &ParseOptions::default())?.vcd)
+}
+
+/// Loads a VCD file by mapping it into memory and parsing it in place with
+/// [`load_from_bytes_with_options`], which is much faster than reading it line by line for large
+/// files. Compressed files can't be parsed in place, so they are loaded with
+/// [`load_from_file_with_options`] instead.
+///
+/// The file must not be truncated or rewritten while it's being loaded.
+#[cfg(feature = "mmap")]
+pub fn load_from_file_mmap_with_options(
+    filename: String,
+    options: &ParseOptions,
+) -> Result<LoadReport, LoadError> {
+    load_mapped_file(filename, options, |bytes| {
+        load_from_bytes_with_options(bytes, options)
+    })
+}
+
+/// Like [`load_from_bytes_with_options`], but the value changes are parsed on up to `threads`
+/// threads, each taking a piece of the value change section that starts at a timestamp. The
+/// result is the same as when parsing on one thread, errors and warnings included. Pieces that
+/// can't be parsed on their own, like those holding commands, are parsed in order.
+pub fn load_from_bytes_parallel(
+    bytes: &[u8],
+    options: &ParseOptions,
+    threads: usize,
+) -> Result<LoadReport, LoadError> {
+    let mut state_machine = StateMachine::with_options(options.clone());
+    let mut errors = vec![];
+    parse_bytes_parallel(&mut state_machine, bytes, threads, &mut errors)?;
+    finish(state_machine, options, errors)
+}
+
+/// The file equivalent of [`load_from_bytes_parallel`]; the file is mapped into memory like in
+/// [`load_from_file_mmap_with_options`].
+#[cfg(feature = "mmap")]
+pub fn load_from_file_parallel(
+    filename: String,
+    options: &ParseOptions,
+    threads: usize,
+) -> Result<LoadReport, LoadError> {
+    load_mapped_file(filename, options, |bytes| {
+        load_from_bytes_parallel(bytes, options, threads)
+    })
+}
+
+/// Loads a file mapped into memory with `load`, unless it is compressed.
+#[cfg(feature = "mmap")]
+fn load_mapped_file(
+    filename: String,
+    options: &ParseOptions,
+    load: impl FnOnce(&[u8]) -> Result<LoadReport, LoadError>,
+) -> Result<LoadReport, LoadError> {
+    let map = File::open(filename.as_str()).and_then(|file| {
+        // Safety: the file is only read, and callers are told not to change it while loading.
+        unsafe { memmap2::Mmap::map(&file) }
+    });
+    match map {
+        Ok(map) if compression::Compression::detect(&map) != compression::Compression::None => {
+            load_from_file_with_options(filename, options)
+        }
+        Ok(map) => in_file(load(&map), &filename),
+        Err(e) => {
+            let mut errors = vec![];
+            record(
+                FileOpenError {
+                    filename,
+                    error: e.to_string(),
+                },
+                options,
+                &mut errors,
+            )?;
+            Ok(LoadReport {
+                errors,
+                ..LoadReport::default()
+            })
+        }
+    }
+}
+
+fn options_with_warnings(config: &WarningConfig) -> ParseOptions {
+    ParseOptions {
+        warnings: config.clone(),
+        ..ParseOptions::default()
+    }
+}
+
+fn recovering_options() -> ParseOptions {
+    ParseOptions {
+        recover: true,
+        ..ParseOptions::default()
+    }
+}
+
+/// Names `filename` in the error `result` failed with, or in every error of its report.
+fn in_file(result: Result<LoadReport, LoadError>, filename: &str) -> Result<LoadReport, LoadError> {
+    let mut report = result.map_err(|err| err.in_file(filename))?;
+    report.errors = report
+        .errors
+        .into_iter()
+        .map(|err| err.in_file(filename))
+        .collect();
+    Ok(report)
+}
+
+fn into_report(result: Result<LoadReport, LoadError>) -> LoadReport {
+    result.unwrap_or_else(|err| LoadReport {
+        errors: vec![err],
+        ..LoadReport::default()
+    })
+}
+
+fn parse_text<V: VcdVisitor>(
+    state_machine: &mut StateMachine<V>,
+    text: &str,
+    position: Position,
+    options: &ParseOptions,
+    errors: &mut Vec<LoadError>,
+) -> Result<(), LoadError> {
+    match options.recover {
+        true => parse_recovering(state_machine, text, position, errors),
+        false => parse(state_machine, text, position),
+    }
+}
+
+fn finish(
+    mut state_machine: StateMachine,
+    options: &ParseOptions,
+    mut errors: Vec<LoadError>,
+) -> Result<LoadReport, LoadError> {
+    if let Err(err) = state_machine.cleanup() {
+        record(err, options, &mut errors)?;
+    }
+    Ok(LoadReport {
+        vcd: state_machine.visitor.vcd,
+        errors,
+        warnings: state_machine.warnings,
+    })
+}
+
+/// Keeps `err` in `errors` when recovering from errors, and fails with it otherwise.
+fn record(
+    err: LoadError,
+    options: &ParseOptions,
+    errors: &mut Vec<LoadError>,
+) -> Result<(), LoadError> {
+    match options.recover {
+        true => {
+            errors.push(err);
+            Ok(())
         }
-        Err(e) => Err(FileOpenError {
+        false => Err(err),
+    }
+}
+
+fn open_file(filename: String) -> Result<Box<dyn BufRead>, LoadError> {
+    File::open(filename.as_str())
+        .and_then(|file| compression::decompress(BufReader::new(file)))
+        .map_err(|e| FileOpenError {
             filename,
             error: e.to_string(),
-        }),
-    }
+        })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::options::Profile;
+    use crate::tokenizer::Span;
     use crate::types::{
         scope::{Scope, ScopeType},
         timescale::{TimeScale, TimeUnit},
+        unknown_command::UnknownCommand,
+        value::{Bit, PortState, PortValue, Strength, Value},
         variable::{VarType, Variable, VariableBuilder},
     };
+    use crate::warning::{Level, WarningKind};
     use std::collections::HashMap;
 
     fn get_scope_vec(scopes: Vec<(ScopeType, &str)>) -> Vec<Scope> {
@@ -84,7 +367,9 @@ mod tests {
     #[test]
     fn end_without_matching_command_throws_error() {
         let lines = r#"$end"#;
-        let exp_err = LoadError::DanglingEnd { line: 1 };
+        let exp_err = LoadError::DanglingEnd {
+            span: Span::new(0, 4, 1, 1),
+        };
         assert_eq!(load_from_str(lines).err(), Some(exp_err));
     }
 
@@ -110,7 +395,7 @@ $end"#;
 Date text"#;
         let err = load_from_str(contents).err();
         let exp_err = LoadError::MissingEnd {
-            line: 2,
+            span: Span::new(11, 4, 2, 6),
             command: "date".to_string(),
         };
         assert_eq!(err, Some(exp_err));
@@ -125,12 +410,19 @@ $version
 $end"#;
         let err = load_from_str(contents).err();
         let exp_err = LoadError::MissingEnd {
-            line: 3,
+            span: Span::new(20, 8, 3, 1),
             command: "date".to_string(),
         };
         assert_eq!(err, Some(exp_err));
     }
 
+    #[test]
+    fn date_command_crlf() {
+        let contents = "$date\r\n    Date text\r\n$end\r\n";
+        let vcd = load_from_str(contents).unwrap();
+        assert_eq!(vcd.date, "Date text".to_string());
+    }
+
     #[test]
     fn version_command_multiple_newlines() {
         let contents = r#"$version
@@ -155,7 +447,7 @@ $end"#;
             This version has no end"#;
         let err = load_from_str(contents).err();
         let exp_err = LoadError::MissingEnd {
-            line: 2,
+            span: Span::new(41, 3, 2, 33),
             command: "version".to_string(),
         };
         assert_eq!(err, Some(exp_err));
@@ -171,7 +463,7 @@ $version
 $end"#;
         let err = load_from_str(contents).err();
         let exp_err = LoadError::InvalidMultipleCommand {
-            line: 4,
+            span: Span::new(30, 8, 4, 1),
             command: "version".to_string(),
         };
         assert_eq!(err, Some(exp_err));
@@ -187,7 +479,7 @@ $date
 $end"#;
         let err = load_from_str(contents).err();
         let exp_err = LoadError::InvalidMultipleCommand {
-            line: 4,
+            span: Span::new(30, 5, 4, 1),
             command: "date".to_string(),
         };
         assert_eq!(err, Some(exp_err));
@@ -224,7 +516,7 @@ $end"#;
         let contents = "$comment This comment is missing an end";
         let err = load_from_str(contents).err();
         let exp_err = LoadError::MissingEnd {
-            line: 1,
+            span: Span::new(36, 3, 1, 37),
             command: "comment".to_string(),
         };
         assert_eq!(err, Some(exp_err));
@@ -247,6 +539,22 @@ $var wire 8 # data $end"#;
         assert_eq!(exp_vars, act_vars);
     }
 
+    #[test]
+    fn parse_tab_separated_scope_and_var() {
+        let lines = "$scope\tmodule\tlvl_1\t$end\r\n$var\twire\t8\t#\tdata\t$end\r\n";
+        let exp_var: Variable = VariableBuilder::default()
+            .scope(get_scope_vec(vec![(ScopeType::Module, "lvl_1")]))
+            .var_type(VarType::Wire)
+            .bit_width(8)
+            .ascii_identifier("#".to_string())
+            .reference("data".to_string())
+            .build()
+            .unwrap();
+        let exp_vars = get_var_hash_map(vec![exp_var]);
+        let act_vars = load_from_str(lines).unwrap().variables;
+        assert_eq!(exp_vars, act_vars);
+    }
+
     #[test]
     fn parse_two_lvl1_scopes_each_with_one_var() {
         let lines = r#"$scope module lvl_1_one $end
@@ -379,7 +687,7 @@ $var event 2 e my_var"#;
 
         let exp_err = LoadError::MissingEnd {
             command: "var".to_string(),
-            line: 2,
+            span: Span::new(39, 6, 2, 16),
         };
 
         assert_eq!(load_from_str(lines).err(), Some(exp_err));
@@ -396,7 +704,7 @@ my_var"#;
 
         let exp_err = LoadError::MissingEnd {
             command: "var".to_string(),
-            line: 6,
+            span: Span::new(39, 6, 6, 1),
         };
         assert_eq!(load_from_str(lines).err(), Some(exp_err));
     }
@@ -409,7 +717,7 @@ $upscope $end"#;
 
         let exp_err = LoadError::MissingEnd {
             command: "var".to_string(),
-            line: 3,
+            span: Span::new(46, 8, 3, 1),
         };
 
         assert_eq!(load_from_str(lines).err(), Some(exp_err));
@@ -420,7 +728,7 @@ $upscope $end"#;
         let lines = r#"$scope module name"#;
         let exp_err = LoadError::MissingEnd {
             command: "scope".to_string(),
-            line: 1,
+            span: Span::new(14, 4, 1, 15),
         };
         assert_eq!(load_from_str(lines).err(), Some(exp_err));
     }
@@ -432,7 +740,7 @@ module
 name"#;
         let exp_err = LoadError::MissingEnd {
             command: "scope".to_string(),
-            line: 3,
+            span: Span::new(14, 4, 3, 1),
         };
         assert_eq!(load_from_str(lines).err(), Some(exp_err));
     }
@@ -445,7 +753,7 @@ name
 $var integer 8 a my_var $end"#;
         let exp_err = LoadError::MissingEnd {
             command: "scope".to_string(),
-            line: 4,
+            span: Span::new(19, 4, 4, 1),
         };
         assert_eq!(load_from_str(lines).err(), Some(exp_err));
     }
@@ -455,7 +763,7 @@ $var integer 8 a my_var $end"#;
         let lines = r#"$upscope"#;
         let exp_err = LoadError::MissingEnd {
             command: "upscope".to_string(),
-            line: 1,
+            span: Span::new(0, 8, 1, 1),
         };
         assert_eq!(load_from_str(lines).err(), Some(exp_err));
     }
@@ -467,7 +775,7 @@ $upscope
 $scope module other_name $end"#;
         let exp_err = LoadError::MissingEnd {
             command: "upscope".to_string(),
-            line: 3,
+            span: Span::new(33, 6, 3, 1),
         };
         assert_eq!(load_from_str(lines).err(), Some(exp_err));
     }
@@ -478,7 +786,7 @@ $scope module other_name $end"#;
 $var wire 8 # $end"#;
         let exp_err = LoadError::TooFewParameters {
             command: "var".to_string(),
-            line: 2,
+            span: Span::new(39, 4, 2, 15),
         };
         assert_eq!(load_from_str(lines).err(), Some(exp_err));
     }
@@ -488,7 +796,7 @@ $var wire 8 # $end"#;
         let lines = r#"$var wire 8 # data $end"#;
         let exp_err = LoadError::ScopeStackEmpty {
             command: "var".to_string(),
-            line: 1,
+            span: Span::new(0, 4, 1, 1),
         };
         assert_eq!(load_from_str(lines).err(), Some(exp_err));
     }
@@ -499,7 +807,7 @@ $var wire 8 # $end"#;
 $var wire 8 # data BAD_PARAM $end"#;
         let exp_err = LoadError::TooManyParameters {
             command: "var".to_string(),
-            line: 2,
+            span: Span::new(44, 9, 2, 20),
         };
         assert_eq!(load_from_str(lines).err(), Some(exp_err));
     }
@@ -508,7 +816,7 @@ $var wire 8 # data BAD_PARAM $end"#;
     fn upscope_with_empty_hierarchy_throws_error() {
         let lines = r#"$upscope $end"#;
         let exp_err = LoadError::ScopeStackEmpty {
-            line: 1,
+            span: Span::new(9, 4, 1, 10),
             command: "upscope".to_string(),
         };
         assert_eq!(load_from_str(lines).err(), Some(exp_err));
@@ -520,8 +828,611 @@ $var wire 8 # data BAD_PARAM $end"#;
         let exp_err = LoadError::InvalidParameterForCommand {
             parameter: "parameter".to_string(),
             command: "upscope".to_string(),
-            line: 1,
+            span: Span::new(9, 9, 1, 10),
+        };
+        assert_eq!(load_from_str(lines).err(), Some(exp_err));
+    }
+
+    #[test]
+    fn recovering_load_collects_every_error() {
+        let lines = r#"$date
+    Date text
+$version
+    Version 1
+$end
+$scope module top $end
+$var wire 8 # data BAD $end
+$var wire 1 ! clk $end
+$upscope $end
+$upscope $end
+$timescale 1 ps $end"#;
+        let report = load_from_str_recovering(lines);
+        assert_eq!(
+            report.errors,
+            vec![
+                LoadError::MissingEnd {
+                    command: "date".to_string(),
+                    span: Span::new(20, 8, 3, 1),
+                },
+                LoadError::TooManyParameters {
+                    command: "var".to_string(),
+                    span: Span::new(90, 3, 7, 20),
+                },
+                LoadError::ScopeStackEmpty {
+                    command: "upscope".to_string(),
+                    span: Span::new(145, 4, 10, 10),
+                },
+            ]
+        );
+        assert!(!report.is_ok());
+        assert_eq!(report.vcd.date, "Date text");
+        assert_eq!(report.vcd.version, "Version 1");
+        assert_eq!(report.vcd.timescale, TimeScale::new(1, TimeUnit::PS));
+        let exp_vars = get_var_hash_map(vec![VariableBuilder::default()
+            .scope(get_scope_vec(vec![(ScopeType::Module, "top")]))
+            .var_type(VarType::Wire)
+            .bit_width(1)
+            .ascii_identifier("!".to_string())
+            .reference("clk".to_string())
+            .build()
+            .unwrap()]);
+        assert_eq!(report.vcd.variables, exp_vars);
+    }
+
+    #[test]
+    fn recovering_load_keeps_broken_scope_on_stack() {
+        let lines = r#"$scope module top
+$var wire 1 ! clk $end
+$upscope $end"#;
+        let report = load_from_str_recovering(lines);
+        assert_eq!(
+            report.errors,
+            vec![LoadError::MissingEnd {
+                command: "scope".to_string(),
+                span: Span::new(18, 4, 2, 1),
+            }]
+        );
+        assert_eq!(
+            report.vcd.variables["!"].scope,
+            get_scope_vec(vec![(ScopeType::Module, "top")])
+        );
+    }
+
+    #[test]
+    fn recovering_load_reports_missing_end_at_end_of_input() {
+        let report = load_from_str_recovering("$comment no end");
+        assert_eq!(
+            report.errors,
+            vec![LoadError::MissingEnd {
+                command: "comment".to_string(),
+                span: Span::new(12, 3, 1, 13),
+            }]
+        );
+    }
+
+    #[test]
+    fn recovering_load_of_valid_input_has_no_errors() {
+        let report = load_from_str_recovering("$date today $end");
+        assert!(report.is_ok());
+        assert_eq!(report.vcd.date, "today");
+    }
+
+    fn bits(s: &str) -> Value {
+        Value::Vector(s.chars().map(|c| Bit::from_char(c).unwrap()).collect())
+    }
+
+    const VALUE_CHANGES: &str = r#"$timescale 1 ps $end
+$scope module top $end
+$var wire 8 # data $end
+$var wire 1 ! clk $end
+$var real 64 r% temp $end
+$upscope $end
+$enddefinitions $end
+$dumpvars
+bxxxxxxxx #
+0!
+r0 r%
+$end
+#0
+b10000001 #
+#5
+1!
+b1010 #
+R1.5 r%
+#10
+0!"#;
+
+    #[test]
+    fn parse_value_changes() {
+        let vcd = load_from_str(VALUE_CHANGES).unwrap();
+        assert_eq!(
+            vcd.waveforms["#"].changes(),
+            &[
+                (0, bits("xxxxxxxx")),
+                (0, bits("10000001")),
+                (5, bits("00001010")),
+            ]
+        );
+        assert_eq!(
+            vcd.waveforms["!"].changes(),
+            &[
+                (0, Value::Scalar(Bit::Zero)),
+                (5, Value::Scalar(Bit::One)),
+                (10, Value::Scalar(Bit::Zero)),
+            ]
+        );
+        assert_eq!(vcd.waveforms["r%"].value_at(7), Some(&Value::Real(1.5)));
+    }
+
+    #[test]
+    fn value_change_for_undeclared_identifier_throws_error() {
+        let lines = r#"$scope module top $end
+$var wire 1 ! clk $end
+$upscope $end
+$enddefinitions $end
+#0
+1?"#;
+        let exp_err = LoadError::UnknownIdentifier {
+            span: Span::new(84, 2, 6, 1),
+            identifier: "?".to_string(),
         };
         assert_eq!(load_from_str(lines).err(), Some(exp_err));
     }
+
+    #[test]
+    fn vector_value_without_identifier_throws_error() {
+        let lines = r#"$scope module top $end
+$var wire 2 ! bus $end
+$upscope $end
+$enddefinitions $end
+$dumpvars b01 $end"#;
+        let exp_err = LoadError::InvalidVarDump {
+            span: Span::new(91, 3, 5, 11),
+        };
+        assert_eq!(load_from_str(lines).err(), Some(exp_err));
+    }
+
+    #[test]
+    fn invalid_timestamp_throws_error() {
+        let lines = "$enddefinitions $end\n#1ns";
+        let exp_err = LoadError::InvalidTimeValue {
+            span: Span::new(21, 4, 2, 1),
+            value: "#1ns".to_string(),
+        };
+        assert_eq!(load_from_str(lines).err(), Some(exp_err));
+    }
+
+    #[test]
+    fn no_warnings_for_clean_input() {
+        let lines = r#"$date today $end
+$timescale 1 ns $end
+$scope module top $end
+$var wire 1 ! clk $end
+$upscope $end
+$enddefinitions $end
+$dumpvars 0! $end
+#1
+1!"#;
+        let (_, warnings) = load_from_str_with_warnings(lines, &WarningConfig::new()).unwrap();
+        assert_eq!(warnings, vec![]);
+    }
+
+    #[test]
+    fn warnings_for_suspicious_input() {
+        let lines = r#"$date $end
+$scope module top $end
+$var wire 1 ! clk $end
+$var wire 1 " unused $end
+$upscope $end
+$enddefinitions $end
+$dumpvars 0! $end
+#1
+0!
+$comment late comment $end"#;
+        let (vcd, warnings) = load_from_str_with_warnings(lines, &WarningConfig::new()).unwrap();
+        assert_eq!(vcd.comments, vec!["late comment"]);
+        let kinds: Vec<(WarningKind, usize)> = warnings
+            .iter()
+            .map(|warning| (warning.kind, warning.span.line))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (WarningKind::EmptyDate, 1),
+                (WarningKind::MissingTimescale, 6),
+                (WarningKind::RepeatedValue, 9),
+                (WarningKind::CommentInValueChanges, 10),
+                (WarningKind::NeverDumped, 4),
+            ]
+        );
+        assert_eq!(
+            warnings[4].message,
+            "Variable unused (\") is declared but never dumped"
+        );
+    }
+
+    #[test]
+    fn allowed_warnings_are_not_reported() {
+        let mut config = WarningConfig::new();
+        config
+            .set(WarningKind::EmptyDate, Level::Allow)
+            .set(WarningKind::MissingTimescale, Level::Allow);
+        let (_, warnings) = load_from_str_with_warnings("$date $end", &config).unwrap();
+        assert_eq!(warnings, vec![]);
+    }
+
+    #[test]
+    fn denied_warning_throws_error() {
+        let mut config = WarningConfig::new();
+        config.set(WarningKind::EmptyDate, Level::Deny);
+        let err = load_from_str_with_warnings("$date $end", &config).err();
+        assert_eq!(err.map(|err| err.code()), Some("E013"));
+    }
+
+    #[test]
+    fn recovering_load_reports_warnings() {
+        let report = load_from_str_recovering("$date $end\n$end");
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(
+            report
+                .warnings
+                .iter()
+                .map(|warning| warning.kind)
+                .collect::<Vec<_>>(),
+            vec![WarningKind::EmptyDate, WarningKind::MissingTimescale]
+        );
+    }
+
+    #[test]
+    fn header_only_load_skips_value_changes() {
+        let options = ParseOptions {
+            header_only: true,
+            ..ParseOptions::default()
+        };
+        let vcd = "$timescale 1 ns $end\n$scope module top $end\n$var wire 1 ! a $end\n\
+                   $upscope $end\n$enddefinitions $end #0 1! 1? $end\n";
+        let report = load_from_str_with_options(vcd, &options).unwrap();
+        assert_eq!(report.vcd.variables.len(), 1);
+        assert!(report.vcd.waveforms.is_empty());
+        assert_eq!(report.warnings, vec![]);
+        assert_eq!(
+            load_from_bytes_with_options(vcd.as_bytes(), &options)
+                .unwrap()
+                .warnings,
+            vec![]
+        );
+    }
+
+    #[test]
+    fn progress_is_reported_and_can_cancel_loading() {
+        use crate::options::{Progress, ProgressHook};
+        use std::ops::ControlFlow;
+        use std::sync::{Arc, Mutex};
+        let mut vcd = "$timescale 1 ns $end\n$scope module top $end\n$var wire 1 ! a $end\n\
+                       $upscope $end\n$enddefinitions $end\n"
+            .to_string();
+        for time in 0..100 {
+            vcd.push_str(&format!("#{}\n{}!\n", time, time % 2));
+        }
+        let reported = Arc::new(Mutex::new(vec![]));
+        let seen = Arc::clone(&reported);
+        let options = ParseOptions {
+            progress: Some(ProgressHook::new(200, move |progress: &Progress| {
+                seen.lock().unwrap().push(*progress);
+                ControlFlow::Continue(())
+            })),
+            ..ParseOptions::default()
+        };
+        assert!(load_from_str_with_options(&vcd, &options).is_ok());
+        let reported = reported.lock().unwrap().clone();
+        assert_eq!(reported.len(), vcd.len() / 200 + 1);
+        assert!(reported
+            .windows(2)
+            .all(|pair| pair[1].bytes >= pair[0].bytes + 200));
+        let last = reported.last().unwrap();
+        assert_eq!(
+            last.lines as usize,
+            vcd[..last.bytes as usize].matches('\n').count()
+        );
+        assert_eq!(
+            last.time,
+            vcd[..last.bytes as usize].matches('#').count() as u64 - 1
+        );
+
+        let stop_past_500 = ProgressHook::new(100, |progress: &Progress| match progress.bytes {
+            0..=500 => ControlFlow::Continue(()),
+            _ => ControlFlow::Break(()),
+        });
+        for recover in [false, true].iter() {
+            let options = ParseOptions {
+                progress: Some(stop_past_500.clone()),
+                recover: *recover,
+                ..ParseOptions::default()
+            };
+            let err = load_from_bytes_with_options(vcd.as_bytes(), &options).err();
+            match err {
+                Some(LoadError::Cancelled { offset }) => assert!(offset > 500 && offset < 700),
+                _ => panic!("expected loading to be cancelled"),
+            }
+        }
+    }
+
+    #[test]
+    fn permissive_profile_accepts_sloppy_input() {
+        let vcd = r#"$date first $end
+$date second $end
+$timescale 1ns $end
+$end
+$scope module top $end
+$var string 1 ! name $end
+$upscope $end
+$enddefinitions $end
+#0
+1!
+1?
+"#;
+        let report =
+            load_from_str_with_options(vcd, &ParseOptions::new(Profile::Permissive)).unwrap();
+        assert_eq!(report.vcd.date, "second");
+        assert_eq!(report.vcd.timescale, TimeScale::new(1, TimeUnit::NS));
+        assert_eq!(report.vcd.variables["!"].var_type, VarType::String);
+        assert_eq!(report.vcd.waveforms["!"].len(), 1);
+        assert!(!report.vcd.waveforms.contains_key("?"));
+    }
+
+    #[test]
+    fn strict_profile_rejects_odd_timescale() {
+        let err = load_from_str_with_options(
+            "$timescale 42 ns $end",
+            &ParseOptions::new(Profile::Ieee1364_2005),
+        )
+        .err();
+        assert_eq!(err.map(|err| err.code()), Some("E006"));
+        assert!(load_from_str("$timescale 42 ns $end").is_ok());
+    }
+
+    #[test]
+    fn profile_rejects_var_types_from_later_standards() {
+        let logic = "$scope module top $end $var logic 1 ! a $end";
+        let realtime = "$scope module top $end $var realtime 1 ! t $end";
+        let err = load_from_str_with_options(logic, &ParseOptions::new(Profile::Ieee1364_2005))
+            .err()
+            .unwrap();
+        assert_eq!(
+            err,
+            LoadError::InvalidParameterForCommand {
+                span: Span::new(28, 5, 1, 29),
+                parameter: "logic".to_string(),
+                command: "$var".to_string(),
+            }
+        );
+        assert!(
+            load_from_str_with_options(realtime, &ParseOptions::new(Profile::Ieee1364_1995))
+                .is_err()
+        );
+        assert!(
+            load_from_str_with_options(realtime, &ParseOptions::new(Profile::Ieee1364_2001))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn recover_option_collects_errors() {
+        let mut options = ParseOptions::new(Profile::Ieee1364_2001);
+        options.recover = true;
+        let report = load_from_str_with_options("$end\n$date today $end", &options).unwrap();
+        assert_eq!(report.vcd.date, "today");
+        assert_eq!(report.errors.len(), 1);
+    }
+
+    const EXTENDED_VCD: &str = r#"$timescale 1 ns $end
+$scope module top $end
+$var port 1 <0 clk $end
+$var port [1:0] <1 bus $end
+$upscope $end
+$enddefinitions $end
+#0
+$dumpports
+pN 6 6 <0
+pDU 66 60 <1
+$end
+#10
+pH 0 6 <0
+$vcdclose #20 $end
+"#;
+
+    #[test]
+    fn parse_extended_vcd() {
+        let vcd = load_from_str(EXTENDED_VCD).unwrap();
+        assert_eq!(vcd.variables["<1"].var_type, VarType::Port);
+        assert_eq!(vcd.variables["<1"].bit_width, 2);
+        let port = |state, strength0, strength1| PortValue {
+            state,
+            strength0,
+            strength1,
+        };
+        assert_eq!(
+            vcd.waveforms["<0"].changes(),
+            &[
+                (
+                    0,
+                    Value::Port(vec![port(
+                        PortState::InputUnknown,
+                        Strength::Strong,
+                        Strength::Strong
+                    )])
+                ),
+                (
+                    10,
+                    Value::Port(vec![port(
+                        PortState::OutputHigh,
+                        Strength::HighZ,
+                        Strength::Strong
+                    )])
+                ),
+            ][..]
+        );
+        assert_eq!(
+            vcd.waveforms["<1"].value_at(10),
+            Some(&Value::Port(vec![
+                port(PortState::InputLow, Strength::Strong, Strength::Strong),
+                port(PortState::InputHigh, Strength::Strong, Strength::HighZ),
+            ]))
+        );
+    }
+
+    #[test]
+    fn extended_vcd_is_not_in_1364_1995() {
+        let err = load_from_str_with_options(
+            "$dumpports $end",
+            &ParseOptions::new(Profile::Ieee1364_1995),
+        )
+        .err();
+        assert_eq!(
+            err,
+            Some(LoadError::UnsupportedCommand {
+                span: Span::new(0, 10, 1, 1),
+                command: "$dumpports".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn port_value_change_with_bad_strength_throws_error() {
+        let vcd = "$scope module top $end $var port 1 <0 clk $end $enddefinitions $end pD 9 6 <0";
+        let err = load_from_str(vcd).err();
+        assert_eq!(
+            err,
+            Some(LoadError::InvalidVarDump {
+                span: Span::new(71, 1, 1, 72)
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_command_is_kept_and_reported() {
+        let vcd = "$timescale 1 ns $end\n$vendorinfo tool   v1.2\n\tbuild 7 $end\n";
+        let (vcd, warnings) = load_from_str_with_warnings(vcd, &WarningConfig::new()).unwrap();
+        assert_eq!(
+            vcd.unknown_commands,
+            vec![UnknownCommand {
+                command: "$vendorinfo".to_string(),
+                payload: "tool v1.2 build 7".to_string(),
+                span: Span::new(21, 11, 2, 1),
+            }]
+        );
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].kind, WarningKind::UnknownCommand);
+        assert_eq!(warnings[0].message, "Unknown command $vendorinfo, skipped");
+        assert_eq!(warnings[0].span, Span::new(21, 11, 2, 1));
+    }
+
+    #[test]
+    fn unknown_command_with_no_end_throws_load_error() {
+        let err = load_from_str("$typo something").err();
+        assert_eq!(
+            err,
+            Some(LoadError::MissingEnd {
+                span: Span::new(6, 9, 1, 7),
+                command: "typo".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_command_in_value_changes() {
+        let vcd = "$scope module top $end $var wire 1 ! a $end $enddefinitions $end\n#0 1! $bogus $end #5 0!";
+        let vcd = load_from_str(vcd).unwrap();
+        assert_eq!(vcd.unknown_commands[0].command, "$bogus");
+        assert_eq!(vcd.waveforms["!"].len(), 2);
+    }
+
+    #[test]
+    fn attributes_are_attached_to_scopes_and_variables() {
+        let vcd = r#"$timescale 1 ns $end
+$attrbegin misc 04 /src/top.v 3 $end
+$scope module top $end
+$attrbegin misc 07 state_t 3 IDLE RUN DONE 00 01 10 1 $end
+$attrbegin misc 07 1 $end
+$var logic 2 ! state $end
+$attrbegin misc 02 STD_LOGIC 1025 $end
+$attrend $end
+$var wire 1 " clk $end
+$upscope $end
+$enddefinitions $end
+#0
+b01 !
+"#;
+        let vcd = load_from_str(vcd).unwrap();
+        let state = &vcd.variables["!"];
+        assert_eq!(
+            state.scope[0].attributes[0].source_location(),
+            Some(("/src/top.v", 3))
+        );
+        assert_eq!(state.attributes.len(), 1);
+        let table = vcd.enum_table(state).unwrap();
+        assert_eq!(table.name, "state_t");
+        let value = vcd.waveforms["!"].value_at(0).unwrap();
+        assert_eq!(table.name_of(value), Some("RUN"));
+        assert_eq!(vcd.variables["\""].attributes, vec![]);
+        assert_eq!(vcd.enum_table(&vcd.variables["\""]), None);
+    }
+
+    #[test]
+    fn variables_sharing_an_identifier_are_aliases() {
+        let vcd = r#"$scope module top $end
+$var wire 1 ! clk $end
+$scope module sub $end
+$var wire 1 ! clk_in $end
+$upscope $end
+$upscope $end
+$enddefinitions $end
+#0
+1!
+"#;
+        let vcd = load_from_str(vcd).unwrap();
+        assert_eq!(vcd.variables.len(), 1);
+        assert_eq!(vcd.variables["!"].reference, "clk");
+        assert_eq!(vcd.aliases.len(), 1);
+        assert_eq!(vcd.aliases[0].reference, "clk_in");
+        assert_eq!(vcd.aliases[0].scope.len(), 2);
+        assert_eq!(vcd.waveforms.len(), 1);
+        assert_eq!(
+            vcd.waveforms["!"].value_at(0),
+            Some(&Value::Scalar(Bit::One))
+        );
+    }
+
+    #[test]
+    fn identifier_starting_with_dollar() {
+        let vcd = r#"$timescale 1 ns $end
+$scope module top $end
+$var wire 1 $ clk $end
+$var wire 1 $! rst $end
+$var wire 1 $a x $end
+$var wire 2 $end y $end
+$upscope $end
+$enddefinitions $end
+#0
+1$
+0$!
+1$a
+b10 $end
+"#;
+        let vcd = load_from_str(vcd).unwrap();
+        assert_eq!(vcd.variables["$"].reference, "clk");
+        assert_eq!(
+            vcd.waveforms["$!"].value_at(0),
+            Some(&Value::Scalar(Bit::Zero))
+        );
+        assert_eq!(vcd.variables["$a"].reference, "x");
+        assert_eq!(
+            vcd.waveforms["$a"].value_at(0),
+            Some(&Value::Scalar(Bit::One))
+        );
+        assert_eq!(
+            vcd.waveforms["$end"].value_at(0),
+            Some(&Value::Vector(vec![Bit::One, Bit::Zero]))
+        );
+    }
 }
@@ -0,0 +1,236 @@
+use crate::types::{scope::ScopeType, variable::VarType};
+use crate::warning::WarningConfig;
+use std::fmt;
+use std::ops::ControlFlow;
+use std::sync::Arc;
+
+/// The revision of the VCD format a file is checked against.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Profile {
+    Ieee1364_1995,
+    Ieee1364_2001,
+    Ieee1364_2005,
+    Ieee1800,
+    /// Accepts everything this crate knows about, including tool extensions, and turns off the
+    /// checks that real-world files commonly fail.
+    Permissive,
+}
+
+impl Profile {
+    pub fn allows_var_type(&self, var_type: &VarType) -> bool {
+        use Profile::*;
+        use VarType::*;
+        match var_type {
+            Event | Integer | Parameter | Real | Reg | Supply0 | Supply1 | Time | Tri | TriAnd
+            | TriOr | TriReg | Tri0 | Tri1 | WAnd | Wire | WOr => true,
+            RealTime => *self != Ieee1364_1995,
+            Port => self.has_extended_vcd(),
+            Logic | Bit | Byte | Int | ShortInt | LongInt | ShortReal | Enum => {
+                matches!(self, Ieee1800 | Permissive)
+            }
+            String | RealParameter | SparseArray => *self == Permissive,
+        }
+    }
+
+    /// Whether the revision has extended VCD: `$dumpports` and friends, and `port` variables.
+    pub fn has_extended_vcd(&self) -> bool {
+        *self != Profile::Ieee1364_1995
+    }
+
+    pub fn allows_scope_type(&self, scope_type: &ScopeType) -> bool {
+        use Profile::*;
+        use ScopeType::*;
+        match scope_type {
+            Begin | Fork | Function | Module | Task => true,
+            Interface | Package | Program | Class | Struct | Union => {
+                matches!(self, Ieee1800 | Permissive)
+            }
+            Generate | VhdlArchitecture | VhdlRecord | VhdlBlock | VhdlGenerate | VhdlIfGenerate
+            | VhdlForGenerate | VhdlProcess | VhdlProcedure | VhdlFunction | VhdlPackage => {
+                *self == Permissive
+            }
+        }
+    }
+}
+
+/// Rules a VCD has to follow. Each one is on for the IEEE profiles and off for
+/// [`Profile::Permissive`]. The default options leave out `timescale_magnitude`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Checks {
+    /// At most one `$date`, `$version` and `$timescale`. When off, the last one wins.
+    pub single_header_commands: bool,
+    /// Every `$end` closes a command.
+    pub matched_end: bool,
+    /// The `$timescale` number is 1, 10 or 100.
+    pub timescale_magnitude: bool,
+    /// The `$timescale` number and unit are separate words. When off, `1ns` is also accepted.
+    pub timescale_unit_separated: bool,
+    /// Value changes are only for declared identifiers. When off, other value changes are
+    /// dropped.
+    pub declared_identifiers: bool,
+}
+
+impl Checks {
+    pub fn all() -> Self {
+        Checks {
+            single_header_commands: true,
+            matched_end: true,
+            timescale_magnitude: true,
+            timescale_unit_separated: true,
+            declared_identifiers: true,
+        }
+    }
+
+    pub fn none() -> Self {
+        Checks {
+            single_header_commands: false,
+            matched_end: false,
+            timescale_magnitude: false,
+            timescale_unit_separated: false,
+            declared_identifiers: false,
+        }
+    }
+}
+
+/// How a VCD is parsed.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ParseOptions {
+    pub profile: Profile,
+    pub checks: Checks,
+    pub warnings: WarningConfig,
+    /// Keep going after errors instead of stopping at the first one. See
+    /// [`crate::load_from_str_recovering`].
+    pub recover: bool,
+    /// Stop at the end of `$enddefinitions`, leaving the value changes out. See
+    /// [`crate::load_header_from_file`].
+    pub header_only: bool,
+    /// Told how far parsing got every so often, and able to stop it.
+    pub progress: Option<ProgressHook>,
+}
+
+impl Default for ParseOptions {
+    /// The [`Profile::Ieee1800`] options, except that any `$timescale` number is accepted, as it
+    /// was before there were profiles.
+    fn default() -> Self {
+        let mut options = ParseOptions::new(Profile::Ieee1800);
+        options.checks.timescale_magnitude = false;
+        options
+    }
+}
+
+impl ParseOptions {
+    pub fn new(profile: Profile) -> Self {
+        ParseOptions {
+            profile,
+            checks: match profile {
+                Profile::Permissive => Checks::none(),
+                _ => Checks::all(),
+            },
+            warnings: WarningConfig::new(),
+            recover: false,
+            header_only: false,
+            progress: None,
+        }
+    }
+}
+
+/// How far parsing got.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Progress {
+    /// Bytes of input parsed.
+    pub bytes: u64,
+    /// Lines of input parsed.
+    pub lines: u64,
+    /// The time of the last timestamp parsed.
+    pub time: u64,
+}
+
+/// A callback told the [`Progress`] of parsing each time another `every` bytes of input have
+/// been parsed. Returning [`ControlFlow::Break`] stops parsing with a
+/// [`crate::error::LoadError::Cancelled`].
+///
+/// The callback is shared by the clones of the options, and may be called from another thread
+/// than the one that started parsing.
+#[derive(Clone)]
+pub struct ProgressHook {
+    every: u64,
+    callback: Arc<ProgressCallback>,
+}
+
+type ProgressCallback = dyn Fn(&Progress) -> ControlFlow<()> + Send + Sync;
+
+impl ProgressHook {
+    pub fn new(
+        every: u64,
+        callback: impl Fn(&Progress) -> ControlFlow<()> + Send + Sync + 'static,
+    ) -> Self {
+        ProgressHook {
+            every: every.max(1),
+            callback: Arc::new(callback),
+        }
+    }
+
+    /// How many bytes are parsed between two calls of the callback.
+    pub fn every(&self) -> u64 {
+        self.every
+    }
+
+    pub fn report(&self, progress: &Progress) -> ControlFlow<()> {
+        (self.callback)(progress)
+    }
+}
+
+impl fmt::Debug for ProgressHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProgressHook")
+            .field("every", &self.every)
+            .finish()
+    }
+}
+
+/// Hooks are equal if they share the same callback.
+impl PartialEq for ProgressHook {
+    fn eq(&self, other: &Self) -> bool {
+        self.every == other.every && Arc::ptr_eq(&self.callback, &other.callback)
+    }
+}
+
+impl Eq for ProgressHook {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn later_standards_add_var_types() {
+        assert!(!Profile::Ieee1364_1995.allows_var_type(&VarType::RealTime));
+        assert!(Profile::Ieee1364_2001.allows_var_type(&VarType::RealTime));
+        assert!(!Profile::Ieee1364_1995.allows_var_type(&VarType::Port));
+        assert!(Profile::Ieee1364_2001.allows_var_type(&VarType::Port));
+        assert!(!Profile::Ieee1364_2005.allows_var_type(&VarType::Logic));
+        assert!(Profile::Ieee1800.allows_var_type(&VarType::Logic));
+        assert!(!Profile::Ieee1800.allows_var_type(&VarType::String));
+        assert!(Profile::Permissive.allows_var_type(&VarType::String));
+    }
+
+    #[test]
+    fn later_standards_add_scope_types() {
+        assert!(Profile::Ieee1364_1995.allows_scope_type(&ScopeType::Module));
+        assert!(!Profile::Ieee1364_2005.allows_scope_type(&ScopeType::Interface));
+        assert!(Profile::Ieee1800.allows_scope_type(&ScopeType::Interface));
+        assert!(!Profile::Ieee1800.allows_scope_type(&ScopeType::VhdlArchitecture));
+        assert!(Profile::Permissive.allows_scope_type(&ScopeType::VhdlArchitecture));
+    }
+
+    #[test]
+    fn permissive_profile_turns_off_checks() {
+        assert_eq!(
+            ParseOptions::new(Profile::Permissive).checks,
+            Checks::none()
+        );
+        assert_eq!(
+            ParseOptions::new(Profile::Ieee1364_1995).checks,
+            Checks::all()
+        );
+    }
+}
@@ -0,0 +1,244 @@
+//! Parsing the value change section of a VCD on several threads.
+//!
+//! The header is parsed first. The rest is split into pieces of about the same length, each but
+//! the first starting at a line that starts with a timestamp, and every piece is parsed on its own
+//! thread by a [`StateMachine::fork`] of the header's machine. The pieces are then merged in
+//! order. A piece holding anything but value changes, one with errors, and one whose previous
+//! piece didn't end between two value changes are parsed again in order instead, so the result is
+//! always the same as parsing everything on one thread.
+
+use crate::error::LoadError;
+use crate::parser::{parse_bytes, parse_bytes_recovering};
+use crate::state_machine::StateMachine;
+use crate::tokenizer::{ByteTokenizer, Position};
+use std::ops::Range;
+use std::thread;
+
+/// Pieces smaller than this aren't worth a thread.
+const MIN_PIECE_LEN: usize = 64 * 1024;
+
+/// Parses a whole VCD held as bytes like [`parse_bytes`], or like [`parse_bytes_recovering`] if
+/// the options of `state_machine` say to recover, with the value changes parsed on up to
+/// `threads` threads.
+pub fn parse_bytes_parallel(
+    state_machine: &mut StateMachine,
+    bytes: &[u8],
+    threads: usize,
+    errors: &mut Vec<LoadError>,
+) -> Result<(), LoadError> {
+    let (header_len, body_position) = match header_end(bytes) {
+        Some(end) => end,
+        None => return parse_piece(state_machine, bytes, Position::new(), errors),
+    };
+    parse_piece(state_machine, &bytes[..header_len], Position::new(), errors)?;
+    if state_machine.options().header_only && state_machine.at_value_change_boundary() {
+        return Ok(());
+    }
+    let body = &bytes[header_len..];
+    let pieces = split(body, threads);
+    if pieces.len() < 2 || !state_machine.at_value_change_boundary() {
+        return parse_piece(state_machine, body, body_position, errors);
+    }
+    let positions = positions(body, &pieces, body_position);
+    let forks: Vec<Option<StateMachine>> = thread::scope(|scope| {
+        let handles: Vec<_> = pieces
+            .iter()
+            .zip(&positions)
+            .map(|(piece, position)| {
+                let fork = state_machine.fork();
+                scope.spawn(move || parse_fork(fork, &body[piece.clone()], *position))
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect()
+    });
+    for ((piece, position), fork) in pieces.into_iter().zip(positions).zip(forks) {
+        let merged = match fork {
+            Some(fork) if state_machine.at_value_change_boundary() => state_machine.merge(fork),
+            _ => false,
+        };
+        match merged {
+            true => state_machine.report_progress()?,
+            false => parse_piece(state_machine, &body[piece], position, errors)?,
+        }
+    }
+    Ok(())
+}
+
+fn parse_piece(
+    state_machine: &mut StateMachine,
+    bytes: &[u8],
+    position: Position,
+    errors: &mut Vec<LoadError>,
+) -> Result<(), LoadError> {
+    match state_machine.options().recover {
+        true => parse_bytes_recovering(state_machine, bytes, position, errors),
+        false => parse_bytes(state_machine, bytes, position),
+    }
+}
+
+/// Parses a piece with a fork, or gives up if the piece holds a command or an error.
+fn parse_fork(mut fork: StateMachine, piece: &[u8], position: Position) -> Option<StateMachine> {
+    for (token, span) in ByteTokenizer::resume(piece, position) {
+        let word = std::str::from_utf8(token).ok()?;
+        if StateMachine::is_cmd(word) {
+            return None;
+        }
+        fork.parse_word(word, span).ok()?;
+    }
+    Some(fork)
+}
+
+/// The length of the header, up to the `$end` of `$enddefinitions`, and the position after it.
+fn header_end(bytes: &[u8]) -> Option<(usize, Position)> {
+    let mut tokens = ByteTokenizer::new(bytes);
+    tokens.find(|(token, _)| *token == b"$enddefinitions")?;
+    let (_, span) = tokens.next()?;
+    Some((span.offset + span.len, tokens.position()))
+}
+
+/// Splits the body into at most `threads` pieces. All but the first start at a line starting
+/// with `#` and a digit, which is almost always a timestamp rather than an identifier code.
+fn split(body: &[u8], threads: usize) -> Vec<Range<usize>> {
+    let count = threads.min(body.len() / MIN_PIECE_LEN).max(1);
+    let mut starts = vec![0];
+    for i in 1..count {
+        let from = body.len() / count * i;
+        let start = body[from..]
+            .windows(3)
+            .position(|bytes| bytes[..2] == *b"\n#" && bytes[2].is_ascii_digit())
+            .map(|newline| from + newline + 1);
+        match start {
+            Some(start) if start > *starts.last().unwrap() => starts.push(start),
+            _ => {}
+        }
+    }
+    let ends = starts.iter().skip(1).copied().chain(Some(body.len()));
+    starts
+        .iter()
+        .zip(ends)
+        .map(|(start, end)| *start..end)
+        .collect()
+}
+
+/// Where each piece starts, given where the body does. Lines are counted on several threads.
+fn positions(body: &[u8], pieces: &[Range<usize>], start: Position) -> Vec<Position> {
+    let newlines: Vec<usize> = thread::scope(|scope| {
+        let handles: Vec<_> = pieces
+            .iter()
+            .map(|piece| {
+                scope.spawn(move || body[piece.clone()].iter().filter(|b| **b == b'\n').count())
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect()
+    });
+    let mut position = start;
+    pieces
+        .iter()
+        .zip(newlines)
+        .map(|(piece, newlines)| {
+            let piece_start = position;
+            position = Position {
+                offset: start.offset + piece.end,
+                line: position.line + newlines,
+                column: 1,
+            };
+            piece_start
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::ParseOptions;
+    use crate::warning::{Level, WarningKind};
+    use crate::{load_from_bytes_parallel, load_from_bytes_with_options};
+    use std::fmt::Write;
+
+    /// A dump long enough to be split, with comments, `$dumpoff` blocks, repeated values, and an
+    /// identifier starting with `#` on a line of its own.
+    fn dump(error_at: Option<u64>) -> String {
+        let mut vcd = String::from("$timescale 1 ns $end\n$scope module top $end\n");
+        for identifier in &["!", "\"", "%", "&", "'"] {
+            writeln!(vcd, "$var wire 1 {} s $end", identifier).unwrap();
+        }
+        vcd.push_str("$var wire 4 #x v $end\n$upscope $end\n$enddefinitions $end\n");
+        vcd.push_str("#0\n$dumpvars\n0!\n0\"\n0%\n0&\n0'\nb0 #x\n$end\n");
+        for step in 1..12000 {
+            writeln!(vcd, "#{}", step * 10).unwrap();
+            for (i, identifier) in ["!", "\"", "%", "&", "'"].iter().enumerate() {
+                writeln!(vcd, "{}{}", step / (i as u64 + 2) % 2, identifier).unwrap();
+            }
+            writeln!(vcd, "b{:b}\n#x", step % 16).unwrap();
+            match step {
+                3001 => vcd.push_str("$comment\n#20 is not a time\n$end\n"),
+                7001 => vcd.push_str("$dumpoff\nx!\n$end\n$dumpon\n1!\n$end\n"),
+                _ if Some(step) == error_at => vcd.push_str("1?\n"),
+                _ => {}
+            }
+        }
+        vcd
+    }
+
+    fn assert_same_as_single_threaded(vcd: &str, options: &ParseOptions) {
+        let expected = load_from_bytes_with_options(vcd.as_bytes(), options);
+        for threads in &[3, 8] {
+            let report = load_from_bytes_parallel(vcd.as_bytes(), options, *threads);
+            match (&expected, report) {
+                (Ok(expected), Ok(report)) => {
+                    assert_eq!(report.errors, expected.errors);
+                    assert_eq!(report.warnings, expected.warnings);
+                    assert_eq!(report.vcd.variables, expected.vcd.variables);
+                    assert_eq!(report.vcd.comments, expected.vcd.comments);
+                    assert_eq!(report.vcd.waveforms, expected.vcd.waveforms);
+                }
+                (Err(expected), Err(err)) => assert_eq!(&err, expected),
+                _ => panic!("only one of the loads failed"),
+            }
+        }
+    }
+
+    #[test]
+    fn splits_at_timestamps() {
+        let vcd = dump(None);
+        let pieces = split(vcd.as_bytes(), 4);
+        assert_eq!(pieces.len(), 4);
+        assert_eq!(pieces.last().unwrap().end, vcd.len());
+        for piece in &pieces[1..] {
+            assert_eq!(&vcd[piece.start - 1..piece.start + 1], "\n#");
+        }
+        assert_eq!(split(b"#0\n#1\n", 8), vec![0..6]);
+    }
+
+    #[test]
+    fn same_result_as_single_threaded() {
+        assert_same_as_single_threaded(&dump(None), &ParseOptions::default());
+        let mut options = ParseOptions::default();
+        options
+            .warnings
+            .set(WarningKind::RepeatedValue, Level::Allow);
+        assert_same_as_single_threaded(&dump(None), &options);
+    }
+
+    #[test]
+    fn same_errors_as_single_threaded() {
+        let vcd = dump(Some(5000));
+        assert_same_as_single_threaded(&vcd, &ParseOptions::default());
+        let recovering = ParseOptions {
+            recover: true,
+            ..ParseOptions::default()
+        };
+        assert_same_as_single_threaded(&vcd, &recovering);
+        let mut denying = ParseOptions::default();
+        denying
+            .warnings
+            .set(WarningKind::RepeatedValue, Level::Deny);
+        assert_same_as_single_threaded(&vcd, &denying);
+    }
+}
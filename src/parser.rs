@@ -1,14 +1,260 @@
 use crate::error::LoadError;
 use crate::state_machine::StateMachine;
+use crate::tokenizer::{ByteTokenizer, Position, Span, Tokenizer};
+use crate::visitor::VcdVisitor;
+use std::io::BufRead;
 
-pub fn parse(
-    state_machine: &mut StateMachine,
-    line: String,
-    line_num: usize,
+/// Parses a piece of VCD text that begins at `position` in the whole input.
+pub fn parse<V: VcdVisitor>(
+    state_machine: &mut StateMachine<V>,
+    text: &str,
+    position: Position,
 ) -> Result<(), LoadError> {
-    let words: Vec<_> = line.split(' ').filter(|c| !c.is_empty()).collect();
-    for word in words {
-        state_machine.parse_word(word, line_num)?
+    for token in Tokenizer::resume(text, position) {
+        state_machine.parse_word(token.text, token.span)?
     }
     Ok(())
 }
+
+/// Like [`parse`], but instead of stopping at the first error, records it in `errors`, skips ahead
+/// to the next command or timestamp and keeps going. Only stops for a
+/// [`LoadError::Cancelled`], which can't be recovered from.
+pub fn parse_recovering<V: VcdVisitor>(
+    state_machine: &mut StateMachine<V>,
+    text: &str,
+    position: Position,
+    errors: &mut Vec<LoadError>,
+) -> Result<(), LoadError> {
+    for token in Tokenizer::resume(text, position) {
+        parse_word_recovering(state_machine, token.text, token.span, errors)?
+    }
+    Ok(())
+}
+
+/// Parses VCD held as bytes that begin at `position` in the whole input, such as a file mapped
+/// into memory, without copying them or splitting them into lines. Only tokens are checked to be
+/// UTF-8; one that isn't is a [`LoadError::FileReadError`].
+pub fn parse_bytes<V: VcdVisitor>(
+    state_machine: &mut StateMachine<V>,
+    bytes: &[u8],
+    position: Position,
+) -> Result<(), LoadError> {
+    for (token, span) in ByteTokenizer::resume(bytes, position) {
+        state_machine.parse_word(token_text(token, span)?, span)?
+    }
+    Ok(())
+}
+
+/// Like [`parse_bytes`], but recovers from errors like [`parse_recovering`]. Tokens that aren't
+/// UTF-8 are skipped.
+pub fn parse_bytes_recovering<V: VcdVisitor>(
+    state_machine: &mut StateMachine<V>,
+    bytes: &[u8],
+    position: Position,
+    errors: &mut Vec<LoadError>,
+) -> Result<(), LoadError> {
+    for (token, span) in ByteTokenizer::resume(bytes, position) {
+        match token_text(token, span) {
+            Ok(word) => parse_word_recovering(state_machine, word, span, errors)?,
+            Err(err) => errors.push(err),
+        }
+    }
+    Ok(())
+}
+
+fn token_text(token: &[u8], span: Span) -> Result<&str, LoadError> {
+    std::str::from_utf8(token).map_err(|_| LoadError::FileReadError { span })
+}
+
+fn parse_word_recovering<V: VcdVisitor>(
+    state_machine: &mut StateMachine<V>,
+    word: &str,
+    span: Span,
+    errors: &mut Vec<LoadError>,
+) -> Result<(), LoadError> {
+    if let Err(err) = state_machine.parse_word(word, span) {
+        if let LoadError::Cancelled { .. } = err {
+            return Err(err);
+        }
+        let retry = state_machine.recover(&err, word);
+        errors.push(err);
+        if retry {
+            if let Err(err) = state_machine.parse_word(word, span) {
+                state_machine.recover(&err, word);
+                errors.push(err);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads VCD text line by line, keeping track of the position each line starts at.
+pub struct LineReader<R: BufRead> {
+    reader: R,
+    line: LineBuffer,
+}
+
+impl<R: BufRead> LineReader<R> {
+    pub fn new(reader: R) -> Self {
+        LineReader::resume(reader, Position::new())
+    }
+
+    /// A reader for input that begins at `position` in the whole input, e.g. after seeking.
+    pub fn resume(reader: R, position: Position) -> Self {
+        LineReader {
+            reader,
+            line: LineBuffer::new(position),
+        }
+    }
+
+    /// Reads the next line, including its line terminator, and the position it starts at.
+    /// Returns `None` at the end of the input, or after the underlying reader failed.
+    ///
+    /// A line that isn't valid UTF-8 is reported as a [`LoadError::FileReadError`] and skipped, so
+    /// reading can go on with the next line.
+    pub fn next_line(&mut self) -> Option<Result<(&str, Position), LoadError>> {
+        let buf = self.line.start_line()?;
+        let read = self.reader.read_until(b'\n', buf);
+        self.line.end_line(read)
+    }
+}
+
+/// The line being read by a [`LineReader`], or by a reader of another kind, such as an
+/// asynchronous one, and the position it starts at.
+pub struct LineBuffer {
+    buf: Vec<u8>,
+    position: Position,
+    failed: bool,
+}
+
+impl LineBuffer {
+    pub fn new(position: Position) -> Self {
+        LineBuffer {
+            buf: vec![],
+            position,
+            failed: false,
+        }
+    }
+
+    /// Where the next line starts.
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    /// The buffer to read the next line into with `read_until(b'\n', ..)`, or `None` once
+    /// reading failed.
+    pub fn start_line(&mut self) -> Option<&mut Vec<u8>> {
+        self.buf.clear();
+        match self.failed {
+            true => None,
+            false => Some(&mut self.buf),
+        }
+    }
+
+    /// Takes the line read into the buffer, given what reading it returned, the way
+    /// [`LineReader::next_line`] returns it.
+    pub fn end_line(
+        &mut self,
+        read: std::io::Result<usize>,
+    ) -> Option<Result<(&str, Position), LoadError>> {
+        let start = self.position;
+        let error = LoadError::FileReadError {
+            span: Span::new(start.offset, 0, start.line, start.column),
+        };
+        match read {
+            Ok(0) => return None,
+            Ok(len) => self.advance(len),
+            Err(_) => {
+                self.failed = true;
+                return Some(Err(error));
+            }
+        }
+        match std::str::from_utf8(&self.buf) {
+            Ok(line) => Some(Ok((line, start))),
+            Err(_) => Some(Err(error)),
+        }
+    }
+
+    fn advance(&mut self, len: usize) {
+        self.position.offset += len;
+        match self.buf.last() {
+            Some(b'\n') => {
+                self.position.line += 1;
+                self.position.column = 1;
+            }
+            _ => self.position.column += len,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_reader_positions() {
+        let mut reader = LineReader::new("$date\r\ntoday\n$end".as_bytes());
+        let mut lines = vec![];
+        while let Some(line) = reader.next_line() {
+            let (text, position) = line.unwrap();
+            lines.push((text.to_string(), position));
+        }
+        assert_eq!(
+            lines,
+            vec![
+                ("$date\r\n".to_string(), Position::new()),
+                (
+                    "today\n".to_string(),
+                    Position {
+                        offset: 7,
+                        line: 2,
+                        column: 1
+                    }
+                ),
+                (
+                    "$end".to_string(),
+                    Position {
+                        offset: 13,
+                        line: 3,
+                        column: 1
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_bytes_reports_tokens_that_are_not_utf8() {
+        let text = b"$date today $end\n$version \xff $end\n";
+        let mut state_machine = StateMachine::new();
+        assert_eq!(
+            parse_bytes(&mut state_machine, text, Position::new()).err(),
+            Some(LoadError::FileReadError {
+                span: Span::new(26, 1, 2, 10)
+            })
+        );
+
+        let mut state_machine = StateMachine::new();
+        let mut errors = vec![];
+        parse_bytes_recovering(&mut state_machine, text, Position::new(), &mut errors).unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(state_machine.visitor.vcd.date, "today");
+        assert_eq!(state_machine.visitor.vcd.version, "");
+    }
+
+    #[test]
+    fn line_reader_skips_invalid_utf8() {
+        let mut reader = LineReader::new(&b"$date\n\xff\xfe\n$end\n"[..]);
+        assert_eq!(reader.next_line().unwrap().unwrap().0, "$date\n");
+        assert_eq!(
+            reader.next_line().unwrap().err(),
+            Some(LoadError::FileReadError {
+                span: Span::new(6, 0, 2, 1)
+            })
+        );
+        let (text, position) = reader.next_line().unwrap().unwrap();
+        assert_eq!(text, "$end\n");
+        assert_eq!(position.line, 3);
+        assert!(reader.next_line().is_none());
+    }
+}
@@ -0,0 +1,18 @@
+use crate::error::LoadError;
+use crate::vcd::VCD;
+use crate::warning::Warning;
+
+/// The result of loading a VCD in error-recovery mode: everything that could be parsed, and every
+/// error and warning that was found along the way.
+#[derive(Default, Clone)]
+pub struct LoadReport {
+    pub vcd: VCD,
+    pub errors: Vec<LoadError>,
+    pub warnings: Vec<Warning>,
+}
+
+impl LoadReport {
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
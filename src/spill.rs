@@ -0,0 +1,294 @@
+//! Waveforms kept in a temporary file instead of memory, for dumps whose waveforms don't fit in
+//! memory even as [`CompactWaveform`]s.
+//!
+//! While a file is parsed, the changes parsed so far are written out at regular intervals as
+//! compact pieces of each waveform. Queries read the pieces of a waveform back, and keep the
+//! waveforms used last in memory, up to a budget.
+
+use crate::error::LoadError;
+use crate::options::ParseOptions;
+use crate::parser::LineReader;
+use crate::state_machine::StateMachine;
+use crate::tokenizer::Span;
+use crate::types::compact_waveform::CompactWaveform;
+use crate::types::waveform::{Waveform, WaveformQuery, WaveformStore};
+use crate::vcd::VCD;
+use crate::warning::Warning;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Tells temporary files of the same process apart.
+static NEXT_FILE: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SpillOptions {
+    /// Where the temporary file is made.
+    pub dir: PathBuf,
+    /// How many bytes of input are parsed between two writes of the changes parsed so far.
+    pub spill_interval: u64,
+    /// How many bytes of waveforms read back are kept in memory.
+    pub cache_budget: usize,
+}
+
+impl Default for SpillOptions {
+    fn default() -> Self {
+        SpillOptions {
+            dir: std::env::temp_dir(),
+            spill_interval: 16 * 1024 * 1024,
+            cache_budget: 256 * 1024 * 1024,
+        }
+    }
+}
+
+/// A VCD whose waveforms are in a temporary file, which is deleted when the store is dropped.
+pub struct SpillingStore {
+    /// Everything but the waveforms, which are left empty.
+    pub header: VCD,
+    pub errors: Vec<LoadError>,
+    pub warnings: Vec<Warning>,
+    path: PathBuf,
+    file: RefCell<File>,
+    /// Where the pieces of each waveform are in the file, and how long they are.
+    pieces: HashMap<String, Vec<(u64, usize)>>,
+    cache: RefCell<Cache>,
+}
+
+impl SpillingStore {
+    /// Parses `filename` like [`crate::load_from_file_with_options`], keeping only the changes
+    /// parsed since the last write to the temporary file in memory. Errors found in `filename`
+    /// are wrapped in a [`LoadError::InFile`] naming it.
+    pub fn load_from_file(
+        filename: String,
+        options: &ParseOptions,
+        spill: &SpillOptions,
+    ) -> Result<SpillingStore, LoadError> {
+        let path = spill.dir.join(format!(
+            "vcd_rust_spill_{}_{}",
+            std::process::id(),
+            NEXT_FILE.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .map_err(|e| LoadError::FileOpenError {
+                filename: path.display().to_string(),
+                error: e.to_string(),
+            })?;
+        let mut store = SpillingStore {
+            header: VCD::default(),
+            errors: vec![],
+            warnings: vec![],
+            path,
+            file: RefCell::new(file),
+            pieces: HashMap::new(),
+            cache: RefCell::new(Cache::new(spill.cache_budget)),
+        };
+
+        let mut errors = vec![];
+        let mut reader = match crate::open_file(filename.clone()) {
+            Ok(reader) => LineReader::new(reader),
+            Err(err) => {
+                crate::record(err, options, &mut store.errors)?;
+                return Ok(store);
+            }
+        };
+        let mut state_machine = StateMachine::with_options(options.clone());
+        let mut spilled_at = 0;
+        while let Some(line) = reader.next_line() {
+            let offset = match line {
+                Ok((line, position)) => {
+                    crate::parse_text(&mut state_machine, line, position, options, &mut errors)
+                        .map_err(|err| err.in_file(&filename))?;
+                    (position.offset + line.len()) as u64
+                }
+                Err(err) => {
+                    crate::record(err, options, &mut errors)
+                        .map_err(|err| err.in_file(&filename))?;
+                    continue;
+                }
+            };
+            if offset >= spilled_at + spill.spill_interval
+                && state_machine.at_value_change_boundary()
+            {
+                for (identifier, waveform) in state_machine.drain_changes() {
+                    store.write_piece(identifier, &waveform)?;
+                }
+                spilled_at = offset;
+            }
+        }
+        let mut report = crate::in_file(crate::finish(state_machine, options, errors), &filename)?;
+        for (identifier, waveform) in report.vcd.waveforms.drain() {
+            store.write_piece(identifier, &waveform)?;
+        }
+        store.header = report.vcd;
+        store.errors = report.errors;
+        store.warnings = report.warnings;
+        Ok(store)
+    }
+
+    /// How many bytes of waveforms are in memory.
+    pub fn cached_bytes(&self) -> usize {
+        self.cache.borrow().used
+    }
+
+    fn write_piece(&mut self, identifier: String, waveform: &Waveform) -> Result<(), LoadError> {
+        let mut bytes = vec![];
+        CompactWaveform::from(waveform).write_to(&mut bytes);
+        let file = self.file.get_mut();
+        let offset = file
+            .seek(SeekFrom::End(0))
+            .and_then(|offset| file.write_all(&bytes).map(|_| offset))
+            .map_err(|e| self.file_error(e))?;
+        let pieces = self.pieces.entry(identifier).or_default();
+        pieces.push((offset, bytes.len()));
+        Ok(())
+    }
+
+    /// The waveform of `identifier`, read back from the file unless it's cached.
+    fn waveform(&self, identifier: &str) -> Result<Option<Rc<CompactWaveform>>, LoadError> {
+        let pieces = match self.pieces.get(identifier) {
+            Some(pieces) => pieces,
+            None => return Ok(None),
+        };
+        if let Some(waveform) = self.cache.borrow_mut().get(identifier) {
+            return Ok(Some(waveform));
+        }
+        let mut waveform = CompactWaveform::new();
+        let mut file = self.file.borrow_mut();
+        for (offset, len) in pieces {
+            let mut bytes = vec![0; *len];
+            file.seek(SeekFrom::Start(*offset))
+                .and_then(|_| file.read_exact(&mut bytes))
+                .map_err(|_| LoadError::FileReadError {
+                    span: Span::new(*offset as usize, *len, 1, 1),
+                })?;
+            let piece = CompactWaveform::read_from(&bytes, &mut 0).ok_or_else(|| {
+                LoadError::InvalidBinaryFile {
+                    format: "spill file".to_string(),
+                    offset: *offset as usize,
+                    reason: "invalid waveform".to_string(),
+                }
+            })?;
+            match waveform.is_empty() {
+                true => waveform = piece,
+                false => piece
+                    .iter()
+                    .for_each(|(time, value)| waveform.push(time, value)),
+            }
+        }
+        waveform.shrink_to_fit();
+        let waveform = Rc::new(waveform);
+        let cached = Rc::clone(&waveform);
+        self.cache
+            .borrow_mut()
+            .insert(identifier.to_string(), cached);
+        Ok(Some(waveform))
+    }
+
+    fn file_error(&self, e: std::io::Error) -> LoadError {
+        LoadError::FileOpenError {
+            filename: self.path.display().to_string(),
+            error: e.to_string(),
+        }
+    }
+}
+
+impl WaveformStore for SpillingStore {
+    fn identifiers(&self) -> Vec<String> {
+        self.pieces.keys().cloned().collect()
+    }
+
+    fn query<R>(
+        &self,
+        identifier: &str,
+        query: impl FnOnce(&dyn WaveformQuery) -> R,
+    ) -> Result<Option<R>, LoadError> {
+        Ok(self.waveform(identifier)?.map(|waveform| query(&*waveform)))
+    }
+}
+
+impl Drop for SpillingStore {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Waveforms read back from the file. Once they take more than the budget, the least recently
+/// used ones are dropped, but for the one used last.
+struct Cache {
+    budget: usize,
+    used: usize,
+    clock: u64,
+    waveforms: HashMap<String, (u64, Rc<CompactWaveform>)>,
+    /// The identifiers of the waveforms, by when they were last used.
+    by_use: BTreeMap<u64, String>,
+}
+
+impl Cache {
+    fn new(budget: usize) -> Self {
+        Cache {
+            budget,
+            used: 0,
+            clock: 0,
+            waveforms: HashMap::new(),
+            by_use: BTreeMap::new(),
+        }
+    }
+
+    fn get(&mut self, identifier: &str) -> Option<Rc<CompactWaveform>> {
+        self.clock += 1;
+        let (used_at, waveform) = self.waveforms.get_mut(identifier)?;
+        let identifier = self.by_use.remove(used_at)?;
+        *used_at = self.clock;
+        self.by_use.insert(self.clock, identifier);
+        Some(Rc::clone(waveform))
+    }
+
+    fn insert(&mut self, identifier: String, waveform: Rc<CompactWaveform>) {
+        self.clock += 1;
+        self.used += waveform.memory_usage();
+        self.by_use.insert(self.clock, identifier.clone());
+        self.waveforms.insert(identifier, (self.clock, waveform));
+        while self.used > self.budget && self.by_use.len() > 1 {
+            let (_, oldest) = self.by_use.pop_first().unwrap();
+            let (_, waveform) = self.waveforms.remove(&oldest).unwrap();
+            self.used -= waveform.memory_usage();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn least_recently_used_waveforms_are_dropped() {
+        let waveform = |changes: u64| {
+            let mut waveform = CompactWaveform::new();
+            for time in 0..changes {
+                waveform.push(time, crate::types::value::Value::Real(time as f64));
+            }
+            Rc::new(waveform)
+        };
+        let size = waveform(100).memory_usage();
+        let mut cache = Cache::new(size * 2);
+        cache.insert("a".to_string(), waveform(100));
+        cache.insert("b".to_string(), waveform(100));
+        assert!(cache.get("a").is_some());
+        cache.insert("c".to_string(), waveform(100));
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("a").is_some());
+        assert_eq!(cache.used, size * 2);
+
+        cache.insert("big".to_string(), waveform(1000));
+        assert_eq!(cache.waveforms.len(), 1);
+        assert!(cache.get("big").is_some());
+    }
+}
@@ -1,11 +1,26 @@
+use crate::dumped_var_parser::{
+    get_states_from_port, get_strengths_from_port, get_value_from_vector, parse_scalar,
+};
 use crate::error::LoadError;
+use crate::options::{ParseOptions, Progress};
 use crate::string_helpers::append_word;
-use crate::types::{scope::Scope, variable::Variable};
-use crate::vcd::VCD;
+use crate::tokenizer::Span;
+use crate::types::{
+    attribute::Attribute,
+    scope::Scope,
+    timescale::TimeScale,
+    unknown_command::UnknownCommand,
+    value::{PortState, PortValue, Strength, Value},
+    variable::Variable,
+    waveform::Waveform,
+};
+use crate::visitor::{DumpCommand, VcdBuilder, VcdVisitor};
+use crate::warning::{Level, Warning, WarningKind};
 use std::collections::HashMap;
+use std::ops::ControlFlow;
 use std::str::FromStr;
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, EnumString, ToString)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, EnumString, Display)]
 enum ParserState {
     #[strum(serialize = "end")]
     End,
@@ -33,35 +48,345 @@ enum ParserState {
     DumpVars,
     #[strum(serialize = "enddefinitions")]
     EndDefinitions,
+    #[strum(serialize = "dumpports")]
+    DumpPorts,
+    #[strum(serialize = "dumpportsoff")]
+    DumpPortsOff,
+    #[strum(serialize = "dumpportson")]
+    DumpPortsOn,
+    #[strum(serialize = "dumpportsall")]
+    DumpPortsAll,
+    #[strum(serialize = "vcdclose")]
+    VcdClose,
+    #[strum(serialize = "attrbegin")]
+    AttrBegin,
+    #[strum(serialize = "attrend")]
+    AttrEnd,
+    /// Any other command. Its name is kept in [`StateMachine::unknown_command`].
+    #[strum(disabled)]
+    Unknown,
 }
 
-pub struct StateMachine {
-    pub vcd: VCD,
+impl ParserState {
+    fn dump_command(&self) -> Option<DumpCommand> {
+        use ParserState::*;
+        match self {
+            DumpAll => Some(DumpCommand::DumpAll),
+            DumpOff => Some(DumpCommand::DumpOff),
+            DumpOn => Some(DumpCommand::DumpOn),
+            DumpVars => Some(DumpCommand::DumpVars),
+            DumpPorts => Some(DumpCommand::DumpPorts),
+            DumpPortsOff => Some(DumpCommand::DumpPortsOff),
+            DumpPortsOn => Some(DumpCommand::DumpPortsOn),
+            DumpPortsAll => Some(DumpCommand::DumpPortsAll),
+            _ => None,
+        }
+    }
+
+    /// Commands that only exist in extended VCD.
+    fn is_extended(&self) -> bool {
+        use ParserState::*;
+        matches!(
+            self,
+            DumpPorts | DumpPortsOff | DumpPortsOn | DumpPortsAll | VcdClose
+        )
+    }
+}
+
+/// A value change whose first word has been read, but which still needs more words.
+enum PendingChange {
+    /// A vector or real value, waiting for its identifier.
+    Value(Value),
+    /// The states of a port, waiting for its two strength components and its identifier.
+    Port {
+        states: Vec<PortState>,
+        strengths: Vec<Vec<Strength>>,
+    },
+}
+
+/// A declared variable whose value changes are being parsed.
+struct Signal {
+    bit_width: usize,
+    reference: String,
+    /// Where the variable was first declared.
+    declared_at: Span,
+    /// The value of the last change, to tell changes that repeat it.
+    last_value: Option<Value>,
+    /// Where the first change is, for [`StateMachine::merge`].
+    first_span: Span,
+}
+
+impl Signal {
+    fn new(variable: &Variable, declared_at: Span) -> Self {
+        Signal {
+            bit_width: variable.bit_width,
+            reference: variable.reference.clone(),
+            declared_at,
+            last_value: None,
+            first_span: Span::default(),
+        }
+    }
+
+    /// Keeps a copy of `value` as the last value, reusing the memory of the one before.
+    fn set_last_value(&mut self, value: &Value) {
+        match (&mut self.last_value, value) {
+            (Some(Value::Vector(last)), Value::Vector(bits)) => last.clone_from(bits),
+            (Some(Value::Port(last)), Value::Port(ports)) => last.clone_from(ports),
+            (last, _) => *last = Some(value.clone()),
+        }
+    }
+
+    /// The same variable, with none of its changes parsed yet.
+    fn fork(&self) -> Self {
+        Signal {
+            bit_width: self.bit_width,
+            reference: self.reference.clone(),
+            declared_at: self.declared_at,
+            last_value: None,
+            first_span: Span::default(),
+        }
+    }
+}
+
+/// Tokenized VCD goes through the machine a word at a time. It checks that the words make up a
+/// valid VCD and hands the commands and value changes they make up to its visitor.
+pub struct StateMachine<V: VcdVisitor = VcdBuilder> {
+    pub visitor: V,
+    pub warnings: Vec<Warning>,
+    options: ParseOptions,
     scope: Scope,
     var: Variable,
-    comment: String,
+    /// The words of the `$comment`, `$date` or `$version` being parsed.
+    text: String,
+    /// The `$timescale` being parsed.
+    timescale: TimeScale,
     scope_stack: Vec<Scope>,
     state: ParserState,
     singular_commands_seen: HashMap<ParserState, bool>,
+    last_span: Span,
+    command_span: Span,
+    command_params: usize,
+    resyncing: bool,
+    definitions_done: bool,
+    unknown_command: UnknownCommand,
+    attribute: Attribute,
+    pending_attributes: Vec<Attribute>,
+    time: u64,
+    pending_value: Option<(PendingChange, Span)>,
+    /// Identifier codes interned as indexes into `signals`, so value changes look them up once
+    /// and never allocate. The indexes are the ones the visitor is given.
+    identifiers: HashMap<String, usize>,
+    signals: Vec<Signal>,
+    /// The offset past which progress is reported next, if there's a hook to report it to.
+    next_progress: Option<u64>,
 }
 
 impl Default for StateMachine {
     fn default() -> Self {
+        StateMachine::with_options(ParseOptions::default())
+    }
+}
+
+/// What only a machine building a [`VCD`](crate::vcd::VCD) can do, as it needs the changes parsed so far.
+impl StateMachine {
+    pub fn new() -> Self {
+        StateMachine::default()
+    }
+
+    pub fn with_options(options: ParseOptions) -> Self {
+        StateMachine::with_visitor(VcdBuilder::new(), options)
+    }
+
+    /// The changes of each variable parsed so far, until [`StateMachine::cleanup`] moves them
+    /// into the VCD.
+    pub fn waveforms(&self) -> impl Iterator<Item = (&str, &Waveform)> {
+        self.visitor.waveforms()
+    }
+
+    /// A machine that parses a later piece of the value change section, e.g. on another thread,
+    /// with the variables of this one. The piece can hold nothing but value changes and
+    /// timestamps; what it parsed is added back with [`StateMachine::merge`]. Forks don't report
+    /// progress, as they parse out of order; the machine they are merged into does.
+    pub fn fork(&self) -> StateMachine {
         StateMachine {
-            state: ParserState::End,
+            definitions_done: true,
+            time: self.time,
+            identifiers: self.identifiers.clone(),
+            signals: self.signals.iter().map(Signal::fork).collect(),
+            next_progress: None,
+            ..StateMachine::with_visitor(self.visitor.fork(), self.options.clone())
+        }
+    }
+
+    /// Adds what `fork` parsed right after the input of this machine, which must be at a value
+    /// change boundary, as if this machine had parsed it too. Changes that repeat the last value
+    /// before the fork took over are only found now, and are warned about here. If one of those
+    /// warnings is denied, nothing is merged and false is returned; the piece has to be parsed
+    /// by this machine instead to fail at the right place.
+    pub fn merge(&mut self, mut fork: StateMachine) -> bool {
+        let mut repeated = vec![];
+        for (identifier, index) in &self.identifiers {
+            let changes = fork.visitor.waveform(*index).changes();
+            let first_value = changes.first().map(|(_, value)| value);
+            if first_value.is_some() && self.signals[*index].last_value.as_ref() == first_value {
+                let span = fork.signals[*index].first_span;
+                repeated.push(Self::repeated_value(identifier, span));
+            }
+        }
+        let level = self.options.warnings.level(WarningKind::RepeatedValue);
+        match level {
+            Level::Deny if !repeated.is_empty() => return false,
+            Level::Warn => {
+                repeated.sort_by_key(|warning| warning.span.offset);
+                let mut merged = vec![];
+                let mut fork_warnings = fork.warnings.into_iter().peekable();
+                for warning in repeated {
+                    while let Some(earlier) =
+                        fork_warnings.next_if(|earlier| earlier.span.offset < warning.span.offset)
+                    {
+                        merged.push(earlier);
+                    }
+                    merged.push(warning);
+                }
+                merged.extend(fork_warnings);
+                self.warnings.extend(merged);
+            }
+            _ => self.warnings.extend(fork.warnings),
+        }
+        for (index, (signal, forked)) in self.signals.iter_mut().zip(fork.signals).enumerate() {
+            if signal.last_value.is_none() {
+                signal.first_span = forked.first_span;
+            }
+            if forked.last_value.is_some() {
+                signal.last_value = forked.last_value;
+            }
+            let waveform = std::mem::take(fork.visitor.waveform_mut(index));
+            self.visitor.waveform_mut(index).extend(waveform);
+        }
+        self.time = fork.time;
+        self.last_span = fork.last_span;
+        self.pending_value = fork.pending_value;
+        true
+    }
+
+    /// The last change of every variable that has been dumped: its identifier, time and value.
+    pub fn last_changes(&self) -> Vec<(String, u64, Value)> {
+        let mut changes: Vec<_> = self
+            .visitor
+            .waveforms()
+            .filter_map(|(identifier, waveform)| {
+                let (time, value) = waveform.changes().last()?;
+                Some((identifier.to_string(), *time, value.clone()))
+            })
+            .collect();
+        changes.sort_by(|a, b| a.0.cmp(&b.0));
+        changes
+    }
+
+    /// Forgets all but the last change of every variable, to go through long inputs without
+    /// keeping all of them.
+    pub fn forget_changes(&mut self) {
+        for index in 0..self.signals.len() {
+            self.visitor.waveform_mut(index).forget_all_but_last();
+        }
+    }
+
+    /// Starts the variables off with changes taken earlier with [`StateMachine::last_changes`],
+    /// e.g. to parse from a point in the value change section instead of from the start.
+    pub fn restore(&mut self, time: u64, changes: &[(String, u64, Value)]) {
+        for (identifier, time, value) in changes {
+            if let Some(index) = self.identifiers.get(identifier) {
+                self.signals[*index].last_value = Some(value.clone());
+                self.visitor.waveform_mut(*index).push(*time, value.clone());
+            }
+        }
+        self.time = time;
+    }
+
+    /// Takes all but the last change of every variable out, e.g. to store them elsewhere while
+    /// going through long inputs. The last changes stay, to tell later changes that repeat them.
+    pub fn drain_changes(&mut self) -> Vec<(String, Waveform)> {
+        let visitor = &mut self.visitor;
+        self.identifiers
+            .iter()
+            .map(|(identifier, index)| {
+                let earlier = visitor.waveform_mut(*index).take_all_but_last();
+                (identifier.clone(), earlier)
+            })
+            .filter(|(_, earlier)| !earlier.is_empty())
+            .collect()
+    }
+
+    /// Takes the changes of the variable with identifier `identifier` parsed so far, if there
+    /// are any.
+    pub fn take_waveform(&mut self, identifier: &str) -> Option<Waveform> {
+        let index = *self.identifiers.get(identifier)?;
+        let waveform = std::mem::take(self.visitor.waveform_mut(index));
+        match waveform.is_empty() {
+            true => None,
+            false => Some(waveform),
+        }
+    }
+
+    /// Commands are a `$` followed by a name. Identifier codes can start with `$` too, as in
+    /// those `fst2vcd` writes, but never with a letter after it.
+    pub fn is_cmd(word: &str) -> bool {
+        let mut chars = word.chars();
+        chars.next() == Some('$') && chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+    }
+}
+
+impl<V: VcdVisitor> StateMachine<V> {
+    /// A machine handing what it parses to `visitor`.
+    pub fn with_visitor(visitor: V, options: ParseOptions) -> Self {
+        StateMachine {
+            visitor,
+            warnings: vec![],
+            next_progress: options.progress.as_ref().map(|_| 0),
+            options,
             scope: Scope::new(),
             var: Variable::default(),
-            comment: String::new(),
+            text: String::new(),
+            timescale: TimeScale::default(),
             scope_stack: vec![],
-            vcd: VCD::default(),
-            singular_commands_seen: StateMachine::get_singular_commands_seen(),
+            state: ParserState::End,
+            singular_commands_seen: Self::get_singular_commands_seen(),
+            last_span: Span::default(),
+            command_span: Span::default(),
+            command_params: 0,
+            resyncing: false,
+            definitions_done: false,
+            unknown_command: UnknownCommand::default(),
+            attribute: Attribute::default(),
+            pending_attributes: vec![],
+            time: 0,
+            pending_value: None,
+            identifiers: HashMap::new(),
+            signals: vec![],
         }
     }
-}
 
-impl StateMachine {
-    pub fn new() -> Self {
-        StateMachine::default()
+    pub fn options(&self) -> &ParseOptions {
+        &self.options
+    }
+
+    /// Whether `$enddefinitions` has been parsed.
+    pub fn definitions_done(&self) -> bool {
+        self.definitions_done
+    }
+
+    /// The time of the last timestamp.
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+
+    /// Whether the machine is between two value changes of the value change section, where a
+    /// machine made by [`StateMachine::fork`] can take over.
+    pub fn at_value_change_boundary(&self) -> bool {
+        self.definitions_done
+            && self.state == ParserState::End
+            && self.pending_value.is_none()
+            && !self.resyncing
     }
 
     fn get_singular_commands_seen() -> HashMap<ParserState, bool> {
@@ -73,35 +398,152 @@ impl StateMachine {
         map
     }
 
-    pub fn parse_word(&mut self, word: &str, line_num: usize) -> Result<(), LoadError> {
-        if StateMachine::is_cmd(&word) {
-            self.try_transition(word, line_num)?;
+    pub fn parse_word(&mut self, word: &str, span: Span) -> Result<(), LoadError> {
+        if self.options.header_only && self.at_value_change_boundary() {
+            return Ok(());
+        }
+        self.last_span = span;
+        self.parse_spanned_word(word, span)?;
+        self.report_progress()
+    }
+
+    fn parse_spanned_word(&mut self, word: &str, span: Span) -> Result<(), LoadError> {
+        if self.resyncing {
+            if !Self::is_resync_point(word) {
+                return Ok(());
+            }
+            self.resyncing = false;
+            if word == "$end" {
+                return Ok(());
+            }
+        }
+        if StateMachine::is_cmd(word) && !self.is_identifier_slot(word) {
+            self.try_transition(word, span)?;
         } else {
-            self.do_work(word, line_num)?;
+            self.do_work(word, span)?;
         }
         Ok(())
     }
 
-    fn try_transition(&mut self, cmd: &str, line_num: usize) -> Result<(), LoadError> {
+    /// Whether `word` is an identifier code, even though it looks like a command: the identifier
+    /// of a `$var` always is, and the word after a vector, real or port value is if it was
+    /// declared. Codes like `$a` are what `fst2vcd` gives to some handles.
+    fn is_identifier_slot(&self, word: &str) -> bool {
+        match &self.pending_value {
+            Some((PendingChange::Value(_), _)) => self.identifiers.contains_key(word),
+            Some((PendingChange::Port { strengths, .. }, _)) => {
+                strengths.len() == 2 && self.identifiers.contains_key(word)
+            }
+            None => self.state == ParserState::Var && self.var.expects_identifier(),
+        }
+    }
+
+    /// Tells the progress hook of the options how far parsing got, up to the end of the last word
+    /// parsed, if it hasn't been told for a while. Fails with [`LoadError::Cancelled`] if the hook
+    /// asks to stop.
+    pub fn report_progress(&mut self) -> Result<(), LoadError> {
+        let offset = (self.last_span.offset + self.last_span.len) as u64;
+        match (self.next_progress, &self.options.progress) {
+            (Some(next), Some(hook)) if offset >= next => {
+                self.next_progress = Some(offset + hook.every());
+                let progress = Progress {
+                    bytes: offset,
+                    lines: self.last_span.line.saturating_sub(1) as u64,
+                    time: self.time,
+                };
+                match hook.report(&progress) {
+                    ControlFlow::Continue(()) => Ok(()),
+                    ControlFlow::Break(()) => Err(LoadError::Cancelled { offset }),
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Puts the state machine back into a consistent state after `word` caused `error`, dropping
+    /// whatever command was being parsed. Returns true if `word` starts a new command and should be
+    /// parsed again. Otherwise the rest of the broken command is skipped, up to its `$end` or the
+    /// next command or timestamp.
+    pub fn recover(&mut self, error: &LoadError, word: &str) -> bool {
+        match self.state {
+            // Keep the scope so the following $upscope still has something to pop.
+            ParserState::Scope => self.push_to_scope_stack(),
+            // Keep what was parsed of header commands.
+            ParserState::Date => self.visitor.on_date(&self.text),
+            ParserState::Version => self.visitor.on_version(&self.text),
+            ParserState::Timescale => self.visitor.on_timescale(&self.timescale),
+            _ => {}
+        }
+        self.scope = Scope::new();
+        self.var = Variable::default();
+        self.text = String::new();
+        self.timescale = TimeScale::default();
+        self.unknown_command = UnknownCommand::default();
+        self.attribute = Attribute::default();
+        self.pending_value = None;
+        self.state = ParserState::End;
+        match (error, word) {
+            (_, "$end") => false,
+            (LoadError::MissingEnd { .. }, _) if StateMachine::is_cmd(word) => true,
+            _ => {
+                self.resyncing = true;
+                false
+            }
+        }
+    }
+
+    fn try_transition(&mut self, cmd: &str, span: Span) -> Result<(), LoadError> {
         let cmd_wo_dollar = &cmd[1..];
-        let next_state = ParserState::from_str(cmd_wo_dollar).unwrap();
+        let next_state = ParserState::from_str(cmd_wo_dollar).unwrap_or(ParserState::Unknown);
+        self.check_if_value_change_is_incomplete()?;
         self.state = match self.state {
             ParserState::End => {
-                self.check_if_end_followed_by_end(line_num, next_state)?;
-                self.check_if_invalid_multiple_command(line_num, next_state)?;
+                self.check_if_end_followed_by_end(span, next_state)?;
+                self.check_if_invalid_multiple_command(span, next_state)?;
+                self.check_if_command_is_supported(span, next_state)?;
                 if next_state == ParserState::Var {
-                    self.update_variable_scope(line_num, next_state)?;
+                    self.update_variable_scope(span, next_state)?;
+                    self.var.attributes = std::mem::take(&mut self.pending_attributes);
+                }
+                if next_state == ParserState::Scope {
+                    self.scope.attributes = std::mem::take(&mut self.pending_attributes);
+                }
+                if next_state == ParserState::Comment && self.definitions_done {
+                    self.warn(
+                        WarningKind::CommentInValueChanges,
+                        span,
+                        "$comment in the value change section".to_string(),
+                    )?;
+                }
+                if next_state == ParserState::Unknown {
+                    self.unknown_command = UnknownCommand::new(cmd, span);
                 }
+                if let Some(command) = next_state.dump_command() {
+                    self.visitor.on_dump_command(command);
+                }
+                if next_state == ParserState::Timescale {
+                    self.timescale = TimeScale::default();
+                }
+                self.text.clear();
+                self.command_span = span;
+                self.command_params = 0;
                 next_state
             }
             _ => {
-                self.check_if_missing_end(line_num, next_state)?;
+                self.check_if_missing_end(span, next_state)?;
 
                 match self.state {
-                    ParserState::Var => self.append_variable(line_num)?,
+                    ParserState::Var => self.append_variable(span)?,
                     ParserState::Comment => self.append_comment(),
                     ParserState::Scope => self.push_to_scope_stack(),
-                    ParserState::UpScope => self.pop_from_scope_stack(line_num)?,
+                    ParserState::UpScope => self.pop_from_scope_stack(span)?,
+                    ParserState::Date => self.append_date()?,
+                    ParserState::Version => self.visitor.on_version(&self.text),
+                    ParserState::Timescale => self.visitor.on_timescale(&self.timescale),
+                    ParserState::EndDefinitions => self.end_definitions(span)?,
+                    ParserState::Unknown => self.append_unknown_command()?,
+                    ParserState::AttrBegin => self.append_attribute(span)?,
+                    ParserState::AttrEnd => self.pending_attributes.clear(),
                     _ => {}
                 }
 
@@ -111,156 +553,441 @@ impl StateMachine {
         Ok(())
     }
 
+    /// The name of the command being parsed, without its `$`.
+    fn command_name(&self) -> String {
+        match self.state {
+            ParserState::Unknown => self.unknown_command.command[1..].to_string(),
+            state => state.to_string(),
+        }
+    }
+
     fn check_if_end_followed_by_end(
         &mut self,
-        line_num: usize,
+        span: Span,
         state: ParserState,
     ) -> Result<(), LoadError> {
         match state {
-            ParserState::End => Err(LoadError::DanglingEnd { line: line_num }),
+            ParserState::End if self.options.checks.matched_end => {
+                Err(LoadError::DanglingEnd { span })
+            }
             _ => Ok(()),
         }
     }
 
-    fn check_if_missing_end(
-        &mut self,
-        line_num: usize,
-        state: ParserState,
-    ) -> Result<(), LoadError> {
+    fn check_if_missing_end(&mut self, span: Span, state: ParserState) -> Result<(), LoadError> {
         match state {
             ParserState::End => Ok(()),
             _ => Err(LoadError::MissingEnd {
-                line: line_num,
-                command: self.state.to_string(),
+                span,
+                command: self.command_name(),
             }),
         }
     }
 
     fn check_if_invalid_multiple_command(
         &mut self,
-        line_num: usize,
+        span: Span,
         state: ParserState,
     ) -> Result<(), LoadError> {
         if let Some(seen) = self.singular_commands_seen.get(&state) {
             match seen {
-                true => {
+                true if self.options.checks.single_header_commands => {
                     return Err(LoadError::InvalidMultipleCommand {
-                        line: line_num,
+                        span,
                         command: state.to_string(),
                     })
                 }
+                true => {}
                 false => *self.singular_commands_seen.get_mut(&state).unwrap() = true,
             }
         }
         Ok(())
     }
 
-    pub fn cleanup(&self, line_num: usize) -> Result<(), LoadError> {
+    fn check_if_command_is_supported(
+        &mut self,
+        span: Span,
+        state: ParserState,
+    ) -> Result<(), LoadError> {
+        match state.is_extended() && !self.options.profile.has_extended_vcd() {
+            true => Err(LoadError::UnsupportedCommand {
+                span,
+                command: format!("${}", state),
+            }),
+            false => Ok(()),
+        }
+    }
+
+    pub fn cleanup(&mut self) -> Result<(), LoadError> {
+        self.visitor.on_finish();
         match self.state {
-            ParserState::End | ParserState::DumpVars => {}
+            ParserState::End | ParserState::DumpVars | ParserState::DumpPorts => {}
             _ => {
                 return Err(LoadError::MissingEnd {
-                    line: line_num,
-                    command: self.state.to_string(),
+                    span: self.last_span,
+                    command: self.command_name(),
                 })
             }
         }
+        self.check_if_value_change_is_incomplete()?;
+        let input_is_empty = self.last_span.len == 0;
+        if !self.definitions_done && !input_is_empty {
+            self.check_if_timescale_is_missing(self.last_span)?;
+        }
+        match self.options.header_only {
+            true => Ok(()),
+            false => self.check_for_variables_never_dumped(),
+        }
+    }
+
+    fn warn(&mut self, kind: WarningKind, span: Span, message: String) -> Result<(), LoadError> {
+        let warning = Warning::new(kind, span, message);
+        match self.options.warnings.level(kind) {
+            Level::Allow => Ok(()),
+            Level::Warn => {
+                self.warnings.push(warning);
+                Ok(())
+            }
+            Level::Deny => Err(LoadError::DeniedWarning { warning }),
+        }
+    }
+
+    fn append_date(&mut self) -> Result<(), LoadError> {
+        self.visitor.on_date(&self.text);
+        match self.text.is_empty() {
+            true => self.warn(
+                WarningKind::EmptyDate,
+                self.command_span,
+                "$date is empty".to_string(),
+            ),
+            false => Ok(()),
+        }
+    }
+
+    /// Keeps a finished attribute for the next `$scope` or `$var`. Enum table definitions aren't
+    /// attached to anything; variables refer to them by handle.
+    fn append_attribute(&mut self, span: Span) -> Result<(), LoadError> {
+        let mut attribute = std::mem::take(&mut self.attribute);
+        attribute.finish(span)?;
+        match attribute.enum_table() {
+            Some(table) => self.visitor.on_enum_table(attribute.argument, &table),
+            None => self.pending_attributes.push(attribute),
+        }
         Ok(())
     }
 
-    fn append_variable(&mut self, line_num: usize) -> Result<(), LoadError> {
-        self.check_if_var_is_done(line_num)?;
-        self.vcd
-            .variables
-            .insert(self.var.ascii_identifier.clone(), self.var.clone());
-        self.var = Variable::default();
+    fn append_unknown_command(&mut self) -> Result<(), LoadError> {
+        let unknown_command = std::mem::take(&mut self.unknown_command);
+        let message = format!("Unknown command {}, skipped", unknown_command.command);
+        self.visitor.on_unknown_command(&unknown_command);
+        self.warn(WarningKind::UnknownCommand, self.command_span, message)
+    }
+
+    fn end_definitions(&mut self, span: Span) -> Result<(), LoadError> {
+        self.definitions_done = true;
+        self.visitor.on_enddefinitions();
+        self.check_if_timescale_is_missing(span)
+    }
+
+    fn check_if_timescale_is_missing(&mut self, span: Span) -> Result<(), LoadError> {
+        match self.singular_commands_seen[&ParserState::Timescale] {
+            true => Ok(()),
+            false => self.warn(
+                WarningKind::MissingTimescale,
+                span,
+                "No $timescale, defaulting to 0 ms".to_string(),
+            ),
+        }
+    }
+
+    fn check_for_variables_never_dumped(&mut self) -> Result<(), LoadError> {
+        let mut never_dumped: Vec<(String, Span, String)> = self
+            .identifiers
+            .iter()
+            .map(|(identifier, index)| (identifier, &self.signals[*index]))
+            .filter(|(_, signal)| signal.last_value.is_none())
+            .map(|(identifier, signal)| {
+                let reference = signal.reference.clone();
+                (identifier.clone(), signal.declared_at, reference)
+            })
+            .collect();
+        never_dumped.sort_by_key(|(_, span, _)| span.offset);
+        for (identifier, span, reference) in never_dumped {
+            self.warn(
+                WarningKind::NeverDumped,
+                span,
+                format!(
+                    "Variable {} ({}) is declared but never dumped",
+                    reference, identifier
+                ),
+            )?;
+        }
         Ok(())
     }
 
-    fn check_if_var_is_done(&mut self, line_num: usize) -> Result<(), LoadError> {
+    fn check_if_value_change_is_incomplete(&mut self) -> Result<(), LoadError> {
+        match self.pending_value.take() {
+            Some((_, span)) => Err(LoadError::InvalidVarDump { span }),
+            None => Ok(()),
+        }
+    }
+
+    fn append_variable(&mut self, span: Span) -> Result<(), LoadError> {
+        self.check_if_var_is_done(span)?;
+        let var = std::mem::take(&mut self.var);
+        let index = match self.identifiers.get(&var.ascii_identifier) {
+            Some(index) => *index,
+            None => {
+                let index = self.signals.len();
+                self.identifiers.insert(var.ascii_identifier.clone(), index);
+                self.signals.push(Signal::new(&var, self.command_span));
+                index
+            }
+        };
+        self.visitor.on_var(index, &var);
+        Ok(())
+    }
+
+    fn check_if_var_is_done(&mut self, span: Span) -> Result<(), LoadError> {
         match self.var.is_done() {
             true => Ok(()),
             false => Err(LoadError::TooFewParameters {
-                line: line_num,
+                span,
                 command: "var".to_string(),
             }),
         }
     }
 
-    fn update_variable_scope(
-        &mut self,
-        line_num: usize,
-        state: ParserState,
-    ) -> Result<(), LoadError> {
-        self.check_if_scope_stack_is_empty(line_num, state)?;
+    fn update_variable_scope(&mut self, span: Span, state: ParserState) -> Result<(), LoadError> {
+        self.check_if_scope_stack_is_empty(span, state)?;
         self.var.scope = self.scope_stack.clone();
         Ok(())
     }
 
     fn append_comment(&mut self) {
-        self.vcd.comments.push(self.comment.clone());
-        self.comment = String::new();
+        self.visitor.on_comment(&self.text);
     }
 
     fn push_to_scope_stack(&mut self) {
-        self.scope_stack.push(self.scope.clone());
-        self.scope = Scope::new();
+        self.visitor.on_scope(&self.scope);
+        self.scope_stack.push(std::mem::take(&mut self.scope));
     }
 
-    fn pop_from_scope_stack(&mut self, line_num: usize) -> Result<(), LoadError> {
-        self.check_if_scope_stack_is_empty(line_num, self.state)?;
+    fn pop_from_scope_stack(&mut self, span: Span) -> Result<(), LoadError> {
+        self.check_if_scope_stack_is_empty(span, self.state)?;
         self.scope_stack.pop();
+        self.visitor.on_upscope();
         Ok(())
     }
 
     fn check_if_scope_stack_is_empty(
         &mut self,
-        line_num: usize,
+        span: Span,
         state: ParserState,
     ) -> Result<(), LoadError> {
         match self.scope_stack.is_empty() {
             true => Err(LoadError::ScopeStackEmpty {
-                line: line_num,
+                span,
                 command: state.to_string(),
             }),
             false => Ok(()),
         }
     }
 
-    fn do_work(&mut self, word: &str, line_num: usize) -> Result<(), LoadError> {
+    fn do_work(&mut self, word: &str, span: Span) -> Result<(), LoadError> {
         use ParserState::*;
         match self.state {
-            Comment => append_word(&mut self.comment, word),
-            Date => append_word(&mut self.vcd.date, word),
-            Version => append_word(&mut self.vcd.version, word),
-            Timescale => self.vcd.timescale.append(word, line_num)?,
-            Scope => self.scope.append(word, line_num)?,
-            Var => self.var.append(word, line_num)?,
-            DumpAll => {}
-            DumpOff => {}
-            DumpOn => {}
-            DumpVars => {}
-            EndDefinitions | UpScope => {
-                StateMachine::raise_invalid_param(self.state.to_string(), line_num, word)?
+            Comment | Date | Version => append_word(&mut self.text, word),
+            Unknown => self.unknown_command.append(word),
+            AttrBegin => self.attribute.append(word, span)?,
+            Timescale => self.append_timescale(word, span)?,
+            Scope => self.append_scope(word, span)?,
+            Var => self.append_var(word, span)?,
+            DumpAll | DumpOff | DumpOn | DumpVars | DumpPorts | DumpPortsOff | DumpPortsOn
+            | DumpPortsAll => self.parse_value_change(word, span)?,
+            VcdClose => self.time = Self::parse_time(word, span)?,
+            EndDefinitions | UpScope | AttrEnd => {
+                Self::raise_invalid_param(self.state.to_string(), span, word)?
             }
+            End if self.definitions_done => self.parse_value_change(word, span)?,
             _ => {}
         }
+        self.command_params += 1;
         Ok(())
     }
 
-    fn raise_invalid_param(
-        command: String,
-        line_num: usize,
-        parameter: &str,
+    fn append_timescale(&mut self, word: &str, span: Span) -> Result<(), LoadError> {
+        match Self::split_timescale(word) {
+            Some((value, unit))
+                if self.command_params == 0 && !self.options.checks.timescale_unit_separated =>
+            {
+                self.timescale.append(value, span)?;
+                self.check_timescale_magnitude(value, span)?;
+                self.timescale.append(unit, span)
+            }
+            _ => {
+                self.timescale.append(word, span)?;
+                match self.command_params {
+                    0 => self.check_timescale_magnitude(word, span),
+                    _ => Ok(()),
+                }
+            }
+        }
+    }
+
+    /// Splits a timescale written without a space, like `1ns`, into its number and unit.
+    fn split_timescale(word: &str) -> Option<(&str, &str)> {
+        let unit_start = word.find(|c: char| !c.is_ascii_digit())?;
+        match unit_start {
+            0 => None,
+            _ => Some(word.split_at(unit_start)),
+        }
+    }
+
+    fn check_timescale_magnitude(&self, word: &str, span: Span) -> Result<(), LoadError> {
+        match self.timescale.value {
+            1 | 10 | 100 => Ok(()),
+            _ if !self.options.checks.timescale_magnitude => Ok(()),
+            _ => Self::raise_invalid_param("$timescale".to_string(), span, word),
+        }
+    }
+
+    fn append_scope(&mut self, word: &str, span: Span) -> Result<(), LoadError> {
+        self.scope.append(word, span)?;
+        match self.command_params == 0
+            && !self
+                .options
+                .profile
+                .allows_scope_type(&self.scope.scope_type)
+        {
+            true => Self::raise_invalid_param("$scope".to_string(), span, word),
+            false => Ok(()),
+        }
+    }
+
+    fn append_var(&mut self, word: &str, span: Span) -> Result<(), LoadError> {
+        self.var.append(word, span)?;
+        match self.command_params == 0 && !self.options.profile.allows_var_type(&self.var.var_type)
+        {
+            true => Self::raise_invalid_param("$var".to_string(), span, word),
+            false => Ok(()),
+        }
+    }
+
+    fn parse_value_change(&mut self, word: &str, span: Span) -> Result<(), LoadError> {
+        if let Some((pending, start)) = self.pending_value.take() {
+            return self.continue_value_change(pending, start, word, span);
+        }
+        match word.chars().next() {
+            Some('#') => {
+                self.time = Self::parse_time(word, span)?;
+                self.visitor.on_timestamp(self.time);
+            }
+            Some('b') | Some('B') | Some('r') | Some('R') => {
+                let value = get_value_from_vector(word, span)?;
+                self.pending_value = Some((PendingChange::Value(value), span))
+            }
+            Some('p') => {
+                let port = PendingChange::Port {
+                    states: get_states_from_port(word, span)?,
+                    strengths: vec![],
+                };
+                self.pending_value = Some((port, span))
+            }
+            _ => {
+                let dumped_var = parse_scalar(word, span)?;
+                self.change_value(dumped_var.value, dumped_var.identifier, span)?
+            }
+        }
+        Ok(())
+    }
+
+    fn continue_value_change(
+        &mut self,
+        pending: PendingChange,
+        start: Span,
+        word: &str,
+        span: Span,
+    ) -> Result<(), LoadError> {
+        match pending {
+            PendingChange::Value(value) => self.change_value(value, word, span),
+            PendingChange::Port {
+                states,
+                mut strengths,
+            } if strengths.len() < 2 => {
+                strengths.push(get_strengths_from_port(word, states.len(), span)?);
+                self.pending_value = Some((PendingChange::Port { states, strengths }, start));
+                Ok(())
+            }
+            PendingChange::Port { states, strengths } => {
+                let ports = states
+                    .iter()
+                    .zip(strengths[0].iter().zip(&strengths[1]))
+                    .map(|(state, (strength0, strength1))| PortValue {
+                        state: *state,
+                        strength0: *strength0,
+                        strength1: *strength1,
+                    })
+                    .collect();
+                self.change_value(Value::Port(ports), word, span)
+            }
+        }
+    }
+
+    fn parse_time(word: &str, span: Span) -> Result<u64, LoadError> {
+        word[1..]
+            .parse::<u64>()
+            .map_err(|_| LoadError::InvalidTimeValue {
+                span,
+                value: word.to_string(),
+            })
+    }
+
+    fn change_value(
+        &mut self,
+        value: Value,
+        identifier: &str,
+        span: Span,
     ) -> Result<(), LoadError> {
+        let index = match self.identifiers.get(identifier) {
+            Some(index) => *index,
+            None if !self.options.checks.declared_identifiers => return Ok(()),
+            None => {
+                return Err(LoadError::UnknownIdentifier {
+                    span,
+                    identifier: identifier.to_string(),
+                })
+            }
+        };
+        let signal = &mut self.signals[index];
+        let value = value.extend_to(signal.bit_width);
+        let repeated = signal.last_value.as_ref() == Some(&value);
+        if signal.last_value.is_none() {
+            signal.first_span = span;
+        }
+        signal.set_last_value(&value);
+        self.visitor.on_change(self.time, index, identifier, value);
+        if repeated && self.state == ParserState::End {
+            let warning = Self::repeated_value(identifier, span);
+            self.warn(warning.kind, warning.span, warning.message)?;
+        }
+        Ok(())
+    }
+
+    fn repeated_value(identifier: &str, span: Span) -> Warning {
+        let message = format!("Value change for {} repeats its current value", identifier);
+        Warning::new(WarningKind::RepeatedValue, span, message)
+    }
+
+    fn raise_invalid_param(command: String, span: Span, parameter: &str) -> Result<(), LoadError> {
         Err(LoadError::InvalidParameterForCommand {
-            line: line_num,
+            span,
             command,
             parameter: parameter.to_string(),
         })
     }
 
-    fn is_cmd(word: &str) -> bool {
-        word.starts_with('$')
+    fn is_resync_point(word: &str) -> bool {
+        StateMachine::is_cmd(word) || word.starts_with('#')
     }
 }
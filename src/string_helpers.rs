@@ -5,6 +5,30 @@ pub fn append_word(being_appended: &mut String, word: &str) {
     being_appended.push_str(word);
 }
 
+/// The identifier code GTKWave's `fst2vcd` gives the variable with handle `handle`, which counts
+/// from 1. Readers of formats without identifier codes use it to key waveforms.
+pub fn identifier_for_handle(mut handle: u64) -> String {
+    let mut identifier = String::new();
+    while handle != 0 {
+        handle -= 1;
+        identifier.push((b'!' + (handle % 94) as u8) as char);
+        handle /= 94;
+    }
+    identifier
+}
+
+/// The handle `identifier` would have been given by [`identifier_for_handle`], if it is made of
+/// printable characters.
+pub fn handle_for_identifier(identifier: &str) -> Option<u64> {
+    identifier
+        .bytes()
+        .rev()
+        .try_fold(0u64, |handle, c| match c {
+            b'!'..=b'~' => handle.checked_mul(94)?.checked_add((c - b'!') as u64 + 1),
+            _ => None,
+        })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -17,4 +41,17 @@ mod tests {
         append_word(&mut being_appended, "World!");
         assert_eq!(being_appended, "Hello World!".to_string());
     }
+
+    #[test]
+    fn identifiers_for_handles() {
+        assert_eq!(identifier_for_handle(1), "!");
+        assert_eq!(identifier_for_handle(94), "~");
+        assert_eq!(identifier_for_handle(95), "!!");
+        assert_eq!(identifier_for_handle(96), "\"!");
+        for handle in &[1, 94, 95, 96, 9000] {
+            let identifier = identifier_for_handle(*handle);
+            assert_eq!(handle_for_identifier(&identifier), Some(*handle));
+        }
+        assert_eq!(handle_for_identifier("a b"), None);
+    }
 }
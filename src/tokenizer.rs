@@ -0,0 +1,263 @@
+/// A location in the input being parsed. `offset` is a byte offset from the start of the input,
+/// `line` and `column` are 1-based, and `column` is counted in bytes.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Position {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Position {
+            offset: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+}
+
+impl Position {
+    pub fn new() -> Self {
+        Position::default()
+    }
+}
+
+/// The region of the input a token covers. Tokens never contain whitespace, so a span never
+/// crosses a line boundary.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub offset: usize,
+    pub len: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(offset: usize, len: usize, line: usize, column: usize) -> Self {
+        Span {
+            offset,
+            len,
+            line,
+            column,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Token<'a> {
+    pub text: &'a str,
+    pub span: Span,
+}
+
+/// Splits VCD text into whitespace separated tokens. Any ASCII whitespace separates tokens,
+/// including tabs and the `\r` of CRLF line endings.
+///
+/// A tokenizer can be resumed from a [`Position`], so that text which is read in pieces (e.g. line
+/// by line from a file) still gets offsets relative to the start of the whole input.
+pub struct Tokenizer<'a> {
+    src: &'a str,
+    bytes: ByteTokenizer<'a>,
+}
+
+impl<'a> Tokenizer<'a> {
+    pub fn new(src: &'a str) -> Self {
+        Tokenizer::resume(src, Position::new())
+    }
+
+    pub fn resume(src: &'a str, position: Position) -> Self {
+        Tokenizer {
+            src,
+            bytes: ByteTokenizer::resume(src.as_bytes(), position),
+        }
+    }
+
+    /// The position just past the last token or whitespace consumed.
+    pub fn position(&self) -> Position {
+        self.bytes.position
+    }
+}
+
+impl<'a> Iterator for Tokenizer<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (bytes, span) = self.bytes.next()?;
+        let end = self.bytes.cursor;
+        Some(Token {
+            text: &self.src[end - bytes.len()..end],
+            span,
+        })
+    }
+}
+
+/// Splits raw bytes into tokens the way [`Tokenizer`] splits text, without copying them or
+/// checking that they are UTF-8. Used to parse a whole file at once, e.g. one mapped into memory.
+pub struct ByteTokenizer<'a> {
+    src: &'a [u8],
+    cursor: usize,
+    position: Position,
+}
+
+impl<'a> ByteTokenizer<'a> {
+    pub fn new(src: &'a [u8]) -> Self {
+        ByteTokenizer::resume(src, Position::new())
+    }
+
+    pub fn resume(src: &'a [u8], position: Position) -> Self {
+        ByteTokenizer {
+            src,
+            cursor: 0,
+            position,
+        }
+    }
+
+    /// The position just past the last token or whitespace consumed.
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(&byte) = self.src.get(self.cursor) {
+            if !byte.is_ascii_whitespace() {
+                break;
+            }
+            self.cursor += 1;
+            self.position.offset += 1;
+            if byte == b'\n' {
+                self.position.line += 1;
+                self.position.column = 1;
+            } else {
+                self.position.column += 1;
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for ByteTokenizer<'a> {
+    type Item = (&'a [u8], Span);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.skip_whitespace();
+        let start = self.cursor;
+        let len = self.src[start..]
+            .iter()
+            .position(|byte| byte.is_ascii_whitespace())
+            .unwrap_or(self.src.len() - start);
+        if len == 0 {
+            return None;
+        }
+        // Tokens never hold a newline, so only the column moves.
+        let span = Span::new(
+            self.position.offset,
+            len,
+            self.position.line,
+            self.position.column,
+        );
+        self.cursor += len;
+        self.position.offset += len;
+        self.position.column += len;
+        Some((&self.src[start..self.cursor], span))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spans(src: &str) -> Vec<(&str, Span)> {
+        Tokenizer::new(src).map(|t| (t.text, t.span)).collect()
+    }
+
+    #[test]
+    fn splits_on_spaces() {
+        assert_eq!(
+            spans("$date today $end"),
+            vec![
+                ("$date", Span::new(0, 5, 1, 1)),
+                ("today", Span::new(6, 5, 1, 7)),
+                ("$end", Span::new(12, 4, 1, 13)),
+            ]
+        );
+    }
+
+    #[test]
+    fn splits_on_tabs() {
+        assert_eq!(
+            spans("$var\twire\t1 ! clk $end")
+                .iter()
+                .map(|(text, _)| *text)
+                .collect::<Vec<_>>(),
+            vec!["$var", "wire", "1", "!", "clk", "$end"]
+        );
+    }
+
+    #[test]
+    fn crlf_is_not_part_of_tokens() {
+        assert_eq!(
+            spans("$date\r\ntoday\r\n$end\r\n"),
+            vec![
+                ("$date", Span::new(0, 5, 1, 1)),
+                ("today", Span::new(7, 5, 2, 1)),
+                ("$end", Span::new(14, 4, 3, 1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn multi_line_command_offsets() {
+        let src = "$scope\n  module\n\ttop\n$end";
+        assert_eq!(
+            spans(src),
+            vec![
+                ("$scope", Span::new(0, 6, 1, 1)),
+                ("module", Span::new(9, 6, 2, 3)),
+                ("top", Span::new(17, 3, 3, 2)),
+                ("$end", Span::new(21, 4, 4, 1)),
+            ]
+        );
+        for (text, span) in spans(src) {
+            assert_eq!(&src[span.offset..span.offset + span.len], text);
+        }
+    }
+
+    #[test]
+    fn resumed_tokenizer_continues_offsets() {
+        let mut tokenizer = Tokenizer::new("$date\n");
+        assert_eq!(tokenizer.next().unwrap().span, Span::new(0, 5, 1, 1));
+        assert_eq!(tokenizer.next(), None);
+        let mut tokenizer = Tokenizer::resume("  today\n", tokenizer.position());
+        assert_eq!(tokenizer.next().unwrap().span, Span::new(8, 5, 2, 3));
+        assert_eq!(tokenizer.next(), None);
+        assert_eq!(
+            tokenizer.position(),
+            Position {
+                offset: 14,
+                line: 3,
+                column: 1
+            }
+        );
+    }
+
+    #[test]
+    fn byte_tokenizer_matches_tokenizer() {
+        let src = "$scope\r\n  module\n\ttop $end\n#10\n";
+        let bytes: Vec<_> = ByteTokenizer::new(src.as_bytes())
+            .map(|(bytes, span)| (std::str::from_utf8(bytes).unwrap(), span))
+            .collect();
+        assert_eq!(bytes, spans(src));
+        let mut tokenizer = ByteTokenizer::new(b"\xff\xfe $end");
+        assert_eq!(
+            tokenizer.next(),
+            Some((&b"\xff\xfe"[..], Span::new(0, 2, 1, 1)))
+        );
+        assert_eq!(tokenizer.next().unwrap().1, Span::new(3, 4, 1, 4));
+        assert_eq!(tokenizer.position().offset, 7);
+    }
+
+    #[test]
+    fn whitespace_only_input_has_no_tokens() {
+        assert_eq!(spans(" \t\r\n\x0c "), vec![]);
+    }
+}
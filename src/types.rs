@@ -1,3 +1,8 @@
+pub mod attribute;
+pub mod compact_waveform;
 pub mod scope;
 pub mod timescale;
+pub mod unknown_command;
+pub mod value;
 pub mod variable;
+pub mod waveform;
@@ -0,0 +1,338 @@
+use crate::error::LoadError;
+use crate::tokenizer::Span;
+use crate::types::value::{Bit, Value};
+use std::str::FromStr;
+use strum_macros::EnumString;
+
+/// The type of a GTKWave `$attrbegin` attribute.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, EnumString)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AttributeType {
+    #[strum(serialize = "misc")]
+    Misc,
+    #[strum(serialize = "array")]
+    Array,
+    #[strum(serialize = "enum")]
+    Enum,
+    #[strum(serialize = "class")]
+    Class,
+}
+
+/// What a [`AttributeType::Misc`] attribute describes, from its subtype.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MiscKind {
+    Comment,
+    EnvVar,
+    /// The VHDL type of a variable. The name is the type's name.
+    SupVar,
+    PathName,
+    /// Where in the source a variable or scope is declared. The name is the file, the argument
+    /// the line.
+    SourceStem,
+    /// Where in the source a scope is instantiated.
+    SourceInstantiationStem,
+    ValueList,
+    /// Either the definition of an enum table, or a reference to one by its handle.
+    EnumTable,
+    Unknown,
+}
+
+impl MiscKind {
+    fn from_subtype(subtype: u32) -> MiscKind {
+        use MiscKind::*;
+        match subtype {
+            0 => Comment,
+            1 => EnvVar,
+            2 => SupVar,
+            3 => PathName,
+            4 => SourceStem,
+            5 => SourceInstantiationStem,
+            6 => ValueList,
+            7 => EnumTable,
+            _ => Unknown,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum BuildState {
+    AttributeType,
+    SubType,
+    NameAndArgument,
+}
+
+impl BuildState {
+    /// A deserialized attribute has its type and subtype, and only its name and argument could
+    /// still be parsed.
+    #[cfg(feature = "serde")]
+    fn done() -> Self {
+        BuildState::NameAndArgument
+    }
+}
+
+/// An attribute from a GTKWave `$attrbegin type subtype name argument $end` command, as written
+/// by GTKWave, Verilator and nvc. It annotates the `$scope` or `$var` that follows it.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Attribute {
+    pub attribute_type: AttributeType,
+    /// The subtype, written in hex. Its meaning depends on the type.
+    pub subtype: u32,
+    /// The name. It can be empty, or span several words.
+    pub name: String,
+    pub argument: u64,
+    #[cfg_attr(feature = "serde", serde(skip, default = "BuildState::done"))]
+    state: BuildState,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    words: Vec<String>,
+}
+
+impl PartialEq for Attribute {
+    fn eq(&self, other: &Self) -> bool {
+        self.attribute_type == other.attribute_type
+            && self.subtype == other.subtype
+            && self.name == other.name
+            && self.argument == other.argument
+    }
+}
+
+impl Default for Attribute {
+    fn default() -> Self {
+        Attribute {
+            attribute_type: AttributeType::Misc,
+            subtype: 0,
+            name: "".to_string(),
+            argument: 0,
+            state: BuildState::AttributeType,
+            words: vec![],
+        }
+    }
+}
+
+impl Attribute {
+    pub fn new(attribute_type: AttributeType, subtype: u32, name: String, argument: u64) -> Self {
+        Attribute {
+            attribute_type,
+            subtype,
+            name,
+            argument,
+            ..Attribute::default()
+        }
+    }
+
+    pub fn append(&mut self, word: &str, span: Span) -> Result<(), LoadError> {
+        match self.state {
+            BuildState::AttributeType => {
+                self.attribute_type = AttributeType::from_str(word)
+                    .map_err(|_| Attribute::invalid_param(word, span))?;
+                self.state = BuildState::SubType;
+            }
+            BuildState::SubType => {
+                self.subtype = u32::from_str_radix(word, 16)
+                    .map_err(|_| Attribute::invalid_param(word, span))?;
+                self.state = BuildState::NameAndArgument;
+            }
+            BuildState::NameAndArgument => self.words.push(word.to_string()),
+        }
+        Ok(())
+    }
+
+    /// Completes the attribute once its `$end` is found at `span`. The last word is the argument,
+    /// everything between the subtype and it is the name.
+    pub fn finish(&mut self, span: Span) -> Result<(), LoadError> {
+        let argument = match (&self.state, self.words.pop()) {
+            (BuildState::NameAndArgument, Some(argument)) => argument,
+            _ => {
+                return Err(LoadError::TooFewParameters {
+                    span,
+                    command: "attrbegin".to_string(),
+                })
+            }
+        };
+        self.argument = argument
+            .parse::<u64>()
+            .map_err(|_| Attribute::invalid_param(&argument, span))?;
+        self.name = self.words.join(" ");
+        self.words.clear();
+        Ok(())
+    }
+
+    /// What the attribute describes, if it is a misc attribute.
+    pub fn misc_kind(&self) -> Option<MiscKind> {
+        match self.attribute_type {
+            AttributeType::Misc => Some(MiscKind::from_subtype(self.subtype)),
+            _ => None,
+        }
+    }
+
+    /// The source file and line, for source stem attributes.
+    pub fn source_location(&self) -> Option<(&str, u64)> {
+        match self.misc_kind()? {
+            MiscKind::SourceStem | MiscKind::SourceInstantiationStem => {
+                Some((&self.name, self.argument))
+            }
+            _ => None,
+        }
+    }
+
+    /// The VHDL type name, for supplemental variable attributes.
+    pub fn vhdl_type(&self) -> Option<&str> {
+        match self.misc_kind()? {
+            MiscKind::SupVar => Some(&self.name),
+            _ => None,
+        }
+    }
+
+    /// The enum table this attribute defines, if it defines one. Its handle is the argument.
+    pub fn enum_table(&self) -> Option<EnumTable> {
+        match self.misc_kind()? {
+            MiscKind::EnumTable if !self.name.is_empty() => EnumTable::parse(&self.name),
+            _ => None,
+        }
+    }
+
+    /// The handle of the enum table this attribute points a variable at, if it is a reference
+    /// to one.
+    pub fn enum_table_handle(&self) -> Option<u64> {
+        match self.misc_kind()? {
+            MiscKind::EnumTable if self.name.is_empty() => Some(self.argument),
+            _ => None,
+        }
+    }
+
+    fn invalid_param(word: &str, span: Span) -> LoadError {
+        LoadError::InvalidParameterForCommand {
+            span,
+            command: "$attrbegin".to_string(),
+            parameter: word.to_string(),
+        }
+    }
+}
+
+/// Names for the values of an enum, so that a variable holding one can be shown by name.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EnumTable {
+    pub name: String,
+    /// Each value, as bits, with its name.
+    pub entries: Vec<(Value, String)>,
+}
+
+impl EnumTable {
+    /// Parses an enum table from an attribute name: `name count names... values...`.
+    fn parse(text: &str) -> Option<EnumTable> {
+        let words: Vec<&str> = text.split(' ').collect();
+        let count = words.get(1)?.parse::<usize>().ok()?;
+        if words.len() != 2 + 2 * count {
+            return None;
+        }
+        let names = &words[2..2 + count];
+        let values = &words[2 + count..];
+        let entries = values
+            .iter()
+            .zip(names)
+            .map(|(value, name)| {
+                let bits = value
+                    .chars()
+                    .map(Bit::from_char)
+                    .collect::<Option<Vec<Bit>>>()?;
+                Some((Value::Vector(bits), name.to_string()))
+            })
+            .collect::<Option<Vec<(Value, String)>>>()?;
+        Some(EnumTable {
+            name: words[0].to_string(),
+            entries,
+        })
+    }
+
+    /// The name of `value`, if the table has one for it.
+    pub fn name_of(&self, value: &Value) -> Option<&str> {
+        let bits = match value {
+            Value::Scalar(bit) => vec![*bit],
+            Value::Vector(bits) => bits.clone(),
+            _ => return None,
+        };
+        self.entries.iter().find_map(|(entry, name)| {
+            let entry = match entry {
+                Value::Vector(entry) => entry,
+                _ => return None,
+            };
+            let width = entry.len().max(bits.len());
+            match Value::Vector(entry.clone()).extend_to(width)
+                == Value::Vector(bits.clone()).extend_to(width)
+            {
+                true => Some(name.as_str()),
+                false => None,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build(words: &[&str]) -> Result<Attribute, LoadError> {
+        let mut attribute = Attribute::default();
+        for word in words {
+            attribute.append(word, Span::default())?;
+        }
+        attribute.finish(Span::default())?;
+        Ok(attribute)
+    }
+
+    #[test]
+    fn build_source_stem() {
+        let attribute = build(&["misc", "04", "/src/top.v", "12"]).unwrap();
+        assert_eq!(attribute.misc_kind(), Some(MiscKind::SourceStem));
+        assert_eq!(attribute.source_location(), Some(("/src/top.v", 12)));
+        assert_eq!(attribute.vhdl_type(), None);
+    }
+
+    #[test]
+    fn build_enum_table() {
+        let attribute = build(&[
+            "misc", "07", "state_t", "3", "IDLE", "RUN", "DONE", "00", "01", "10", "1",
+        ])
+        .unwrap();
+        let table = attribute.enum_table().unwrap();
+        assert_eq!(table.name, "state_t");
+        assert_eq!(
+            table.name_of(&Value::Vector(vec![Bit::One, Bit::Zero])),
+            Some("DONE")
+        );
+        assert_eq!(table.name_of(&Value::Scalar(Bit::One)), Some("RUN"));
+        assert_eq!(
+            table.name_of(&Value::Vector(vec![Bit::One, Bit::One])),
+            None
+        );
+        assert_eq!(attribute.enum_table_handle(), None);
+
+        let reference = build(&["misc", "07", "1"]).unwrap();
+        assert_eq!(reference.enum_table(), None);
+        assert_eq!(reference.enum_table_handle(), Some(1));
+    }
+
+    #[test]
+    fn attribute_without_argument_throws_error() {
+        assert_eq!(
+            build(&["misc", "07"]).err(),
+            Some(LoadError::TooFewParameters {
+                span: Span::default(),
+                command: "attrbegin".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_attribute_parameters_throw_error() {
+        for words in &[
+            &["bogus", "00", "1"][..],
+            &["misc", "zz", "1"],
+            &["misc", "00", "a"],
+        ] {
+            assert_eq!(build(words).err().map(|err| err.code()), Some("E006"));
+        }
+    }
+}
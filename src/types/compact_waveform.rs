@@ -0,0 +1,644 @@
+use crate::types::value::{Bit, Value};
+use crate::types::waveform::{Waveform, WaveformQuery};
+use std::convert::TryInto;
+use std::mem::size_of;
+
+/// How many changes there are between two blocks, which queries start decoding from.
+const BLOCK_LEN: usize = 64;
+
+/// The value changes of one variable, stored compactly: times as the differences between them,
+/// values of scalars and vectors bit-packed, and a value repeated by several changes in a row
+/// stored once. It is queried through [`WaveformQuery`], like [`Waveform`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CompactWaveform {
+    len: usize,
+    last_time: u64,
+    /// The time of each change minus the time of the one before, in LEB128.
+    times: Vec<u8>,
+    /// The values of the changes, without those that repeat the one before.
+    values: Values,
+    /// How many changes in a row each value is for, in LEB128, but for the last value.
+    runs: Vec<u8>,
+    /// How many changes in a row the last value is for so far.
+    open_run: u64,
+    blocks: Vec<Block>,
+}
+
+/// Where decoding starts for every `BLOCK_LEN`th change.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Block {
+    first_time: u64,
+    /// The time of the change before, which the time of the first one differs from.
+    time_before: u64,
+    times_offset: usize,
+    /// The value of the first change, how many changes of that value come before it, and where
+    /// the length of the value's run is.
+    value: usize,
+    run_offset: u64,
+    runs_offset: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Values {
+    /// Scalars, or vectors of one width. Each bit takes one bit while all of them are `0` or `1`,
+    /// and two bits once an `x` or a `z` turns up.
+    Bits {
+        scalar: bool,
+        width: usize,
+        four_state: bool,
+        len: usize,
+        words: Vec<u64>,
+    },
+    Reals(Vec<f64>),
+    /// Values of other kinds, or of several kinds or widths.
+    Other(Vec<Value>),
+}
+
+impl Default for Values {
+    fn default() -> Self {
+        Values::Other(vec![])
+    }
+}
+
+impl CompactWaveform {
+    pub fn new() -> Self {
+        CompactWaveform::default()
+    }
+
+    pub fn push(&mut self, time: u64, value: Value) {
+        let repeat = self.values.len() != 0 && self.values.get(self.values.len() - 1) == value;
+        if !repeat {
+            if self.values.len() != 0 {
+                put_leb128(&mut self.runs, self.open_run);
+            }
+            self.values.push(value);
+            self.open_run = 0;
+        }
+        if self.len.is_multiple_of(BLOCK_LEN) {
+            self.blocks.push(Block {
+                first_time: time,
+                time_before: self.last_time,
+                times_offset: self.times.len(),
+                value: self.values.len() - 1,
+                run_offset: self.open_run,
+                runs_offset: self.runs.len(),
+            });
+        }
+        self.open_run += 1;
+        put_leb128(&mut self.times, time.wrapping_sub(self.last_time));
+        self.last_time = time;
+        self.len += 1;
+    }
+
+    /// Frees memory set aside for more changes.
+    pub fn shrink_to_fit(&mut self) {
+        self.times.shrink_to_fit();
+        self.runs.shrink_to_fit();
+        self.blocks.shrink_to_fit();
+        match &mut self.values {
+            Values::Bits { words, .. } => words.shrink_to_fit(),
+            Values::Reals(reals) => reals.shrink_to_fit(),
+            Values::Other(values) => values.shrink_to_fit(),
+        }
+    }
+
+    /// Appends the waveform in a binary form, which [`CompactWaveform::read_from`] reads back.
+    pub fn write_to(&self, bytes: &mut Vec<u8>) {
+        for field in &[self.len as u64, self.last_time, self.open_run] {
+            put_leb128(bytes, *field);
+        }
+        for stream in &[&self.times, &self.runs] {
+            put_leb128(bytes, stream.len() as u64);
+            bytes.extend(stream.iter());
+        }
+        put_leb128(bytes, self.blocks.len() as u64);
+        for block in &self.blocks {
+            put_leb128(bytes, block.first_time);
+            put_leb128(bytes, block.time_before);
+            put_leb128(bytes, block.times_offset as u64);
+            put_leb128(bytes, block.value as u64);
+            put_leb128(bytes, block.run_offset);
+            put_leb128(bytes, block.runs_offset as u64);
+        }
+        match &self.values {
+            Values::Bits {
+                scalar,
+                width,
+                four_state,
+                len,
+                words,
+            } => {
+                bytes.extend(&[b'B', *scalar as u8, *four_state as u8]);
+                put_leb128(bytes, *width as u64);
+                put_leb128(bytes, *len as u64);
+                put_leb128(bytes, words.len() as u64);
+                for word in words {
+                    bytes.extend(&word.to_le_bytes());
+                }
+            }
+            Values::Reals(reals) => {
+                bytes.push(b'R');
+                put_leb128(bytes, reals.len() as u64);
+                for real in reals {
+                    bytes.extend(&real.to_le_bytes());
+                }
+            }
+            Values::Other(values) => {
+                bytes.push(b'O');
+                put_leb128(bytes, values.len() as u64);
+                for value in values {
+                    value.write_to(bytes);
+                }
+            }
+        }
+    }
+
+    /// Reads a waveform written by [`CompactWaveform::write_to`] at `offset` in `bytes`, moving
+    /// `offset` past it. `None` if there isn't a valid waveform there.
+    pub fn read_from(bytes: &[u8], offset: &mut usize) -> Option<CompactWaveform> {
+        let len = read_leb128(bytes, offset)? as usize;
+        let last_time = read_leb128(bytes, offset)?;
+        let open_run = read_leb128(bytes, offset)?;
+        let mut stream = || {
+            let len = read_leb128(bytes, offset)? as usize;
+            let stream = bytes.get(*offset..offset.checked_add(len)?)?.to_vec();
+            *offset += len;
+            Some(stream)
+        };
+        let times = stream()?;
+        let runs = stream()?;
+        let mut number = || read_leb128(bytes, offset);
+        let blocks = (0..number()?)
+            .map(|_| {
+                Some(Block {
+                    first_time: number()?,
+                    time_before: number()?,
+                    times_offset: number()? as usize,
+                    value: number()? as usize,
+                    run_offset: number()?,
+                    runs_offset: number()? as usize,
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        let take_u64 = |offset: &mut usize| {
+            let word = bytes.get(*offset..offset.checked_add(8)?)?;
+            *offset += 8;
+            Some(u64::from_le_bytes(word.try_into().ok()?))
+        };
+        let tag = *bytes.get(*offset)?;
+        *offset += 1;
+        let values = match tag {
+            b'B' => {
+                let flags = bytes.get(*offset..offset.checked_add(2)?)?;
+                *offset += 2;
+                let width = read_leb128(bytes, offset)? as usize;
+                let len = read_leb128(bytes, offset)? as usize;
+                let words = (0..read_leb128(bytes, offset)?)
+                    .map(|_| take_u64(offset))
+                    .collect::<Option<_>>()?;
+                Values::Bits {
+                    scalar: flags[0] != 0,
+                    width,
+                    four_state: flags[1] != 0,
+                    len,
+                    words,
+                }
+            }
+            b'R' => Values::Reals(
+                (0..read_leb128(bytes, offset)?)
+                    .map(|_| take_u64(offset).map(f64::from_bits))
+                    .collect::<Option<_>>()?,
+            ),
+            b'O' => Values::Other(
+                (0..read_leb128(bytes, offset)?)
+                    .map(|_| Value::read_from(bytes, offset))
+                    .collect::<Option<_>>()?,
+            ),
+            _ => return None,
+        };
+        Some(CompactWaveform {
+            len,
+            last_time,
+            times,
+            values,
+            runs,
+            open_run,
+            blocks,
+        })
+    }
+
+    /// The changes from the first one of block `block` on.
+    fn iter_from(&self, block: usize) -> Changes<'_> {
+        let mut changes = Changes {
+            waveform: self,
+            left: 0,
+            time: 0,
+            times_offset: 0,
+            value: 0,
+            run_left: 0,
+            runs_offset: 0,
+        };
+        if let Some(block_start) = self.blocks.get(block) {
+            changes.left = self.len - block * BLOCK_LEN;
+            changes.time = block_start.time_before;
+            changes.times_offset = block_start.times_offset;
+            changes.value = block_start.value;
+            changes.runs_offset = block_start.runs_offset;
+            changes.run_left = changes.run_len() - block_start.run_offset;
+        }
+        changes
+    }
+}
+
+impl From<&Waveform> for CompactWaveform {
+    fn from(waveform: &Waveform) -> Self {
+        let mut compact = CompactWaveform::new();
+        for (time, value) in waveform.changes() {
+            compact.push(*time, value.clone());
+        }
+        compact.shrink_to_fit();
+        compact
+    }
+}
+
+impl WaveformQuery for CompactWaveform {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn value_at(&self, time: u64) -> Option<Value> {
+        let block = self
+            .blocks
+            .partition_point(|block| block.first_time <= time);
+        if block == 0 {
+            return None;
+        }
+        self.iter_from(block - 1)
+            .take(BLOCK_LEN)
+            .take_while(|(t, _)| *t <= time)
+            .last()
+            .map(|(_, value)| value)
+    }
+
+    fn last_value(&self) -> Option<Value> {
+        match self.values.len() {
+            0 => None,
+            len => Some(self.values.get(len - 1)),
+        }
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (u64, Value)> + '_> {
+        Box::new(self.iter_from(0))
+    }
+
+    fn memory_usage(&self) -> usize {
+        size_of::<Self>()
+            + self.times.capacity()
+            + self.runs.capacity()
+            + self.blocks.capacity() * size_of::<Block>()
+            + self.values.heap_size()
+    }
+}
+
+struct Changes<'a> {
+    waveform: &'a CompactWaveform,
+    left: usize,
+    time: u64,
+    times_offset: usize,
+    value: usize,
+    run_left: u64,
+    runs_offset: usize,
+}
+
+impl Changes<'_> {
+    /// The length of the run of the current value, moving past it in `runs`.
+    fn run_len(&mut self) -> u64 {
+        if self.value + 1 == self.waveform.values.len() {
+            return self.waveform.open_run;
+        }
+        get_leb128(&self.waveform.runs, &mut self.runs_offset)
+    }
+}
+
+impl Iterator for Changes<'_> {
+    type Item = (u64, Value);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.left == 0 {
+            return None;
+        }
+        if self.run_left == 0 {
+            self.value += 1;
+            self.run_left = self.run_len();
+        }
+        let delta = get_leb128(&self.waveform.times, &mut self.times_offset);
+        self.time = self.time.wrapping_add(delta);
+        self.run_left -= 1;
+        self.left -= 1;
+        Some((self.time, self.waveform.values.get(self.value)))
+    }
+}
+
+impl Values {
+    fn for_value(value: &Value) -> Values {
+        let bits = |scalar, width| Values::Bits {
+            scalar,
+            width,
+            four_state: false,
+            len: 0,
+            words: vec![],
+        };
+        match value {
+            Value::Scalar(_) => bits(true, 1),
+            Value::Vector(vector) => bits(false, vector.len()),
+            Value::Real(_) => Values::Reals(vec![]),
+            Value::Port(_) => Values::Other(vec![]),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Values::Bits { len, .. } => *len,
+            Values::Reals(reals) => reals.len(),
+            Values::Other(values) => values.len(),
+        }
+    }
+
+    fn get(&self, index: usize) -> Value {
+        match self {
+            Values::Bits {
+                scalar,
+                width,
+                four_state,
+                words,
+                ..
+            } => {
+                let (bits_per_bit, mask) = if *four_state { (2, 0b11) } else { (1, 0b1) };
+                let mut bits = (0..*width).map(|bit| {
+                    let at = (index * width + bit) * bits_per_bit;
+                    match (words[at / 64] >> (at % 64)) & mask {
+                        0 => Bit::Zero,
+                        1 => Bit::One,
+                        2 => Bit::X,
+                        _ => Bit::Z,
+                    }
+                });
+                match scalar {
+                    true => Value::Scalar(bits.next_back().unwrap()),
+                    false => Value::Vector(bits.collect()),
+                }
+            }
+            Values::Reals(reals) => Value::Real(reals[index]),
+            Values::Other(values) => values[index].clone(),
+        }
+    }
+
+    fn push(&mut self, value: Value) {
+        if self.len() == 0 {
+            *self = Values::for_value(&value);
+        }
+        match (&mut *self, value) {
+            (Values::Reals(reals), Value::Real(real)) => reals.push(real),
+            (Values::Other(values), value) => values.push(value),
+            (Values::Bits { scalar: true, .. }, Value::Scalar(bit)) => self.push_bits(&[bit]),
+            (
+                Values::Bits {
+                    scalar: false,
+                    width,
+                    ..
+                },
+                Value::Vector(bits),
+            ) if bits.len() == *width => self.push_bits(&bits),
+            (_, value) => self.push_other(value),
+        }
+    }
+
+    fn push_bits(&mut self, bits: &[Bit]) {
+        let needs_four_state = bits.iter().any(|bit| matches!(bit, Bit::X | Bit::Z));
+        if let Values::Bits { four_state, .. } = self {
+            if needs_four_state && !*four_state {
+                self.make_four_state();
+            }
+        }
+        if let Values::Bits {
+            width,
+            four_state,
+            len,
+            words,
+            ..
+        } = self
+        {
+            let bits_per_bit = if *four_state { 2 } else { 1 };
+            for (bit, value) in bits.iter().enumerate() {
+                let at = (*len * *width + bit) * bits_per_bit;
+                if words.len() <= at / 64 {
+                    words.push(0);
+                }
+                let code = match value {
+                    Bit::Zero => 0,
+                    Bit::One => 1,
+                    Bit::X => 2,
+                    Bit::Z => 3,
+                };
+                words[at / 64] |= code << (at % 64);
+            }
+            *len += 1;
+        }
+    }
+
+    /// Repacks two-state bits with two bits each.
+    fn make_four_state(&mut self) {
+        if let Values::Bits {
+            width,
+            four_state,
+            len,
+            words,
+            ..
+        } = self
+        {
+            let total = *len * *width;
+            let mut repacked = vec![0u64; (total * 2).div_ceil(64)];
+            for bit in 0..total {
+                let code = (words[bit / 64] >> (bit % 64)) & 1;
+                repacked[bit * 2 / 64] |= code << (bit * 2 % 64);
+            }
+            *words = repacked;
+            *four_state = true;
+        }
+    }
+
+    /// Goes over to storing values as they are, for a value that doesn't fit.
+    fn push_other(&mut self, value: Value) {
+        let mut values: Vec<Value> = (0..self.len()).map(|index| self.get(index)).collect();
+        values.push(value);
+        *self = Values::Other(values);
+    }
+
+    fn heap_size(&self) -> usize {
+        match self {
+            Values::Bits { words, .. } => words.capacity() * size_of::<u64>(),
+            Values::Reals(reals) => reals.capacity() * size_of::<f64>(),
+            Values::Other(values) => {
+                let heap: usize = values.iter().map(Value::heap_size).sum();
+                values.capacity() * size_of::<Value>() + heap
+            }
+        }
+    }
+}
+
+fn put_leb128(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn get_leb128(bytes: &[u8], offset: &mut usize) -> u64 {
+    read_leb128(bytes, offset).expect("changes are encoded as they're pushed")
+}
+
+/// Reads a number at `offset` in `bytes`, moving `offset` past it. `None` if there isn't one.
+fn read_leb128(bytes: &[u8], offset: &mut usize) -> Option<u64> {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = *bytes.get(*offset)?;
+        *offset += 1;
+        value |= ((byte & 0x7f) as u64).checked_shl(shift)?;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::value::{PortState, PortValue, Strength};
+
+    fn assert_same_as(waveform: &Waveform) {
+        let compact = CompactWaveform::from(waveform);
+        assert_eq!(WaveformQuery::len(&compact), waveform.len());
+        assert_eq!(
+            WaveformQuery::iter(&compact).collect::<Vec<_>>(),
+            waveform.changes().to_vec()
+        );
+        assert_eq!(
+            WaveformQuery::last_value(&compact),
+            waveform.last_value().cloned()
+        );
+        let end = waveform.changes().last().map_or(0, |(time, _)| *time);
+        for time in 0..=end + 1 {
+            assert_eq!(
+                WaveformQuery::value_at(&compact, time),
+                waveform.value_at(time).cloned(),
+                "{}",
+                time
+            );
+        }
+    }
+
+    fn bits(s: &str) -> Vec<Bit> {
+        s.chars().map(|c| Bit::from_char(c).unwrap()).collect()
+    }
+
+    #[test]
+    fn scalars_and_repeats() {
+        let mut waveform = Waveform::new();
+        for time in 0..500 {
+            let bit = match time % 7 {
+                0..=2 => Bit::Zero,
+                3 => Bit::X,
+                _ => Bit::One,
+            };
+            waveform.push(time * 3, Value::Scalar(bit));
+        }
+        assert_same_as(&waveform);
+    }
+
+    #[test]
+    fn vectors_turning_four_state() {
+        let mut waveform = Waveform::new();
+        for time in 0..300u64 {
+            let vector = match time {
+                150 => bits("10z01"),
+                _ => bits(&format!("{:05b}", time % 32)),
+            };
+            waveform.push(time, Value::Vector(vector));
+        }
+        assert_same_as(&waveform);
+    }
+
+    #[test]
+    fn changes_at_the_same_time() {
+        let mut waveform = Waveform::new();
+        for time in 0..200 {
+            waveform.push(time / 3, Value::Real(time as f64 / 2.0));
+        }
+        assert_same_as(&waveform);
+    }
+
+    #[test]
+    fn values_of_several_kinds() {
+        let port = PortValue {
+            state: PortState::InputHigh,
+            strength0: Strength::Strong,
+            strength1: Strength::Supply,
+        };
+        let mut waveform = Waveform::new();
+        waveform.push(0, Value::Vector(bits("01")));
+        waveform.push(5, Value::Vector(bits("011")));
+        waveform.push(10, Value::Real(1.5));
+        waveform.push(15, Value::Port(vec![port]));
+        assert_same_as(&waveform);
+        assert_same_as(&Waveform::new());
+    }
+
+    #[test]
+    fn one_bit_signals_take_less_memory() {
+        let mut waveform = Waveform::new();
+        for time in 0..10000 {
+            let bit = if time % 2 == 0 { Bit::Zero } else { Bit::One };
+            waveform.push(time * 5, Value::Scalar(bit));
+        }
+        let compact = CompactWaveform::from(&waveform);
+        assert!(compact.memory_usage() * 10 < WaveformQuery::memory_usage(&waveform));
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let mut waveforms = vec![Waveform::new(); 3];
+        for time in 0..150 {
+            waveforms[0].push(time, Value::Vector(bits(&format!("{:03b}", time % 5))));
+            waveforms[1].push(time * 2, Value::Real(time as f64));
+        }
+        waveforms[2].push(3, Value::Scalar(Bit::Z));
+        waveforms[2].push(4, Value::Vector(bits("x")));
+        let mut bytes = vec![];
+        for waveform in &waveforms {
+            CompactWaveform::from(waveform).write_to(&mut bytes);
+        }
+        let mut offset = 0;
+        for waveform in &waveforms {
+            let read = CompactWaveform::read_from(&bytes, &mut offset);
+            assert_eq!(read, Some(CompactWaveform::from(waveform)));
+        }
+        assert_eq!(offset, bytes.len());
+        assert_eq!(CompactWaveform::read_from(&bytes[..20], &mut 0), None);
+    }
+
+    #[test]
+    fn leb128_round_trip() {
+        let mut bytes = vec![];
+        for value in &[0, 127, 128, 300, u64::MAX] {
+            put_leb128(&mut bytes, *value);
+        }
+        let mut offset = 0;
+        for value in &[0, 127, 128, 300, u64::MAX] {
+            assert_eq!(get_leb128(&bytes, &mut offset), *value);
+        }
+        assert_eq!(offset, bytes.len());
+    }
+}
@@ -1,7 +1,11 @@
 use crate::error::LoadError;
+use crate::tokenizer::Span;
+use crate::types::attribute::Attribute;
+use std::fmt;
 use std::str::FromStr;
 
-#[derive(Debug, Clone, Eq, PartialEq, EnumString)]
+#[derive(Debug, Clone, Eq, PartialEq, EnumString, Display)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ScopeType {
     #[strum(serialize = "begin")]
     Begin,
@@ -13,6 +17,40 @@ pub enum ScopeType {
     Module,
     #[strum(serialize = "task")]
     Task,
+    #[strum(serialize = "interface")]
+    Interface,
+    #[strum(serialize = "package")]
+    Package,
+    #[strum(serialize = "program")]
+    Program,
+    #[strum(serialize = "class")]
+    Class,
+    #[strum(serialize = "struct")]
+    Struct,
+    #[strum(serialize = "union")]
+    Union,
+    #[strum(serialize = "generate")]
+    Generate,
+    #[strum(serialize = "vhdl_architecture")]
+    VhdlArchitecture,
+    #[strum(serialize = "vhdl_record")]
+    VhdlRecord,
+    #[strum(serialize = "vhdl_block")]
+    VhdlBlock,
+    #[strum(serialize = "vhdl_generate")]
+    VhdlGenerate,
+    #[strum(serialize = "vhdl_if_generate")]
+    VhdlIfGenerate,
+    #[strum(serialize = "vhdl_for_generate")]
+    VhdlForGenerate,
+    #[strum(serialize = "vhdl_process")]
+    VhdlProcess,
+    #[strum(serialize = "vhdl_procedure")]
+    VhdlProcedure,
+    #[strum(serialize = "vhdl_function")]
+    VhdlFunction,
+    #[strum(serialize = "vhdl_package")]
+    VhdlPackage,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -23,13 +61,19 @@ enum BuildState {
 }
 
 impl BuildState {
-    fn next(&self, line_num: usize) -> Result<Self, LoadError> {
+    /// A deserialized scope has its type and identifier.
+    #[cfg(feature = "serde")]
+    fn done() -> Self {
+        BuildState::Done
+    }
+
+    fn next(&self, span: Span) -> Result<Self, LoadError> {
         use BuildState::*;
         match *self {
             ScopeType => Ok(Identifier),
             Identifier => Ok(Done),
             Done => Err(LoadError::TooManyParameters {
-                line: line_num,
+                span,
                 command: "$scope".to_string(),
             }),
         }
@@ -37,15 +81,28 @@ impl BuildState {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Scope {
     pub scope_type: ScopeType,
     pub identifier: String,
+    /// Attributes from the `$attrbegin` commands right before the scope.
+    pub attributes: Vec<Attribute>,
+    #[cfg_attr(feature = "serde", serde(skip, default = "BuildState::done"))]
     state: BuildState,
 }
 
 impl PartialEq for Scope {
     fn eq(&self, other: &Self) -> bool {
-        self.scope_type == other.scope_type && self.identifier == other.identifier
+        self.scope_type == other.scope_type
+            && self.identifier == other.identifier
+            && self.attributes == other.attributes
+    }
+}
+
+/// Writes the scope as its command, like `$scope module top $end`. Its attributes aren't written.
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "$scope {} {} $end", self.scope_type, self.identifier)
     }
 }
 
@@ -54,6 +111,7 @@ impl Default for Scope {
         Scope {
             scope_type: ScopeType::Begin,
             identifier: "".to_string(),
+            attributes: vec![],
             state: BuildState::ScopeType,
         }
     }
@@ -68,26 +126,27 @@ impl Scope {
         Scope {
             scope_type,
             identifier,
+            attributes: vec![],
             state: BuildState::ScopeType,
         }
     }
 
-    pub fn append(&mut self, word: &str, line_num: usize) -> Result<(), LoadError> {
+    pub fn append(&mut self, word: &str, span: Span) -> Result<(), LoadError> {
         match self.state {
-            BuildState::ScopeType => self.write_scope_type(word, line_num)?,
+            BuildState::ScopeType => self.write_scope_type(word, span)?,
             BuildState::Identifier => self.write_identifier(word.to_string())?,
             _ => {}
         }
-        self.state = self.state.next(line_num)?;
+        self.state = self.state.next(span)?;
         Ok(())
     }
 
-    fn write_scope_type(&mut self, word: &str, line_num: usize) -> Result<(), LoadError> {
+    fn write_scope_type(&mut self, word: &str, span: Span) -> Result<(), LoadError> {
         self.scope_type = match ScopeType::from_str(word) {
             Ok(scope_type) => scope_type,
             Err(_) => {
                 return Err(LoadError::InvalidParameterForCommand {
-                    line: line_num,
+                    span,
                     command: "$scope".to_string(),
                     parameter: word.to_string(),
                 })
@@ -109,8 +168,8 @@ mod tests {
     #[test]
     fn build_scope_1() {
         let mut scope = Scope::new();
-        scope.append("module", 0).unwrap();
-        scope.append("top", 0).unwrap();
+        scope.append("module", Span::default()).unwrap();
+        scope.append("top", Span::default()).unwrap();
         assert_eq!(scope.scope_type, ScopeType::Module);
         assert_eq!(scope.identifier, "top");
     }
@@ -118,8 +177,8 @@ mod tests {
     #[test]
     fn build_scope_2() {
         let mut scope = Scope::new();
-        scope.append("task", 0).unwrap();
-        scope.append("my_task", 0).unwrap();
+        scope.append("task", Span::default()).unwrap();
+        scope.append("my_task", Span::default()).unwrap();
         assert_eq!(scope.scope_type, ScopeType::Task);
         assert_eq!(scope.identifier, "my_task");
     }
@@ -127,9 +186,9 @@ mod tests {
     #[test]
     fn invalid_scope_type_throws_error() {
         let mut scope = Scope::new();
-        let err = scope.append("NotAScopeType", 0).err();
+        let err = scope.append("NotAScopeType", Span::default()).err();
         let exp_err = LoadError::InvalidParameterForCommand {
-            line: 0,
+            span: Span::default(),
             command: "$scope".to_string(),
             parameter: "NotAScopeType".to_string(),
         };
@@ -139,13 +198,25 @@ mod tests {
     #[test]
     fn extra_params_in_scope_throws_error() {
         let mut scope = Scope::new();
-        scope.append("task", 0).unwrap();
-        scope.append("my_task", 0).unwrap();
-        let err = scope.append("my_task", 0).err();
+        scope.append("task", Span::default()).unwrap();
+        scope.append("my_task", Span::default()).unwrap();
+        let err = scope.append("my_task", Span::default()).err();
         let exp_err = LoadError::TooManyParameters {
-            line: 0,
+            span: Span::default(),
             command: "$scope".to_string(),
         };
         assert_eq!(err, Some(exp_err));
     }
+
+    #[test]
+    fn displayed_scope_builds_back() {
+        let scope = Scope::init(ScopeType::VhdlArchitecture, "top".to_string());
+        let text = scope.to_string();
+        assert_eq!(text, "$scope vhdl_architecture top $end");
+        let mut built = Scope::new();
+        for word in text.split(' ').filter(|word| !word.starts_with('$')) {
+            built.append(word, Span::default()).unwrap();
+        }
+        assert_eq!(built, scope);
+    }
 }
@@ -1,9 +1,14 @@
 use crate::error::LoadError;
+use crate::tokenizer::Span;
+use std::fmt;
 use std::str::FromStr;
 use strum_macros::EnumIter;
 
-#[derive(Debug, Clone, Eq, PartialEq, EnumString, EnumIter)]
+#[derive(Debug, Clone, Eq, PartialEq, EnumString, EnumIter, Display)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TimeUnit {
+    #[strum(serialize = "s")]
+    S,
     #[strum(serialize = "ms")]
     MS,
     #[strum(serialize = "us")]
@@ -12,6 +17,8 @@ pub enum TimeUnit {
     NS,
     #[strum(serialize = "ps")]
     PS,
+    #[strum(serialize = "fs")]
+    FS,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -22,13 +29,19 @@ enum BuildState {
 }
 
 impl BuildState {
-    fn next(&self, line_num: usize) -> Result<Self, LoadError> {
+    /// A deserialized timescale has its value and unit.
+    #[cfg(feature = "serde")]
+    fn done() -> Self {
+        BuildState::Done
+    }
+
+    fn next(&self, span: Span) -> Result<Self, LoadError> {
         use BuildState::*;
         match *self {
             Value => Ok(Unit),
             Unit => Ok(Done),
             Done => Err(LoadError::TooManyParameters {
-                line: line_num,
+                span,
                 command: "$timescale".to_string(),
             }),
         }
@@ -36,9 +49,11 @@ impl BuildState {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TimeScale {
     pub value: usize,
     pub unit: TimeUnit,
+    #[cfg_attr(feature = "serde", serde(skip, default = "BuildState::done"))]
     state: BuildState,
 }
 
@@ -48,6 +63,13 @@ impl PartialEq for TimeScale {
     }
 }
 
+/// Writes the timescale as its command, like `$timescale 10 ns $end`.
+impl fmt::Display for TimeScale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "$timescale {} {} $end", self.value, self.unit)
+    }
+}
+
 impl Default for TimeScale {
     fn default() -> Self {
         TimeScale {
@@ -67,22 +89,22 @@ impl TimeScale {
         }
     }
 
-    pub fn append(&mut self, word: &str, line_num: usize) -> Result<(), LoadError> {
+    pub fn append(&mut self, word: &str, span: Span) -> Result<(), LoadError> {
         match self.state {
-            BuildState::Value => self.write_value(word, line_num)?,
-            BuildState::Unit => self.write_unit(word, line_num)?,
+            BuildState::Value => self.write_value(word, span)?,
+            BuildState::Unit => self.write_unit(word, span)?,
             _ => {}
         };
-        self.state = self.state.next(line_num)?;
+        self.state = self.state.next(span)?;
         Ok(())
     }
 
-    fn write_unit(&mut self, word: &str, line_num: usize) -> Result<(), LoadError> {
+    fn write_unit(&mut self, word: &str, span: Span) -> Result<(), LoadError> {
         self.unit = match TimeUnit::from_str(word) {
             Ok(time_scale) => time_scale,
             Err(_) => {
                 return Err(LoadError::InvalidTimeScale {
-                    line: line_num,
+                    span,
                     time_scale: word.to_string(),
                 });
             }
@@ -90,12 +112,12 @@ impl TimeScale {
         Ok(())
     }
 
-    fn write_value(&mut self, word: &str, line_num: usize) -> Result<(), LoadError> {
+    fn write_value(&mut self, word: &str, span: Span) -> Result<(), LoadError> {
         self.value = match word.parse::<usize>() {
             Ok(value) => value,
             Err(_) => {
                 return Err(LoadError::InvalidTimeValue {
-                    line: line_num,
+                    span,
                     value: word.to_string(),
                 });
             }
@@ -111,8 +133,8 @@ mod tests {
     #[test]
     fn build_timescale_1() {
         let mut time_scale = TimeScale::default();
-        time_scale.append("10", 0).unwrap();
-        time_scale.append("ns", 0).unwrap();
+        time_scale.append("10", Span::default()).unwrap();
+        time_scale.append("ns", Span::default()).unwrap();
         assert_eq!(time_scale.value, 10);
         assert_eq!(time_scale.unit, TimeUnit::NS);
     }
@@ -120,8 +142,8 @@ mod tests {
     #[test]
     fn build_timescale_2() {
         let mut time_scale = TimeScale::default();
-        time_scale.append("42", 0).unwrap();
-        time_scale.append("ms", 0).unwrap();
+        time_scale.append("42", Span::default()).unwrap();
+        time_scale.append("ms", Span::default()).unwrap();
         assert_eq!(time_scale.value, 42);
         assert_eq!(time_scale.unit, TimeUnit::MS);
     }
@@ -129,9 +151,9 @@ mod tests {
     #[test]
     fn invalid_number_throws_error() {
         let mut time_scale = TimeScale::default();
-        let err = time_scale.append("NaN", 0).err();
+        let err = time_scale.append("NaN", Span::default()).err();
         let exp_err = LoadError::InvalidTimeValue {
-            line: 0,
+            span: Span::default(),
             value: "NaN".to_string(),
         };
         assert_eq!(err, Some(exp_err));
@@ -140,10 +162,10 @@ mod tests {
     #[test]
     fn invalid_timescale_throws_error() {
         let mut time_scale = TimeScale::default();
-        time_scale.append("10", 0).unwrap();
-        let err = time_scale.append("NotATimeScale", 0).err();
+        time_scale.append("10", Span::default()).unwrap();
+        let err = time_scale.append("NotATimeScale", Span::default()).err();
         let exp_err = LoadError::InvalidTimeScale {
-            line: 0,
+            span: Span::default(),
             time_scale: "NotATimeScale".to_string(),
         };
         assert_eq!(err, Some(exp_err));
@@ -152,13 +174,25 @@ mod tests {
     #[test]
     fn extra_params_in_timescale_throws_error() {
         let mut time_scale = TimeScale::default();
-        time_scale.append("10", 0).unwrap();
-        time_scale.append("us", 0).unwrap();
-        let err = time_scale.append("ExtraParameter", 0).err();
+        time_scale.append("10", Span::default()).unwrap();
+        time_scale.append("us", Span::default()).unwrap();
+        let err = time_scale.append("ExtraParameter", Span::default()).err();
         let exp_err = LoadError::TooManyParameters {
-            line: 0,
+            span: Span::default(),
             command: "$timescale".to_string(),
         };
         assert_eq!(err, Some(exp_err));
     }
+
+    #[test]
+    fn displayed_timescale_builds_back() {
+        let time_scale = TimeScale::new(100, TimeUnit::PS);
+        let text = time_scale.to_string();
+        assert_eq!(text, "$timescale 100 ps $end");
+        let mut built = TimeScale::default();
+        for word in text.split(' ').filter(|word| !word.starts_with('$')) {
+            built.append(word, Span::default()).unwrap();
+        }
+        assert_eq!(built, time_scale);
+    }
 }
@@ -0,0 +1,29 @@
+use crate::string_helpers::append_word;
+use crate::tokenizer::Span;
+
+/// A command this crate doesn't know, like a vendor extension or a typo. It is kept as it was
+/// found so that it can still be made sense of.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UnknownCommand {
+    /// The keyword, including its `$`.
+    pub command: String,
+    /// The words between the keyword and its `$end`, separated by single spaces.
+    pub payload: String,
+    /// Where the keyword was found.
+    pub span: Span,
+}
+
+impl UnknownCommand {
+    pub fn new(command: &str, span: Span) -> Self {
+        UnknownCommand {
+            command: command.to_string(),
+            payload: String::new(),
+            span,
+        }
+    }
+
+    pub fn append(&mut self, word: &str) {
+        append_word(&mut self.payload, word);
+    }
+}
@@ -0,0 +1,405 @@
+use std::convert::{TryFrom, TryInto};
+
+/// A single four-state bit.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Bit {
+    Zero,
+    One,
+    X,
+    Z,
+}
+
+impl Bit {
+    pub fn from_char(c: char) -> Option<Bit> {
+        match c {
+            '0' => Some(Bit::Zero),
+            '1' => Some(Bit::One),
+            'x' | 'X' => Some(Bit::X),
+            'z' | 'Z' => Some(Bit::Z),
+            _ => None,
+        }
+    }
+
+    pub fn to_char(self) -> char {
+        match self {
+            Bit::Zero => '0',
+            Bit::One => '1',
+            Bit::X => 'x',
+            Bit::Z => 'z',
+        }
+    }
+}
+
+/// Which side of a port is driving it, in an extended VCD.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PortDirection {
+    /// Driven by the test fixture.
+    Input,
+    /// Driven by the design under test.
+    Output,
+    /// Driven by both sides, or by neither.
+    Unknown,
+}
+
+/// The state of one bit of a port in an extended VCD, as written in a `p` value change.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PortState {
+    /// `D`: input low.
+    InputLow,
+    /// `U`: input high.
+    InputHigh,
+    /// `N`: input unknown.
+    InputUnknown,
+    /// `Z`: input three-state.
+    InputZ,
+    /// `d`: input low, with two or more drivers active.
+    InputLowMulti,
+    /// `u`: input high, with two or more drivers active.
+    InputHighMulti,
+    /// `L`: output low.
+    OutputLow,
+    /// `H`: output high.
+    OutputHigh,
+    /// `X`: output unknown.
+    OutputUnknown,
+    /// `T`: output three-state.
+    OutputZ,
+    /// `l`: output low, with two or more drivers active.
+    OutputLowMulti,
+    /// `h`: output high, with two or more drivers active.
+    OutputHighMulti,
+    /// `0`: input and output both low.
+    Low,
+    /// `1`: input and output both high.
+    High,
+    /// `?`: unknown.
+    Unknown,
+    /// `F`: input and output both unconnected.
+    Z,
+    /// `A`: input low, output high.
+    InputLowOutputHigh,
+    /// `a`: input low, output unknown.
+    InputLowOutputUnknown,
+    /// `B`: input high, output low.
+    InputHighOutputLow,
+    /// `b`: input high, output unknown.
+    InputHighOutputUnknown,
+    /// `C`: input unknown, output low.
+    InputUnknownOutputLow,
+    /// `c`: input unknown, output high.
+    InputUnknownOutputHigh,
+    /// `f`: input and output both three-stated.
+    BothZ,
+}
+
+impl PortState {
+    pub fn from_char(c: char) -> Option<PortState> {
+        use PortState::*;
+        let state = match c {
+            'D' => InputLow,
+            'U' => InputHigh,
+            'N' => InputUnknown,
+            'Z' => InputZ,
+            'd' => InputLowMulti,
+            'u' => InputHighMulti,
+            'L' => OutputLow,
+            'H' => OutputHigh,
+            'X' => OutputUnknown,
+            'T' => OutputZ,
+            'l' => OutputLowMulti,
+            'h' => OutputHighMulti,
+            '0' => Low,
+            '1' => High,
+            '?' => Unknown,
+            'F' => Z,
+            'A' => InputLowOutputHigh,
+            'a' => InputLowOutputUnknown,
+            'B' => InputHighOutputLow,
+            'b' => InputHighOutputUnknown,
+            'C' => InputUnknownOutputLow,
+            'c' => InputUnknownOutputHigh,
+            'f' => BothZ,
+            _ => return None,
+        };
+        Some(state)
+    }
+
+    pub fn to_char(self) -> char {
+        use PortState::*;
+        match self {
+            InputLow => 'D',
+            InputHigh => 'U',
+            InputUnknown => 'N',
+            InputZ => 'Z',
+            InputLowMulti => 'd',
+            InputHighMulti => 'u',
+            OutputLow => 'L',
+            OutputHigh => 'H',
+            OutputUnknown => 'X',
+            OutputZ => 'T',
+            OutputLowMulti => 'l',
+            OutputHighMulti => 'h',
+            Low => '0',
+            High => '1',
+            Unknown => '?',
+            Z => 'F',
+            InputLowOutputHigh => 'A',
+            InputLowOutputUnknown => 'a',
+            InputHighOutputLow => 'B',
+            InputHighOutputUnknown => 'b',
+            InputUnknownOutputLow => 'C',
+            InputUnknownOutputHigh => 'c',
+            BothZ => 'f',
+        }
+    }
+
+    pub fn direction(self) -> PortDirection {
+        use PortState::*;
+        match self {
+            InputLow | InputHigh | InputUnknown | InputZ | InputLowMulti | InputHighMulti => {
+                PortDirection::Input
+            }
+            OutputLow | OutputHigh | OutputUnknown | OutputZ | OutputLowMulti | OutputHighMulti => {
+                PortDirection::Output
+            }
+            _ => PortDirection::Unknown,
+        }
+    }
+
+    /// The logic level of the port, without direction or strength.
+    pub fn to_bit(self) -> Bit {
+        use PortState::*;
+        match self {
+            InputLow | InputLowMulti | OutputLow | OutputLowMulti | Low => Bit::Zero,
+            InputHigh | InputHighMulti | OutputHigh | OutputHighMulti | High => Bit::One,
+            InputZ | OutputZ | Z | BothZ => Bit::Z,
+            _ => Bit::X,
+        }
+    }
+}
+
+/// Drive strength of a port, from the digits of a `p` value change.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Strength {
+    HighZ,
+    Small,
+    Medium,
+    Weak,
+    Large,
+    Pull,
+    Strong,
+    Supply,
+}
+
+impl Strength {
+    pub fn from_char(c: char) -> Option<Strength> {
+        use Strength::*;
+        let strength = match c {
+            '0' => HighZ,
+            '1' => Small,
+            '2' => Medium,
+            '3' => Weak,
+            '4' => Large,
+            '5' => Pull,
+            '6' => Strong,
+            '7' => Supply,
+            _ => return None,
+        };
+        Some(strength)
+    }
+
+    pub fn to_char(self) -> char {
+        (b'0' + self as u8) as char
+    }
+}
+
+/// One bit of a port in an extended VCD: its state, and the strengths of the 0 and 1 components
+/// driving it.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PortValue {
+    pub state: PortState,
+    pub strength0: Strength,
+    pub strength1: Strength,
+}
+
+/// The value of a variable after a value change.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Value {
+    Scalar(Bit),
+    /// Bits of a vector, most significant bit first.
+    Vector(Vec<Bit>),
+    Real(f64),
+    /// Bits of an extended VCD port, most significant bit first.
+    Port(Vec<PortValue>),
+}
+
+impl Value {
+    /// Left-extends a vector to `width` bits, the way a VCD reader is required to when a dumped
+    /// vector is shorter than its variable: `0` and `1` extend with `0`, `x` and `z` extend with
+    /// themselves. Other values are left as they are.
+    pub fn extend_to(self, width: usize) -> Value {
+        match self {
+            Value::Vector(bits) if bits.len() < width => {
+                let fill = match bits.first() {
+                    Some(Bit::X) => Bit::X,
+                    Some(Bit::Z) => Bit::Z,
+                    _ => Bit::Zero,
+                };
+                let mut extended = Vec::with_capacity(width);
+                extended.resize(width - bits.len(), fill);
+                extended.extend(bits);
+                Value::Vector(extended)
+            }
+            value => value,
+        }
+    }
+
+    /// Appends the value in a binary form: a tag byte, then the characters of the value as
+    /// written in a VCD, after their count for vectors and ports, or the bits of a real.
+    pub fn write_to(&self, bytes: &mut Vec<u8>) {
+        match self {
+            Value::Scalar(bit) => bytes.extend(&[b's', bit.to_char() as u8]),
+            Value::Vector(bits) => {
+                bytes.push(b'b');
+                bytes.extend(&(bits.len() as u64).to_le_bytes());
+                bytes.extend(bits.iter().map(|bit| bit.to_char() as u8));
+            }
+            Value::Real(real) => {
+                bytes.push(b'r');
+                bytes.extend(&real.to_le_bytes());
+            }
+            Value::Port(ports) => {
+                bytes.push(b'p');
+                bytes.extend(&(ports.len() as u64).to_le_bytes());
+                for port in ports {
+                    bytes.push(port.state.to_char() as u8);
+                    bytes.push(port.strength0.to_char() as u8);
+                    bytes.push(port.strength1.to_char() as u8);
+                }
+            }
+        }
+    }
+
+    /// Reads a value written by [`Value::write_to`] at `offset` in `bytes`, moving `offset` past
+    /// it. `None` if there isn't a valid value there.
+    pub fn read_from(bytes: &[u8], offset: &mut usize) -> Option<Value> {
+        let value = match take(bytes, offset, 1)?[0] {
+            b's' => Value::Scalar(Bit::from_char(take(bytes, offset, 1)?[0] as char)?),
+            b'b' => {
+                let len = take_len(bytes, offset)?;
+                let bits = take(bytes, offset, len)?;
+                let bits = bits.iter().map(|c| Bit::from_char(*c as char));
+                Value::Vector(bits.collect::<Option<_>>()?)
+            }
+            b'r' => Value::Real(f64::from_le_bytes(take(bytes, offset, 8)?.try_into().ok()?)),
+            b'p' => {
+                let len = take_len(bytes, offset)?;
+                let ports = take(bytes, offset, len.checked_mul(3)?)?;
+                let ports = ports.chunks(3).map(|port| {
+                    Some(PortValue {
+                        state: PortState::from_char(port[0] as char)?,
+                        strength0: Strength::from_char(port[1] as char)?,
+                        strength1: Strength::from_char(port[2] as char)?,
+                    })
+                });
+                Value::Port(ports.collect::<Option<_>>()?)
+            }
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    /// How many bytes the value takes on the heap, besides its own size.
+    pub fn heap_size(&self) -> usize {
+        match self {
+            Value::Vector(bits) => bits.capacity() * std::mem::size_of::<Bit>(),
+            Value::Port(ports) => ports.capacity() * std::mem::size_of::<PortValue>(),
+            Value::Scalar(_) | Value::Real(_) => 0,
+        }
+    }
+}
+
+/// The `len` bytes at `offset` in `bytes`, moving `offset` past them.
+fn take<'a>(bytes: &'a [u8], offset: &mut usize, len: usize) -> Option<&'a [u8]> {
+    let taken = bytes.get(*offset..offset.checked_add(len)?)?;
+    *offset += len;
+    Some(taken)
+}
+
+fn take_len(bytes: &[u8], offset: &mut usize) -> Option<usize> {
+    let len = take(bytes, offset, 8)?.try_into().ok()?;
+    usize::try_from(u64::from_le_bytes(len)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bits(s: &str) -> Vec<Bit> {
+        s.chars().map(|c| Bit::from_char(c).unwrap()).collect()
+    }
+
+    #[test]
+    fn extend_vector() {
+        assert_eq!(
+            Value::Vector(bits("10")).extend_to(4),
+            Value::Vector(bits("0010"))
+        );
+        assert_eq!(
+            Value::Vector(bits("x0")).extend_to(4),
+            Value::Vector(bits("xxx0"))
+        );
+        assert_eq!(
+            Value::Vector(bits("z")).extend_to(3),
+            Value::Vector(bits("zzz"))
+        );
+    }
+
+    #[test]
+    fn port_state_chars_round_trip() {
+        for c in "DUNZduLHXTlh01?FAaBbCcf".chars() {
+            assert_eq!(PortState::from_char(c).map(PortState::to_char), Some(c));
+        }
+        assert_eq!(PortState::from_char('x'), None);
+    }
+
+    #[test]
+    fn port_state_direction_and_level() {
+        assert_eq!(PortState::InputHigh.direction(), PortDirection::Input);
+        assert_eq!(PortState::OutputZ.direction(), PortDirection::Output);
+        assert_eq!(
+            PortState::InputLowOutputHigh.direction(),
+            PortDirection::Unknown
+        );
+        assert_eq!(PortState::OutputLowMulti.to_bit(), Bit::Zero);
+        assert_eq!(PortState::BothZ.to_bit(), Bit::Z);
+        assert_eq!(PortState::InputHighOutputLow.to_bit(), Bit::X);
+    }
+
+    #[test]
+    fn strength_digits() {
+        assert_eq!(Strength::from_char('6'), Some(Strength::Strong));
+        assert_eq!(Strength::Supply.to_char(), '7');
+        assert_eq!(Strength::from_char('8'), None);
+        assert!(Strength::Pull < Strength::Strong);
+    }
+
+    #[test]
+    fn extend_leaves_full_width_and_other_values_alone() {
+        assert_eq!(
+            Value::Vector(bits("1010")).extend_to(4),
+            Value::Vector(bits("1010"))
+        );
+        assert_eq!(Value::Real(1.5).extend_to(4), Value::Real(1.5));
+        assert_eq!(
+            Value::Scalar(Bit::One).extend_to(4),
+            Value::Scalar(Bit::One)
+        );
+    }
+}
@@ -1,9 +1,13 @@
 use crate::error::LoadError;
-use crate::types::scope::Scope;
+use crate::tokenizer::Span;
+use crate::types::{attribute::Attribute, scope::Scope};
+use std::convert::TryFrom;
+use std::fmt;
 use std::str::FromStr;
 use strum_macros::EnumString;
 
-#[derive(Debug, Clone, Eq, PartialEq, EnumString)]
+#[derive(Debug, Clone, Eq, PartialEq, EnumString, Display)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum VarType {
     #[strum(serialize = "event")]
     Event,
@@ -11,8 +15,13 @@ pub enum VarType {
     Integer,
     #[strum(serialize = "parameter")]
     Parameter,
+    /// A port in an extended VCD.
+    #[strum(serialize = "port")]
+    Port,
     #[strum(serialize = "real")]
     Real,
+    #[strum(serialize = "realtime")]
+    RealTime,
     #[strum(serialize = "reg")]
     Reg,
     #[strum(serialize = "supply0")]
@@ -39,6 +48,39 @@ pub enum VarType {
     Wire,
     #[strum(serialize = "wor")]
     WOr,
+    #[strum(serialize = "logic")]
+    Logic,
+    #[strum(serialize = "bit")]
+    Bit,
+    #[strum(serialize = "byte")]
+    Byte,
+    #[strum(serialize = "int")]
+    Int,
+    #[strum(serialize = "shortint")]
+    ShortInt,
+    #[strum(serialize = "longint")]
+    LongInt,
+    #[strum(serialize = "shortreal")]
+    ShortReal,
+    #[strum(serialize = "enum")]
+    Enum,
+    #[strum(serialize = "string")]
+    String,
+    #[strum(serialize = "real_parameter")]
+    RealParameter,
+    #[strum(serialize = "sparray")]
+    SparseArray,
+}
+
+/// Which way a port carries values, as an FST file says.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VarDirection {
+    Input,
+    Output,
+    InOut,
+    Buffer,
+    Linkage,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -51,7 +93,13 @@ enum BuildState {
 }
 
 impl BuildState {
-    fn next(&self, line_num: usize) -> Result<Self, LoadError> {
+    /// Deserialized variables are complete, as only complete ones are serialized.
+    #[cfg(feature = "serde")]
+    fn done() -> Self {
+        BuildState::Done
+    }
+
+    fn next(&self, span: Span) -> Result<Self, LoadError> {
         use BuildState::*;
         match *self {
             VarType => Ok(Size),
@@ -59,7 +107,7 @@ impl BuildState {
             Identifier => Ok(Reference),
             Reference => Ok(Done),
             Done => Err(LoadError::TooManyParameters {
-                line: line_num,
+                span,
                 command: "var".to_string(),
             }),
         }
@@ -67,14 +115,23 @@ impl BuildState {
 }
 
 #[derive(Debug, Clone, Builder)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Variable {
     pub scope: Vec<Scope>,
     pub var_type: VarType,
     pub bit_width: usize,
     pub ascii_identifier: String,
     pub reference: String,
+    /// Attributes from the `$attrbegin` commands right before the variable.
+    #[builder(default)]
+    pub attributes: Vec<Attribute>,
+    /// The direction of a port. Only FST files give it; it's `None` for variables that aren't
+    /// ports and for those read from a VCD.
+    #[builder(default)]
+    pub direction: Option<VarDirection>,
 
     #[builder(default = "BuildState::VarType", setter(skip))]
+    #[cfg_attr(feature = "serde", serde(skip, default = "BuildState::done"))]
     state: BuildState,
 }
 
@@ -86,21 +143,23 @@ impl Default for Variable {
             bit_width: 0,
             ascii_identifier: "".to_string(),
             reference: "".to_string(),
+            attributes: vec![],
+            direction: None,
             state: BuildState::VarType,
         }
     }
 }
 
 impl Variable {
-    pub fn append(&mut self, word: &str, line_num: usize) -> Result<(), LoadError> {
+    pub fn append(&mut self, word: &str, span: Span) -> Result<(), LoadError> {
         match self.state {
-            BuildState::VarType => self.write_var_type(word, line_num)?,
-            BuildState::Size => self.write_bit_width(word, line_num)?,
+            BuildState::VarType => self.write_var_type(word, span)?,
+            BuildState::Size => self.write_bit_width(word, span)?,
             BuildState::Identifier => self.ascii_identifier = word.to_string(),
             BuildState::Reference => self.reference = word.to_string(),
             _ => {}
         }
-        self.state = self.state.next(line_num)?;
+        self.state = self.state.next(span)?;
         Ok(())
     }
 
@@ -108,12 +167,17 @@ impl Variable {
         self.state == BuildState::Done
     }
 
-    fn write_var_type(&mut self, word: &str, line_num: usize) -> Result<(), LoadError> {
+    /// Whether the next word is the identifier code.
+    pub fn expects_identifier(&self) -> bool {
+        self.state == BuildState::Identifier
+    }
+
+    fn write_var_type(&mut self, word: &str, span: Span) -> Result<(), LoadError> {
         self.var_type = match VarType::from_str(word) {
             Ok(var_type) => var_type,
             Err(_) => {
                 return Err(LoadError::InvalidParameterForCommand {
-                    line: line_num,
+                    span,
                     command: "$var".to_string(),
                     parameter: word.to_string(),
                 })
@@ -122,12 +186,16 @@ impl Variable {
         Ok(())
     }
 
-    fn write_bit_width(&mut self, word: &str, line_num: usize) -> Result<(), LoadError> {
-        self.bit_width = match word.parse::<usize>() {
-            Ok(bit_width) => bit_width,
-            Err(_) => {
+    fn write_bit_width(&mut self, word: &str, span: Span) -> Result<(), LoadError> {
+        let bit_width = match self.var_type {
+            VarType::Port => Variable::parse_port_width(word),
+            _ => word.parse::<usize>().ok(),
+        };
+        self.bit_width = match bit_width {
+            Some(bit_width) => bit_width,
+            None => {
                 return Err(LoadError::InvalidParameterForCommand {
-                    line: line_num,
+                    span,
                     command: "$var".to_string(),
                     parameter: word.to_string(),
                 })
@@ -135,6 +203,18 @@ impl Variable {
         };
         Ok(())
     }
+
+    /// Ports in an extended VCD give their size either as a bit count or as a range like `[7:0]`.
+    fn parse_port_width(word: &str) -> Option<usize> {
+        let range = match word.strip_prefix('[').and_then(|w| w.strip_suffix(']')) {
+            Some(range) => range,
+            None => return word.parse::<usize>().ok(),
+        };
+        let (msb, lsb) = range.split_once(':')?;
+        let (msb, lsb) = (msb.parse::<i64>().ok()?, lsb.parse::<i64>().ok()?);
+        let width = usize::try_from(msb.checked_sub(lsb)?.unsigned_abs()).ok()?;
+        width.checked_add(1)
+    }
 }
 
 impl PartialEq for Variable {
@@ -144,6 +224,20 @@ impl PartialEq for Variable {
             && self.bit_width == other.bit_width
             && self.ascii_identifier == other.ascii_identifier
             && self.reference == other.reference
+            && self.attributes == other.attributes
+            && self.direction == other.direction
+    }
+}
+
+/// Writes the variable as its command, like `$var wire 8 # data $end`. Ports are written with
+/// their bit count rather than a range. Neither the scopes nor the attributes are written.
+impl fmt::Display for Variable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "$var {} {} {} {} $end",
+            self.var_type, self.bit_width, self.ascii_identifier, self.reference
+        )
     }
 }
 
@@ -163,7 +257,7 @@ mod tests {
             .unwrap();
         let mut act_var = Variable::default();
         for word in &["wire", "8", "#", "data"] {
-            act_var.append(word, 0).unwrap();
+            act_var.append(word, Span::default()).unwrap();
         }
         assert_eq!(exp_var, act_var);
         assert!(act_var.is_done());
@@ -182,18 +276,29 @@ mod tests {
 
         let mut act_var = Variable::default();
         for word in &["trireg", "4", "e", "my_reference"] {
-            act_var.append(word, 0).unwrap();
+            act_var.append(word, Span::default()).unwrap();
         }
         assert_eq!(exp_var, act_var);
         assert!(act_var.is_done());
     }
 
+    #[test]
+    fn build_port_with_range() {
+        let mut act_var = Variable::default();
+        for word in &["port", "[0:7]", "<1", "data"] {
+            act_var.append(word, Span::default()).unwrap();
+        }
+        assert_eq!(act_var.var_type, VarType::Port);
+        assert_eq!(act_var.bit_width, 8);
+        assert_eq!(act_var.ascii_identifier, "<1");
+    }
+
     #[test]
     fn invalid_var_type_throws_error() {
         let mut act_var = Variable::default();
-        let err = act_var.append("NotAVarType", 0).err();
+        let err = act_var.append("NotAVarType", Span::default()).err();
         let exp_err = LoadError::InvalidParameterForCommand {
-            line: 0,
+            span: Span::default(),
             command: "$var".to_string(),
             parameter: "NotAVarType".to_string(),
         };
@@ -203,27 +308,71 @@ mod tests {
     #[test]
     fn non_digit_bit_width_throws_error() {
         let mut act_var = Variable::default();
-        act_var.append("wire", 0).unwrap();
-        let err = act_var.append("NotADigit", 0).err();
+        act_var.append("wire", Span::default()).unwrap();
+        let err = act_var.append("NotADigit", Span::default()).err();
         let exp_err = LoadError::InvalidParameterForCommand {
-            line: 0,
+            span: Span::default(),
             command: "$var".to_string(),
             parameter: "NotADigit".to_string(),
         };
         assert_eq!(err, Some(exp_err));
     }
 
+    #[test]
+    fn overflowing_port_range_throws_error() {
+        let range = "[9223372036854775807:-1]";
+        let mut act_var = Variable::default();
+        act_var.append("port", Span::default()).unwrap();
+        let err = act_var.append(range, Span::default()).err();
+        let exp_err = LoadError::InvalidParameterForCommand {
+            span: Span::default(),
+            command: "$var".to_string(),
+            parameter: range.to_string(),
+        };
+        assert_eq!(err, Some(exp_err));
+    }
+
     #[test]
     fn extra_params_in_var_throws_error() {
         let mut act_var = Variable::default();
         for word in &["wire", "8", "e", "my_reference"] {
-            act_var.append(word, 0).unwrap();
+            act_var.append(word, Span::default()).unwrap();
         }
-        let err = act_var.append("ExtraParam", 0).err();
+        let err = act_var.append("ExtraParam", Span::default()).err();
         let exp_err = LoadError::TooManyParameters {
-            line: 0,
+            span: Span::default(),
             command: "var".to_string(),
         };
         assert_eq!(err, Some(exp_err));
     }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn deserialized_variable_is_done() {
+        let mut var = Variable::default();
+        for word in &["wire", "8", "#", "data"] {
+            var.append(word, Span::default()).unwrap();
+        }
+        let json = serde_json::to_string(&var).unwrap();
+        assert!(!json.contains("state"));
+        let deserialized: Variable = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, var);
+        assert!(deserialized.is_done());
+    }
+
+    #[test]
+    fn displayed_variable_builds_back() {
+        let mut var = Variable::default();
+        for word in &["port", "[0:7]", "<1", "data"] {
+            var.append(word, Span::default()).unwrap();
+        }
+        let text = var.to_string();
+        assert_eq!(text, "$var port 8 <1 data $end");
+        let mut built = Variable::default();
+        for word in text.split(' ').filter(|word| !word.starts_with('$')) {
+            built.append(word, Span::default()).unwrap();
+        }
+        assert_eq!(built, var);
+        assert!(built.is_done());
+    }
 }
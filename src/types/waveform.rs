@@ -0,0 +1,155 @@
+use crate::error::LoadError;
+use crate::types::value::Value;
+use std::mem::size_of;
+
+/// Queries on the value changes of one variable, however they are stored. Values are returned
+/// by value, as storage other than [`Waveform`] decodes them on the fly.
+pub trait WaveformQuery {
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The value the variable has at `time`, i.e. the value of the last change at or before it.
+    fn value_at(&self, time: u64) -> Option<Value>;
+
+    fn last_value(&self) -> Option<Value>;
+
+    /// The changes, in time order.
+    fn iter(&self) -> Box<dyn Iterator<Item = (u64, Value)> + '_>;
+
+    /// How many bytes the changes take in memory, counting the struct itself.
+    fn memory_usage(&self) -> usize;
+}
+
+/// The value changes of one variable, in time order.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Waveform {
+    changes: Vec<(u64, Value)>,
+}
+
+impl Waveform {
+    pub fn new() -> Self {
+        Waveform::default()
+    }
+
+    pub fn push(&mut self, time: u64, value: Value) {
+        self.changes.push((time, value));
+    }
+
+    /// Appends the changes of `other`, which come after those of this waveform.
+    pub fn extend(&mut self, mut other: Waveform) {
+        self.changes.append(&mut other.changes);
+    }
+
+    /// Drops all changes but the last one.
+    pub fn forget_all_but_last(&mut self) {
+        let earlier = self.changes.len().saturating_sub(1);
+        self.changes.drain(..earlier);
+    }
+
+    /// Takes all changes but the last one out of this waveform.
+    pub fn take_all_but_last(&mut self) -> Waveform {
+        let last = self.changes.pop();
+        let earlier = std::mem::take(&mut self.changes);
+        self.changes.extend(last);
+        Waveform { changes: earlier }
+    }
+
+    pub fn changes(&self) -> &[(u64, Value)] {
+        &self.changes
+    }
+
+    pub fn last_value(&self) -> Option<&Value> {
+        self.changes.last().map(|(_, value)| value)
+    }
+
+    /// The value the variable has at `time`, i.e. the value of the last change at or before it.
+    pub fn value_at(&self, time: u64) -> Option<&Value> {
+        let after = self.changes.partition_point(|(t, _)| *t <= time);
+        match after {
+            0 => None,
+            _ => Some(&self.changes[after - 1].1),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+/// The waveforms of all variables of a dump, however they are stored.
+pub trait WaveformStore {
+    /// The identifiers of the variables with value changes, in no particular order.
+    fn identifiers(&self) -> Vec<String>;
+
+    /// Runs `query` on the waveform of the variable with identifier `identifier`, if it has
+    /// value changes. Fails if the waveform has to be read back from disk and can't be.
+    fn query<R>(
+        &self,
+        identifier: &str,
+        query: impl FnOnce(&dyn WaveformQuery) -> R,
+    ) -> Result<Option<R>, LoadError>;
+}
+
+impl WaveformQuery for Waveform {
+    fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    fn value_at(&self, time: u64) -> Option<Value> {
+        Waveform::value_at(self, time).cloned()
+    }
+
+    fn last_value(&self) -> Option<Value> {
+        Waveform::last_value(self).cloned()
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (u64, Value)> + '_> {
+        Box::new(self.changes.iter().cloned())
+    }
+
+    fn memory_usage(&self) -> usize {
+        let heap: usize = self
+            .changes
+            .iter()
+            .map(|(_, value)| value.heap_size())
+            .sum();
+        size_of::<Self>() + self.changes.capacity() * size_of::<(u64, Value)>() + heap
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::value::Bit;
+
+    #[test]
+    fn value_at() {
+        let mut waveform = Waveform::new();
+        waveform.push(0, Value::Scalar(Bit::X));
+        waveform.push(10, Value::Scalar(Bit::One));
+        waveform.push(10, Value::Scalar(Bit::Zero));
+        waveform.push(20, Value::Scalar(Bit::One));
+        assert_eq!(waveform.value_at(0), Some(&Value::Scalar(Bit::X)));
+        assert_eq!(waveform.value_at(9), Some(&Value::Scalar(Bit::X)));
+        assert_eq!(waveform.value_at(10), Some(&Value::Scalar(Bit::Zero)));
+        assert_eq!(waveform.value_at(25), Some(&Value::Scalar(Bit::One)));
+        assert_eq!(waveform.len(), 4);
+    }
+
+    #[test]
+    fn value_before_first_change() {
+        let mut waveform = Waveform::new();
+        assert_eq!(waveform.value_at(5), None);
+        waveform.push(5, Value::Real(1.0));
+        assert_eq!(waveform.value_at(4), None);
+        assert_eq!(waveform.last_value(), Some(&Value::Real(1.0)));
+    }
+}
@@ -1,11 +1,62 @@
-use crate::types::{timescale::TimeScale, variable::Variable};
+use crate::compact::{memory_report, SignalMemory};
+use crate::error::LoadError;
+use crate::types::{
+    attribute::EnumTable,
+    timescale::TimeScale,
+    unknown_command::UnknownCommand,
+    variable::Variable,
+    waveform::{Waveform, WaveformQuery, WaveformStore},
+};
 use std::collections::HashMap;
 
 #[derive(Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct VCD {
     pub date: String,
     pub version: String,
     pub timescale: TimeScale,
     pub comments: Vec<String>,
     pub variables: HashMap<String, Variable>,
+    /// Variables declared with the identifier code of an earlier variable, in the order they were
+    /// declared. They share that variable's waveform.
+    pub aliases: Vec<Variable>,
+    /// Value changes of each variable, keyed by the variable's identifier code.
+    pub waveforms: HashMap<String, Waveform>,
+    /// Commands that weren't recognized, in the order they were found.
+    pub unknown_commands: Vec<UnknownCommand>,
+    /// Enum tables defined by `$attrbegin` attributes, keyed by their handle.
+    pub enum_tables: HashMap<u64, EnumTable>,
+}
+
+impl VCD {
+    /// The enum table `variable` refers to, whose names can be used to show its values.
+    pub fn enum_table(&self, variable: &Variable) -> Option<&EnumTable> {
+        variable
+            .attributes
+            .iter()
+            .find_map(|attribute| attribute.enum_table_handle())
+            .and_then(|handle| self.enum_tables.get(&handle))
+    }
+
+    /// The memory taken by the waveform of each variable, largest first.
+    pub fn memory_report(&self) -> Vec<SignalMemory> {
+        memory_report(&self.waveforms)
+    }
+}
+
+impl WaveformStore for VCD {
+    fn identifiers(&self) -> Vec<String> {
+        self.waveforms.keys().cloned().collect()
+    }
+
+    fn query<R>(
+        &self,
+        identifier: &str,
+        query: impl FnOnce(&dyn WaveformQuery) -> R,
+    ) -> Result<Option<R>, LoadError> {
+        Ok(self
+            .waveforms
+            .get(identifier)
+            .map(|waveform| query(waveform)))
+    }
 }
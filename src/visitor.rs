@@ -0,0 +1,340 @@
+//! Handing what a VCD holds to a [`VcdVisitor`] as it's parsed, for tools that keep it in their
+//! own data model instead of a [`VCD`].
+//!
+//! The [`StateMachine`] tokenizes and validates the input, and calls the visitor for each command
+//! and value change once it's complete and checked. [`VcdBuilder`], the visitor every `load_*`
+//! function uses, builds a [`VCD`] out of them.
+
+use crate::error::LoadError;
+use crate::options::ParseOptions;
+use crate::parser::LineReader;
+use crate::state_machine::StateMachine;
+use crate::tokenizer::Position;
+use crate::types::{
+    attribute::EnumTable, scope::Scope, timescale::TimeScale, unknown_command::UnknownCommand,
+    value::Value, variable::Variable, waveform::Waveform,
+};
+use crate::vcd::VCD;
+use crate::warning::Warning;
+
+/// Called by the parser for each part of a VCD, in the order they appear in the input. Every
+/// method does nothing by default, so a visitor only implements those it needs.
+pub trait VcdVisitor {
+    fn on_date(&mut self, _date: &str) {}
+
+    fn on_version(&mut self, _version: &str) {}
+
+    fn on_timescale(&mut self, _timescale: &TimeScale) {}
+
+    fn on_comment(&mut self, _comment: &str) {}
+
+    /// A `$scope`, which the following variables are in until the matching `$upscope`.
+    fn on_scope(&mut self, _scope: &Scope) {}
+
+    fn on_upscope(&mut self) {}
+
+    /// A `$var`. Variables are numbered by `index` in the order their identifier codes are first
+    /// declared, so a visitor can keep them in a `Vec`. A variable declared with the identifier
+    /// code of an earlier one gets that one's index.
+    fn on_var(&mut self, _index: usize, _variable: &Variable) {}
+
+    /// An enum table defined by an `$attrbegin` attribute, which variables refer to by `handle`.
+    fn on_enum_table(&mut self, _handle: u64, _table: &EnumTable) {}
+
+    /// A command this crate doesn't know, which was skipped.
+    fn on_unknown_command(&mut self, _command: &UnknownCommand) {}
+
+    fn on_enddefinitions(&mut self) {}
+
+    fn on_timestamp(&mut self, _time: u64) {}
+
+    /// A command like `$dumpvars` that starts a block of value changes.
+    fn on_dump_command(&mut self, _command: DumpCommand) {}
+
+    /// A value change of the variable numbered `index`, extended to the variable's width.
+    fn on_change(&mut self, _time: u64, _index: usize, _identifier: &str, _value: Value) {}
+
+    /// The end of the input.
+    fn on_finish(&mut self) {}
+}
+
+/// The commands that start a block of value changes.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum DumpCommand {
+    DumpAll,
+    DumpOff,
+    DumpOn,
+    DumpVars,
+    DumpPorts,
+    DumpPortsOff,
+    DumpPortsOn,
+    DumpPortsAll,
+}
+
+/// The visitor that builds a [`VCD`].
+#[derive(Default, Clone)]
+pub struct VcdBuilder {
+    pub vcd: VCD,
+    /// The identifier code and changes of each variable, by index, until the end of the input
+    /// moves them into the VCD.
+    waveforms: Vec<(String, Waveform)>,
+}
+
+impl VcdBuilder {
+    pub fn new() -> Self {
+        VcdBuilder::default()
+    }
+
+    /// A builder for a later piece of the value changes, with the variables of this one but
+    /// none of their changes.
+    pub fn fork(&self) -> VcdBuilder {
+        VcdBuilder {
+            vcd: VCD::default(),
+            waveforms: self
+                .waveforms
+                .iter()
+                .map(|(identifier, _)| (identifier.clone(), Waveform::new()))
+                .collect(),
+        }
+    }
+
+    /// The changes of each variable built so far, by index.
+    pub fn waveforms(&self) -> impl Iterator<Item = (&str, &Waveform)> {
+        self.waveforms
+            .iter()
+            .map(|(identifier, waveform)| (identifier.as_str(), waveform))
+    }
+
+    pub fn waveform(&self, index: usize) -> &Waveform {
+        &self.waveforms[index].1
+    }
+
+    pub fn waveform_mut(&mut self, index: usize) -> &mut Waveform {
+        &mut self.waveforms[index].1
+    }
+}
+
+impl VcdVisitor for VcdBuilder {
+    fn on_date(&mut self, date: &str) {
+        self.vcd.date = date.to_string();
+    }
+
+    fn on_version(&mut self, version: &str) {
+        self.vcd.version = version.to_string();
+    }
+
+    fn on_timescale(&mut self, timescale: &TimeScale) {
+        self.vcd.timescale = timescale.clone();
+    }
+
+    fn on_comment(&mut self, comment: &str) {
+        self.vcd.comments.push(comment.to_string());
+    }
+
+    fn on_var(&mut self, index: usize, variable: &Variable) {
+        match index < self.waveforms.len() {
+            true => self.vcd.aliases.push(variable.clone()),
+            false => {
+                let identifier = variable.ascii_identifier.clone();
+                self.waveforms.push((identifier.clone(), Waveform::new()));
+                self.vcd.variables.insert(identifier, variable.clone());
+            }
+        }
+    }
+
+    fn on_enum_table(&mut self, handle: u64, table: &EnumTable) {
+        self.vcd.enum_tables.insert(handle, table.clone());
+    }
+
+    fn on_unknown_command(&mut self, command: &UnknownCommand) {
+        self.vcd.unknown_commands.push(command.clone());
+    }
+
+    fn on_change(&mut self, time: u64, index: usize, _identifier: &str, value: Value) {
+        self.waveforms[index].1.push(time, value);
+    }
+
+    fn on_finish(&mut self) {
+        for (identifier, waveform) in &mut self.waveforms {
+            let waveform = std::mem::take(waveform);
+            if !waveform.is_empty() {
+                self.vcd.waveforms.insert(identifier.clone(), waveform);
+            }
+        }
+    }
+}
+
+/// What a visitor was left with after loading, and the errors and warnings found along the way,
+/// like a [`crate::report::LoadReport`].
+pub struct VisitReport<V> {
+    pub visitor: V,
+    pub errors: Vec<LoadError>,
+    pub warnings: Vec<Warning>,
+}
+
+/// Parses `s` the way `options` say, handing what it holds to `visitor`.
+pub fn visit_str<V: VcdVisitor>(
+    s: &str,
+    options: &ParseOptions,
+    visitor: V,
+) -> Result<VisitReport<V>, LoadError> {
+    let mut state_machine = StateMachine::with_visitor(visitor, options.clone());
+    let mut errors = vec![];
+    crate::parse_text(&mut state_machine, s, Position::new(), options, &mut errors)?;
+    finish(state_machine, options, errors)
+}
+
+/// The file equivalent of [`visit_str`]. A file that can't be opened is an error, or is reported
+/// like the other errors when recovering, in which case `visitor` isn't called at all.
+pub fn visit_file<V: VcdVisitor>(
+    filename: String,
+    options: &ParseOptions,
+    visitor: V,
+) -> Result<VisitReport<V>, LoadError> {
+    let mut state_machine = StateMachine::with_visitor(visitor, options.clone());
+    let mut errors = vec![];
+    match crate::open_file(filename) {
+        Ok(reader) => {
+            let mut reader = LineReader::new(reader);
+            while let Some(line) = reader.next_line() {
+                match line {
+                    Ok((line, position)) => {
+                        crate::parse_text(&mut state_machine, line, position, options, &mut errors)?
+                    }
+                    Err(err) => crate::record(err, options, &mut errors)?,
+                }
+            }
+            finish(state_machine, options, errors)
+        }
+        Err(err) => {
+            crate::record(err, options, &mut errors)?;
+            Ok(VisitReport {
+                visitor: state_machine.visitor,
+                errors,
+                warnings: vec![],
+            })
+        }
+    }
+}
+
+fn finish<V: VcdVisitor>(
+    mut state_machine: StateMachine<V>,
+    options: &ParseOptions,
+    mut errors: Vec<LoadError>,
+) -> Result<VisitReport<V>, LoadError> {
+    if let Err(err) = state_machine.cleanup() {
+        crate::record(err, options, &mut errors)?;
+    }
+    Ok(VisitReport {
+        visitor: state_machine.visitor,
+        errors,
+        warnings: state_machine.warnings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::value::Bit;
+
+    /// Writes each call down as a line of text.
+    #[derive(Default)]
+    struct Recorder {
+        calls: Vec<String>,
+    }
+
+    impl VcdVisitor for Recorder {
+        fn on_timescale(&mut self, timescale: &TimeScale) {
+            let (value, unit) = (timescale.value, &timescale.unit);
+            self.calls.push(format!("timescale {} {:?}", value, unit));
+        }
+
+        fn on_comment(&mut self, comment: &str) {
+            self.calls.push(format!("comment {}", comment));
+        }
+
+        fn on_scope(&mut self, scope: &Scope) {
+            self.calls.push(format!("scope {}", scope.identifier));
+        }
+
+        fn on_upscope(&mut self) {
+            self.calls.push("upscope".to_string());
+        }
+
+        fn on_var(&mut self, index: usize, variable: &Variable) {
+            let reference = &variable.reference;
+            self.calls.push(format!("var {} {}", index, reference));
+        }
+
+        fn on_enddefinitions(&mut self) {
+            self.calls.push("enddefinitions".to_string());
+        }
+
+        fn on_timestamp(&mut self, time: u64) {
+            self.calls.push(format!("#{}", time));
+        }
+
+        fn on_dump_command(&mut self, command: DumpCommand) {
+            self.calls.push(format!("{:?}", command));
+        }
+
+        fn on_change(&mut self, time: u64, index: usize, identifier: &str, value: Value) {
+            let value = format!("{:?}", value);
+            self.calls
+                .push(format!("{} {} {} {}", time, index, identifier, value));
+        }
+
+        fn on_finish(&mut self) {
+            self.calls.push("finish".to_string());
+        }
+    }
+
+    const DUMP: &str = "$timescale 10 ns $end\n$scope module top $end\n\
+                        $var wire 1 ! clk $end\n$scope module sub $end\n\
+                        $var wire 1 ! alias $end\n$var wire 2 \" d $end\n$upscope $end\n\
+                        $upscope $end\n$enddefinitions $end\n$comment hi $end\n\
+                        #0\n$dumpvars\n0!\nb1 \"\n$end\n#5\n1!\n";
+
+    #[test]
+    fn visitor_is_called_in_input_order() {
+        let report = visit_str(DUMP, &ParseOptions::default(), Recorder::default()).unwrap();
+        assert!(report.errors.is_empty());
+        let zero = format!("{:?}", Value::Scalar(Bit::Zero));
+        let one = format!("{:?}", Value::Scalar(Bit::One));
+        let d = format!("{:?}", Value::Vector(vec![Bit::Zero, Bit::One]));
+        assert_eq!(
+            report.visitor.calls,
+            vec![
+                "timescale 10 NS".to_string(),
+                "scope top".to_string(),
+                "var 0 clk".to_string(),
+                "scope sub".to_string(),
+                "var 0 alias".to_string(),
+                "var 1 d".to_string(),
+                "upscope".to_string(),
+                "upscope".to_string(),
+                "enddefinitions".to_string(),
+                "comment hi".to_string(),
+                "#0".to_string(),
+                "DumpVars".to_string(),
+                format!("0 0 ! {}", zero),
+                format!("0 1 \" {}", d),
+                "#5".to_string(),
+                format!("5 0 ! {}", one),
+                "finish".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn builder_builds_the_loaded_vcd() {
+        let options = ParseOptions::default();
+        let report = visit_str(DUMP, &options, VcdBuilder::new()).unwrap();
+        let vcd = crate::load_from_str(DUMP).unwrap();
+        assert_eq!(report.visitor.vcd.timescale, vcd.timescale);
+        assert_eq!(report.visitor.vcd.comments, vcd.comments);
+        assert_eq!(report.visitor.vcd.variables, vcd.variables);
+        assert_eq!(report.visitor.vcd.aliases, vcd.aliases);
+        assert_eq!(report.visitor.vcd.waveforms, vcd.waveforms);
+    }
+}
@@ -0,0 +1,112 @@
+use crate::tokenizer::Span;
+use std::collections::HashMap;
+use strum_macros::EnumIter;
+
+/// Things that are legal, or at least common in practice, but still suspicious.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, EnumIter)]
+pub enum WarningKind {
+    /// A `$comment` in the value change section. Comments are kept, but not where in time they
+    /// were found.
+    CommentInValueChanges,
+    /// A `$date` command with no text.
+    EmptyDate,
+    /// No `$timescale` command, so the timescale silently defaults to 0 ms.
+    MissingTimescale,
+    /// A variable that is declared but never gets a value.
+    NeverDumped,
+    /// A value change to the value the variable already has.
+    RepeatedValue,
+    /// A command this crate doesn't know. It is skipped, and kept in
+    /// [`crate::vcd::VCD::unknown_commands`].
+    UnknownCommand,
+}
+
+impl WarningKind {
+    /// A short code identifying the kind of warning. Like error codes, these are stable.
+    pub fn code(&self) -> &'static str {
+        use WarningKind::*;
+        match self {
+            CommentInValueChanges => "W001",
+            EmptyDate => "W002",
+            MissingTimescale => "W003",
+            NeverDumped => "W004",
+            RepeatedValue => "W005",
+            UnknownCommand => "W006",
+        }
+    }
+}
+
+/// What to do when a warning is found.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Level {
+    /// Ignore the warning.
+    Allow,
+    /// Report the warning next to the loaded VCD.
+    Warn,
+    /// Treat the warning as an error.
+    Deny,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub span: Span,
+    pub message: String,
+}
+
+impl Warning {
+    pub fn new(kind: WarningKind, span: Span, message: String) -> Self {
+        Warning {
+            kind,
+            span,
+            message,
+        }
+    }
+}
+
+/// The [`Level`] of each kind of warning. Every kind is [`Level::Warn`] unless configured
+/// otherwise.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct WarningConfig {
+    levels: HashMap<WarningKind, Level>,
+}
+
+impl WarningConfig {
+    pub fn new() -> Self {
+        WarningConfig::default()
+    }
+
+    pub fn set(&mut self, kind: WarningKind, level: Level) -> &mut Self {
+        self.levels.insert(kind, level);
+        self
+    }
+
+    pub fn level(&self, kind: WarningKind) -> Level {
+        *self.levels.get(&kind).unwrap_or(&Level::Warn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use strum::IntoEnumIterator;
+
+    #[test]
+    fn every_kind_warns_by_default() {
+        let config = WarningConfig::new();
+        for kind in WarningKind::iter() {
+            assert_eq!(config.level(kind), Level::Warn);
+        }
+    }
+
+    #[test]
+    fn set_level() {
+        let mut config = WarningConfig::new();
+        config
+            .set(WarningKind::RepeatedValue, Level::Allow)
+            .set(WarningKind::EmptyDate, Level::Deny);
+        assert_eq!(config.level(WarningKind::RepeatedValue), Level::Allow);
+        assert_eq!(config.level(WarningKind::EmptyDate), Level::Deny);
+        assert_eq!(config.level(WarningKind::NeverDumped), Level::Warn);
+    }
+}
@@ -25,3 +25,614 @@ fn parse_vcd_file() {
         vcd_rust::types::timescale::TimeScale::new(1, vcd_rust::types::timescale::TimeUnit::PS)
     )
 }
+
+#[test]
+fn diagnostic_for_bad_vcd_file() {
+    let test_file = get_test_file_path("dangling_end.bad.vcd");
+    let err = vcd_rust::load_from_file(test_file.clone()).err().unwrap();
+    assert_eq!(err.filename(), Some(test_file.as_str()));
+    assert_eq!(
+        err.to_string(),
+        format!("{}: line 7, column 1: Dangling $end", test_file)
+    );
+    let text = std::fs::read_to_string(&test_file).unwrap();
+    assert_eq!(
+        err.without_filename(),
+        &vcd_rust::load_from_str(&text).err().unwrap()
+    );
+    let diagnostic = vcd_rust::diagnostic::Diagnostic::from_file(&err, &test_file);
+    assert_eq!(diagnostic.code, "E005");
+    assert_eq!(diagnostic.source_line, Some("$end".to_string()));
+    assert_eq!(
+        diagnostic.render(),
+        format!(
+            "error[E005]: Dangling $end\n --> {}:7:1\n  |\n7 | $end\n  | ^^^^\n",
+            test_file
+        )
+    );
+}
+
+#[test]
+fn recovering_load_of_bad_vcd_file() {
+    let test_file = get_test_file_path("dangling_end.bad.vcd");
+    let report = vcd_rust::load_from_file_recovering(test_file.clone());
+    assert_eq!(report.errors.len(), 1);
+    assert_eq!(report.errors[0].code(), "E005");
+    assert_eq!(report.errors[0].filename(), Some(test_file.as_str()));
+    assert_eq!(report.vcd.date, "August 9th, 2020");
+    assert_eq!(report.vcd.version, "Version 4.20");
+}
+
+#[test]
+fn recovering_load_of_missing_file() {
+    let report = vcd_rust::load_from_file_recovering("does_not_exist.vcd".to_string());
+    assert_eq!(report.errors.len(), 1);
+    assert_eq!(report.errors[0].code(), "E001");
+}
+
+#[test]
+fn parse_vcd_file_with_value_changes() {
+    use vcd_rust::types::value::{Bit, Value};
+    let test_file = get_test_file_path("vardump.golden.vcd");
+    let (vcd, warnings) =
+        vcd_rust::load_from_file_with_warnings(test_file, &Default::default()).unwrap();
+
+    assert_eq!(warnings, vec![]);
+    assert_eq!(vcd.waveforms["#"].len(), 3);
+    assert_eq!(
+        vcd.waveforms["!"].value_at(7),
+        Some(&Value::Scalar(Bit::One))
+    );
+}
+
+#[test]
+fn header_only_load_of_file() {
+    use vcd_rust::body::{body_info, BodyInfo};
+    let test_file = get_test_file_path("counter.vcd");
+    let vcd = vcd_rust::load_from_file(test_file.clone()).unwrap();
+    let header = vcd_rust::load_header_from_file(test_file.clone()).unwrap();
+    assert_eq!(header.date, vcd.date);
+    assert_eq!(header.variables, vcd.variables);
+    assert_eq!(header.aliases, vcd.aliases);
+    assert!(header.waveforms.is_empty());
+
+    let text = std::fs::read_to_string(&test_file).unwrap();
+    let offset = text.find("\n#0").unwrap() as u64;
+    assert_eq!(
+        body_info(test_file),
+        Ok(Some(BodyInfo {
+            offset,
+            len: text.len() as u64 - offset,
+            time_range: Some((0, 40)),
+        }))
+    );
+    let no_definitions = get_test_file_path("no_vardump.golden.vcd");
+    assert_eq!(body_info(no_definitions), Ok(None));
+}
+
+#[test]
+fn visitor_sees_every_change_of_file() {
+    use vcd_rust::types::value::Value;
+    use vcd_rust::visitor::{visit_file, VcdBuilder, VcdVisitor};
+
+    #[derive(Default)]
+    struct CountChanges {
+        changes: Vec<usize>,
+    }
+
+    impl VcdVisitor for CountChanges {
+        fn on_change(&mut self, _time: u64, index: usize, _identifier: &str, _value: Value) {
+            if index >= self.changes.len() {
+                self.changes.resize(index + 1, 0);
+            }
+            self.changes[index] += 1;
+        }
+    }
+
+    let test_file = get_test_file_path("vardump.golden.vcd");
+    let options = Default::default();
+    let vcd = vcd_rust::load_from_file(test_file.clone()).unwrap();
+    let counted = visit_file(test_file.clone(), &options, CountChanges::default()).unwrap();
+    let mut counts = counted.visitor.changes;
+    counts.sort_unstable();
+    let mut expected: Vec<_> = vcd
+        .waveforms
+        .values()
+        .map(|waveform| waveform.len())
+        .collect();
+    expected.sort_unstable();
+    assert_eq!(counts, expected);
+
+    let built = visit_file(test_file, &options, VcdBuilder::new()).unwrap();
+    assert_eq!(built.visitor.vcd.variables, vcd.variables);
+    assert_eq!(built.visitor.vcd.waveforms, vcd.waveforms);
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn mapped_file_loads_like_the_read_file() {
+    for filename in &["vardump.golden.vcd", "counter.vcd", "dangling_end.bad.vcd"] {
+        let options = vcd_rust::options::ParseOptions {
+            recover: true,
+            ..Default::default()
+        };
+        let read = vcd_rust::load_from_file_with_options(get_test_file_path(filename), &options);
+        let mapped =
+            vcd_rust::load_from_file_mmap_with_options(get_test_file_path(filename), &options);
+        let (read, mapped) = (read.unwrap(), mapped.unwrap());
+        assert_eq!(mapped.errors, read.errors, "{}", filename);
+        assert_eq!(mapped.warnings, read.warnings, "{}", filename);
+        assert_eq!(mapped.vcd.variables, read.vcd.variables, "{}", filename);
+        assert_eq!(mapped.vcd.waveforms, read.vcd.waveforms, "{}", filename);
+    }
+    let err = vcd_rust::load_from_file_mmap("does_not_exist.vcd".to_string()).err();
+    assert_eq!(err.map(|err| err.code()), Some("E001"));
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn parallel_load_of_file() {
+    let options = Default::default();
+    let test_file = get_test_file_path("vardump.golden.vcd");
+    let vcd = vcd_rust::load_from_file(test_file.clone()).unwrap();
+    let report = vcd_rust::load_from_file_parallel(test_file, &options, 4).unwrap();
+    assert_eq!(report.vcd.variables, vcd.variables);
+    assert_eq!(report.vcd.waveforms, vcd.waveforms);
+    let test_file = get_test_file_path("dangling_end.bad.vcd");
+    let err = vcd_rust::load_from_file_parallel(test_file, &options, 4).err();
+    assert_eq!(err.map(|err| err.code()), Some("E005"));
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn async_load_of_file() {
+    use std::ops::ControlFlow;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+    use vcd_rust::async_reader;
+    use vcd_rust::options::{ParseOptions, Progress, ProgressHook};
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let read = Arc::new(AtomicU64::new(0));
+    let seen = Arc::clone(&read);
+    let options = ParseOptions {
+        progress: Some(ProgressHook::new(1, move |progress: &Progress| {
+            seen.store(progress.bytes, Ordering::Relaxed);
+            ControlFlow::Continue(())
+        })),
+        ..ParseOptions::default()
+    };
+    let test_file = get_test_file_path("vardump.golden.vcd");
+    let vcd = vcd_rust::load_from_file(test_file.clone()).unwrap();
+    let report = runtime.block_on(async_reader::load_from_file(test_file.clone(), &options));
+    let report = report.unwrap();
+    assert_eq!(report.vcd.variables, vcd.variables);
+    assert_eq!(report.vcd.waveforms, vcd.waveforms);
+    let text = std::fs::read_to_string(&test_file).unwrap();
+    assert_eq!(read.load(Ordering::Relaxed), text.trim_end().len() as u64);
+
+    let test_file = get_test_file_path("dangling_end.bad.vcd");
+    let err = runtime
+        .block_on(async_reader::load_from_file(test_file, &options))
+        .err();
+    assert_eq!(err.map(|err| err.code()), Some("E005"));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serialized_vcd_loads_back() {
+    let test_file = get_test_file_path("vardump.golden.vcd");
+    let vcd = vcd_rust::load_from_file(test_file).unwrap();
+    let json = serde_json::to_string(&vcd).unwrap();
+    let bytes = bincode::serialize(&vcd).unwrap();
+    let from_json: vcd_rust::vcd::VCD = serde_json::from_str(&json).unwrap();
+    let from_bincode: vcd_rust::vcd::VCD = bincode::deserialize(&bytes).unwrap();
+    for loaded in [from_json, from_bincode] {
+        assert_eq!(loaded.date, vcd.date);
+        assert_eq!(loaded.timescale, vcd.timescale);
+        assert_eq!(loaded.comments, vcd.comments);
+        assert_eq!(loaded.variables, vcd.variables);
+        assert_eq!(loaded.waveforms, vcd.waveforms);
+    }
+}
+
+#[cfg(feature = "fst")]
+#[test]
+fn fst_file_loads_like_its_vcd() {
+    let fst = vcd_rust::fst::load_from_file(get_test_file_path("counter.fst")).unwrap();
+    let vcd = vcd_rust::load_from_file(get_test_file_path("counter.vcd")).unwrap();
+
+    assert_eq!(fst.date, "Sun Oct 18 2026");
+    assert_eq!(fst.version, "counter generator");
+    assert_eq!(fst.timescale, vcd.timescale);
+    assert_eq!(fst.variables, vcd.variables);
+    assert_eq!(fst.aliases, vcd.aliases);
+    assert_eq!(fst.waveforms, vcd.waveforms);
+}
+
+#[cfg(feature = "fst")]
+#[test]
+fn truncated_fst_file_throws_error() {
+    let bytes = std::fs::read(get_test_file_path("counter.fst")).unwrap();
+    let err = vcd_rust::fst::load_from_bytes(&bytes[..bytes.len() - 20]).err();
+    assert_eq!(err.map(|err| err.code()), Some("E016"));
+}
+
+#[cfg(feature = "fst")]
+#[test]
+fn corrupted_fst_file_never_panics() {
+    let bytes = std::fs::read(get_test_file_path("counter.fst")).unwrap();
+    for offset in 0..bytes.len() {
+        for byte in &[0x00, 0x7f, 0x80, 0xff] {
+            let mut corrupted = bytes.clone();
+            corrupted[offset] = *byte;
+            if let Err(err) = vcd_rust::fst::load_from_bytes(&corrupted) {
+                assert_eq!(err.code(), "E016");
+            }
+        }
+    }
+}
+
+#[cfg(feature = "fst")]
+#[test]
+fn vcd_written_as_fst_loads_back() {
+    use vcd_rust::fst::{Compression, WriteOptions};
+    let vcd = vcd_rust::load_from_file(get_test_file_path("counter.vcd")).unwrap();
+    for compression in &[Compression::None, Compression::Zlib, Compression::Lz4] {
+        for block_size in &[1, 3, 4096] {
+            let options = WriteOptions {
+                block_size: *block_size,
+                compression: *compression,
+            };
+            let fst = vcd_rust::fst::write_to_bytes(&vcd, &options).unwrap();
+            let fst = vcd_rust::fst::load_from_bytes(&fst).unwrap();
+            assert_eq!(fst.date, vcd.date);
+            assert_eq!(fst.timescale, vcd.timescale);
+            assert_eq!(fst.variables, vcd.variables);
+            assert_eq!(fst.aliases, vcd.aliases);
+            assert_eq!(fst.waveforms, vcd.waveforms);
+        }
+    }
+}
+
+#[cfg(feature = "fst")]
+#[test]
+fn fst_file_keeps_port_directions() {
+    use vcd_rust::fst::WriteOptions;
+    use vcd_rust::types::variable::VarDirection;
+    let mut vcd = vcd_rust::load_from_file(get_test_file_path("counter.vcd")).unwrap();
+    let directions = [
+        VarDirection::Input,
+        VarDirection::Output,
+        VarDirection::Linkage,
+    ];
+    for (variable, direction) in vcd.variables.values_mut().zip(directions.iter()) {
+        variable.direction = Some(*direction);
+    }
+    let fst = vcd_rust::fst::write_to_bytes(&vcd, &WriteOptions::default()).unwrap();
+    let fst = vcd_rust::fst::load_from_bytes(&fst).unwrap();
+    assert!(vcd
+        .variables
+        .values()
+        .any(|variable| variable.direction.is_some()));
+    assert_eq!(fst.variables, vcd.variables);
+}
+
+#[cfg(feature = "ghw")]
+#[test]
+fn ghw_file_keeps_vhdl_types() {
+    use vcd_rust::types::scope::ScopeType;
+    use vcd_rust::types::timescale::{TimeScale, TimeUnit};
+    use vcd_rust::types::value::{Bit, Value};
+    use vcd_rust::types::variable::VarType;
+    let vcd = vcd_rust::ghw::load_from_file(get_test_file_path("design.ghw")).unwrap();
+    let variable = |reference: &str| {
+        vcd.variables
+            .values()
+            .find(|variable| variable.reference == reference)
+            .unwrap()
+    };
+    let values = |reference: &str| {
+        let changes = vcd.waveforms[&variable(reference).ascii_identifier].changes();
+        changes.to_vec()
+    };
+    let bits = |text: &str| Value::Vector(text.chars().filter_map(Bit::from_char).collect());
+
+    assert_eq!(vcd.timescale, TimeScale::new(1, TimeUnit::FS));
+    assert_eq!(vcd.variables.len(), 13);
+    assert_eq!(
+        values("data"),
+        vec![
+            (0, bits("xxxx")),
+            (1000, bits("1010")),
+            (2000, bits("1011"))
+        ]
+    );
+    assert_eq!(
+        variable("data").attributes[0].vhdl_type(),
+        Some("std_logic_vector")
+    );
+
+    let state = variable("state");
+    assert_eq!(
+        (state.var_type.clone(), state.bit_width),
+        (VarType::Enum, 2)
+    );
+    let table = vcd.enum_table(state).unwrap();
+    let names: Vec<_> = values("state")
+        .iter()
+        .map(|(_, value)| table.name_of(value).unwrap())
+        .collect();
+    assert_eq!(names, vec!["idle", "run", "done"]);
+
+    assert_eq!(values("count")[2], (3000, bits(&format!("{:032b}", -3i32))));
+    assert_eq!(
+        values("level"),
+        vec![
+            (0, Value::Real(0.0)),
+            (1000, Value::Real(1.5)),
+            (3000, Value::Real(-2.25))
+        ]
+    );
+
+    let valid = variable("valid");
+    assert_eq!(valid.scope[1].scope_type, ScopeType::VhdlRecord);
+    assert_eq!(valid.scope[1].identifier, "bus");
+    assert_eq!(values("valid")[2], (3000, Value::Scalar(Bit::X)));
+    assert_eq!(values("addr"), vec![(0, bits("00")), (2000, bits("10"))]);
+    assert_eq!(values("mem[1]"), vec![(0, bits("00")), (2000, bits("11"))]);
+    assert_eq!(
+        values("low"),
+        vec![(0, bits("xx")), (1000, bits("10")), (2000, bits("11"))]
+    );
+    assert_eq!(variable("x").scope[1].identifier, "g(0)");
+
+    assert_eq!(vcd.aliases.len(), 1);
+    assert_eq!(vcd.aliases[0].reference, "clk_in");
+    assert_eq!(
+        vcd.aliases[0].ascii_identifier,
+        variable("clk").ascii_identifier
+    );
+    assert_eq!(
+        vcd.aliases[0].scope[1].scope_type,
+        ScopeType::VhdlArchitecture
+    );
+}
+
+#[cfg(feature = "ghw")]
+#[test]
+fn truncated_ghw_file_throws_error() {
+    let bytes = std::fs::read(get_test_file_path("design.ghw")).unwrap();
+    let err = vcd_rust::ghw::load_from_bytes(&bytes[..300]).err();
+    assert_eq!(err.map(|err| err.code()), Some("E016"));
+}
+
+#[cfg(feature = "ghw")]
+#[test]
+fn corrupted_ghw_file_never_panics() {
+    let bytes = std::fs::read(get_test_file_path("design.ghw")).unwrap();
+    for offset in 0..bytes.len() {
+        for byte in &[0x00, 0x7f, 0x80, 0xff] {
+            let mut corrupted = bytes.clone();
+            corrupted[offset] = *byte;
+            if let Err(err) = vcd_rust::ghw::load_from_bytes(&corrupted) {
+                assert_eq!(err.code(), "E016");
+            }
+        }
+    }
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn compressed_vcd_files_load_like_the_plain_file() {
+    use std::io::Write;
+    let plain = std::fs::read(get_test_file_path("vardump.golden.vcd")).unwrap();
+    let vcd = vcd_rust::load_from_file(get_test_file_path("vardump.golden.vcd")).unwrap();
+
+    let mut compressed = vec![];
+    let mut gzip = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+    gzip.write_all(&plain).unwrap();
+    compressed.push(("gz", gzip.finish().unwrap()));
+    #[cfg(feature = "zstd")]
+    compressed.push(("zst", zstd::stream::encode_all(&plain[..], 3).unwrap()));
+    #[cfg(feature = "bzip2")]
+    {
+        let mut bzip2 = bzip2::write::BzEncoder::new(vec![], bzip2::Compression::default());
+        bzip2.write_all(&plain).unwrap();
+        compressed.push(("bz2", bzip2.finish().unwrap()));
+    }
+    #[cfg(feature = "xz")]
+    {
+        let mut xz = xz2::write::XzEncoder::new(vec![], 6);
+        xz.write_all(&plain).unwrap();
+        compressed.push(("xz", xz.finish().unwrap()));
+    }
+
+    let dir = std::env::temp_dir();
+    for (extension, data) in compressed {
+        // The name gives no hint, so the format has to come from the magic bytes.
+        let name = format!(
+            "vcd_rust_compressed_{}_{}.vcd",
+            std::process::id(),
+            extension
+        );
+        let path = dir.join(name);
+        std::fs::write(&path, data).unwrap();
+        let loaded = vcd_rust::load_from_file(path.display().to_string());
+        std::fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.date, vcd.date, "{}", extension);
+        assert_eq!(loaded.variables, vcd.variables, "{}", extension);
+        assert_eq!(loaded.waveforms, vcd.waveforms, "{}", extension);
+    }
+}
+
+#[test]
+fn seek_index_loads_time_windows() {
+    use vcd_rust::index::{sidecar_path, SeekIndex};
+    use vcd_rust::options::ParseOptions;
+    let mut text = "$timescale 1 ns $end\n$scope module top $end\n$var wire 1 ! a $end\n$var wire 4 \" b $end\n\
+                    $var wire 1 # c $end\n$upscope $end\n$enddefinitions $end\n#0\n$dumpvars 0! b0 \" 0# $end\n"
+        .to_string();
+    for time in 1..200u64 {
+        text.push_str(&format!("#{}\n{}!\n", time * 10, time % 2));
+        if time % 7 == 0 {
+            text.push_str(&format!("b{:b} \"\n", time % 16));
+        }
+        if time == 30 {
+            text.push_str("1#\n");
+        }
+    }
+    let path = std::env::temp_dir().join(format!("vcd_rust_seek_index_{}.vcd", std::process::id()));
+    let filename = path.display().to_string();
+    std::fs::write(&path, &text).unwrap();
+    let options = ParseOptions::default();
+    let vcd = vcd_rust::load_from_str(&text).unwrap();
+
+    let index = SeekIndex::build(&filename, &options, 200).unwrap();
+    assert!(index.checkpoints.len() > 5);
+    let window = index.load_window(&filename, &options, 1000, 1500).unwrap();
+    assert_eq!(window.variables, vcd.variables);
+    for (identifier, waveform) in &vcd.waveforms {
+        let windowed = &window.waveforms[identifier];
+        for time in 1000..=1500 {
+            assert_eq!(windowed.value_at(time), waveform.value_at(time), "{}", time);
+        }
+        assert!(windowed.changes().iter().all(|(time, _)| *time <= 1500));
+    }
+    assert!(window.waveforms["!"].len() < vcd.waveforms["!"].len() / 2);
+
+    // The sidecar is written once and then reused, until the file changes.
+    let sidecar = sidecar_path(&filename);
+    let _ = std::fs::remove_file(&sidecar);
+    let opened = SeekIndex::open(&filename, &options).unwrap();
+    assert_eq!(SeekIndex::read(&sidecar), Ok(opened.clone()));
+    text.push_str("#2000\n1!\n");
+    std::fs::write(&path, &text).unwrap();
+    let stale = opened.load_window(&filename, &options, 0, 10).err();
+    assert_eq!(stale.map(|err| err.code()), Some("E001"));
+    let reopened = SeekIndex::open(&filename, &options).unwrap();
+    assert_ne!(reopened.source, opened.source);
+    let window = reopened
+        .load_window(&filename, &options, 1995, 2000)
+        .unwrap();
+    assert_eq!(
+        window.waveforms["!"].last_value(),
+        vcd.waveforms["!"].value_at(10)
+    );
+
+    // A sidecar that isn't an index isn't overwritten.
+    std::fs::write(&sidecar, "not an index").unwrap();
+    assert_eq!(SeekIndex::open(&filename, &options), Ok(reopened));
+    assert_eq!(std::fs::read(&sidecar).unwrap(), b"not an index");
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(&sidecar).unwrap();
+}
+
+#[test]
+fn lazy_load_of_file() {
+    use vcd_rust::lazy::LazyVcd;
+    use vcd_rust::options::ParseOptions;
+    let mut text = "$timescale 1 ns $end\n$scope module top $end\n".to_string();
+    for signal in 0..20 {
+        text.push_str(&format!("$var wire 8 s{} sig{} $end\n", signal, signal));
+    }
+    text.push_str("$var real 1 r real $end\n$var wire 1 n never $end\n$upscope $end\n");
+    text.push_str("$enddefinitions $end\n");
+    for time in 0..20000u64 {
+        text.push_str(&format!("#{}\n", time));
+        for signal in (0..20).filter(|signal| time % (signal + 1) == 0) {
+            text.push_str(&format!("b{:b} s{}\n", (time / (signal + 1)) % 256, signal));
+        }
+        if time == 15000 {
+            text.push_str("$comment r1.5 s0 $end\nr2.5 r\n");
+        }
+    }
+    let path = std::env::temp_dir().join(format!("vcd_rust_lazy_{}.vcd", std::process::id()));
+    let filename = path.display().to_string();
+    std::fs::write(&path, &text).unwrap();
+    let vcd = vcd_rust::load_from_str(&text).unwrap();
+
+    let mut lazy = LazyVcd::open(&filename, &ParseOptions::default()).unwrap();
+    assert_eq!(lazy.vcd().variables, vcd.variables);
+    assert!(lazy.vcd().waveforms.is_empty());
+    for identifier in &["s0", "s7", "s19", "r"] {
+        assert!(!lazy.is_loaded(identifier));
+        let waveform = lazy.waveform(identifier).unwrap();
+        assert_eq!(waveform, vcd.waveforms.get(*identifier), "{}", identifier);
+        assert!(lazy.is_loaded(identifier));
+    }
+    assert_eq!(lazy.waveform("n").unwrap(), None);
+    lazy.unload("s0");
+    assert!(!lazy.is_loaded("s0"));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn spilling_store_answers_like_the_loaded_vcd() {
+    use vcd_rust::options::ParseOptions;
+    use vcd_rust::spill::{SpillOptions, SpillingStore};
+    use vcd_rust::types::waveform::WaveformStore;
+    let mut text = "$scope module top $end\n".to_string();
+    for signal in 0..10 {
+        text.push_str(&format!("$var wire 4 s{} sig{} $end\n", signal, signal));
+    }
+    text.push_str("$var real 1 r real $end\n$upscope $end\n$enddefinitions $end\n");
+    for time in 0..3000u64 {
+        text.push_str(&format!("#{}\nr{} r\n", time, time as f64 / 4.0));
+        for signal in (0..10).filter(|signal| time % (signal + 1) == 0) {
+            text.push_str(&format!("b{:b} s{}\n", (time + signal) % 16, signal));
+        }
+    }
+    text.push_str("#3000\nb0 s9\n");
+    let path = std::env::temp_dir().join(format!("vcd_rust_spilled_{}.vcd", std::process::id()));
+    std::fs::write(&path, &text).unwrap();
+    let dir = std::env::temp_dir().join(format!("vcd_rust_spill_test_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    // Removes the directory even if the test fails.
+    struct RemoveDir(std::path::PathBuf);
+    impl Drop for RemoveDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+    let _remove_dir = RemoveDir(dir.clone());
+
+    let options = ParseOptions::default();
+    let report = vcd_rust::load_from_str_with_options(&text, &options).unwrap();
+    let spill = SpillOptions {
+        dir: dir.clone(),
+        spill_interval: 4096,
+        cache_budget: 2048,
+    };
+    let store =
+        SpillingStore::load_from_file(path.display().to_string(), &options, &spill).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(store.header.variables, report.vcd.variables);
+    assert_eq!(store.warnings, report.warnings);
+    let mut identifiers = store.identifiers();
+    identifiers.sort();
+    let mut expected = report.vcd.identifiers();
+    expected.sort();
+    assert_eq!(identifiers, expected);
+    for identifier in &identifiers {
+        let changes = store.query(identifier, |waveform| waveform.iter().collect::<Vec<_>>());
+        assert_eq!(
+            changes.unwrap().unwrap(),
+            report.vcd.waveforms[identifier].changes().to_vec()
+        );
+        let value = store.query(identifier, |waveform| waveform.value_at(1234));
+        let expected = report
+            .vcd
+            .query(identifier, |waveform| waveform.value_at(1234));
+        assert_eq!(value.unwrap(), expected.unwrap());
+    }
+    assert!(store
+        .query("nope", |waveform| waveform.len())
+        .unwrap()
+        .is_none());
+    assert!(std::fs::read_dir(&dir).unwrap().count() == 1);
+    drop(store);
+    assert!(std::fs::read_dir(&dir).unwrap().count() == 0);
+}